@@ -0,0 +1,439 @@
+use std::{io::ErrorKind, net::IpAddr};
+
+use anyhow::{Context, bail};
+use fs_err::tokio as fs;
+use jiff::Zoned;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    control,
+    profile::{Profile, Resolver},
+    workspace,
+};
+
+/// How the server records who created a ban from its console.
+const BAN_SOURCE: &str = "Server";
+/// The reason the server records when a ban is given without one.
+const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+const FOREVER: &str = "forever";
+/// Permission level the server gives ops unless `op-permission-level` is set.
+pub const DEFAULT_OP_LEVEL: u8 = 4;
+
+/// An entry in one of the server's JSON player lists.
+pub trait Entry: Serialize + DeserializeOwned {
+    /// Location of the list, relative to the workspace.
+    const PATH: &str;
+
+    /// Whether the entry is for `target`, a player name or IP address.
+    fn is(&self, target: &str) -> bool;
+}
+
+/// An operator in `ops.json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Op {
+    pub uuid: String,
+    pub name: String,
+    /// Permission level from 1 to 4.
+    pub level: u8,
+    pub bypasses_player_limit: bool,
+}
+
+impl Entry for Op {
+    const PATH: &str = "ops.json";
+
+    fn is(&self, target: &str) -> bool {
+        self.name.eq_ignore_ascii_case(target)
+    }
+}
+
+/// A player allowed to join in `whitelist.json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Allowed {
+    pub uuid: String,
+    pub name: String,
+}
+
+impl Entry for Allowed {
+    const PATH: &str = "whitelist.json";
+
+    fn is(&self, target: &str) -> bool {
+        self.name.eq_ignore_ascii_case(target)
+    }
+}
+
+/// A banned player in `banned-players.json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PlayerBan {
+    pub uuid: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub ban: Ban,
+}
+
+impl Entry for PlayerBan {
+    const PATH: &str = "banned-players.json";
+
+    fn is(&self, target: &str) -> bool {
+        self.name.eq_ignore_ascii_case(target)
+    }
+}
+
+/// A banned IP address in `banned-ips.json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct IpBan {
+    pub ip: String,
+    #[serde(flatten)]
+    pub ban: Ban,
+}
+
+impl Entry for IpBan {
+    const PATH: &str = "banned-ips.json";
+
+    fn is(&self, target: &str) -> bool {
+        self.ip == target
+    }
+}
+
+/// The details shared by player and IP bans.
+///
+/// Times are kept in the server's own `yyyy-MM-dd HH:mm:ss Z` format.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Ban {
+    pub created: String,
+    pub source: String,
+    /// When the ban ends, or `forever`.
+    pub expires: String,
+    pub reason: String,
+}
+
+impl Ban {
+    /// A permanent ban starting now, as the server console would create it.
+    pub fn new(reason: Option<&str>) -> Self {
+        Ban {
+            created: Zoned::now().strftime("%Y-%m-%d %H:%M:%S %z").to_string(),
+            source: BAN_SOURCE.to_string(),
+            expires: FOREVER.to_string(),
+            reason: reason.unwrap_or(DEFAULT_BAN_REASON).to_string(),
+        }
+    }
+}
+
+/// Read one of the server's player lists, which is empty until it's created.
+pub async fn load<T: Entry>() -> anyhow::Result<Vec<T>> {
+    let content = match fs::read_to_string(T::PATH).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    // The server writes an empty file before it has anything to list.
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&content).with_context(|| format!("Invalid {}", T::PATH))
+}
+
+/// Write one of the server's player lists.
+pub async fn save<T: Entry>(entries: &[T]) -> anyhow::Result<()> {
+    fs::write(T::PATH, serde_json::to_string_pretty(entries)?).await?;
+    Ok(())
+}
+
+/// Add `entry`, replacing any existing entry for the same target.
+pub(crate) fn insert<T: Entry>(entries: &mut Vec<T>, target: &str, entry: T) {
+    entries.retain(|existing| !existing.is(target));
+    entries.push(entry);
+}
+
+/// Remove the entry for `target`, returning whether there was one.
+pub(crate) fn remove<T: Entry>(entries: &mut Vec<T>, target: &str) -> bool {
+    let before = entries.len();
+    entries.retain(|existing| !existing.is(target));
+    entries.len() != before
+}
+
+/// Refuse anything but a plausible player name, so that it can be sent as a
+/// console command argument.
+pub(crate) fn check_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 16
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_');
+    if !valid {
+        bail!("Invalid player name: {name}");
+    }
+    Ok(())
+}
+
+/// Refuse a ban reason that would end the console command early.
+pub(crate) fn check_reason(reason: &str) -> anyhow::Result<()> {
+    if reason.chars().any(char::is_control) {
+        bail!("Ban reasons can't contain control characters");
+    }
+    Ok(())
+}
+
+/// Whether a ban target is an IP address rather than a player name.
+pub(crate) fn is_ip(target: &str) -> bool {
+    target.parse::<IpAddr>().is_ok()
+}
+
+/// The profile of a player to add to one of the server's lists.
+async fn resolve(profile_api_url: &str, name: &str) -> anyhow::Result<Profile> {
+    let resolver = Resolver::new(profile_api_url, workspace::online_mode().await?)?;
+    resolver.resolve(name).await
+}
+
+/// Remove `target` from one of the server's lists, returning whether it was
+/// listed.
+async fn remove_saved<T: Entry>(target: &str) -> anyhow::Result<bool> {
+    let mut entries = load::<T>().await?;
+    let removed = remove(&mut entries, target);
+    if removed {
+        save(&entries).await?;
+    }
+    Ok(removed)
+}
+
+/// Make a player an operator, through the console if the server is running.
+///
+/// The level and player limit bypass can only be set in `ops.json`, so they
+/// require the server to be stopped. Returns the entry written to
+/// `ops.json`, or `None` if the server was told instead.
+pub async fn op(
+    profile_api_url: &str,
+    name: &str,
+    level: Option<u8>,
+    bypasses_player_limit: bool,
+) -> anyhow::Result<Option<Op>> {
+    check_name(name)?;
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        if level.is_some() || bypasses_player_limit {
+            bail!("--level and --bypass-player-limit can only be set while the server is stopped");
+        }
+        control::command(&format!("op {name}")).await?;
+        return Ok(None);
+    };
+
+    let profile = resolve(profile_api_url, name).await?;
+    let level = match level {
+        Some(level) => level,
+        None => workspace::properties()
+            .await?
+            .get("op-permission-level")
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(DEFAULT_OP_LEVEL),
+    };
+    let op = Op {
+        uuid: profile.uuid,
+        name: profile.name,
+        level,
+        bypasses_player_limit,
+    };
+    let mut ops = load().await?;
+    insert(&mut ops, name, op.clone());
+    save(&ops).await?;
+    Ok(Some(op))
+}
+
+/// Remove an operator, through the console if the server is running.
+pub async fn deop(name: &str) -> anyhow::Result<()> {
+    check_name(name)?;
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        return control::command(&format!("deop {name}")).await;
+    };
+    if !remove_saved::<Op>(name).await? {
+        bail!("{name} isn't an operator");
+    }
+    Ok(())
+}
+
+/// Add players to the whitelist, through the console if the server is
+/// running.
+///
+/// Otherwise every player is resolved before `whitelist.json` is written,
+/// so an unknown name leaves it as it was.
+pub async fn allow(profile_api_url: &str, names: &[String]) -> anyhow::Result<()> {
+    for name in names {
+        check_name(name)?;
+    }
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        for name in names {
+            control::command(&format!("whitelist add {name}")).await?;
+        }
+        return Ok(());
+    };
+
+    let mut allowed = load::<Allowed>().await?;
+    for name in names {
+        let profile = resolve(profile_api_url, name).await?;
+        insert(
+            &mut allowed,
+            name,
+            Allowed {
+                uuid: profile.uuid,
+                name: profile.name,
+            },
+        );
+    }
+    save(&allowed).await
+}
+
+/// Remove players from the whitelist, through the console if the server is
+/// running.
+pub async fn disallow(names: &[String]) -> anyhow::Result<()> {
+    for name in names {
+        check_name(name)?;
+    }
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        for name in names {
+            control::command(&format!("whitelist remove {name}")).await?;
+        }
+        return Ok(());
+    };
+
+    let mut allowed = load::<Allowed>().await?;
+    for name in names {
+        if !remove(&mut allowed, name) {
+            bail!("{name} isn't on the whitelist");
+        }
+    }
+    save(&allowed).await
+}
+
+/// Ban a player or IP address, through the console if the server is
+/// running.
+pub async fn ban(profile_api_url: &str, target: &str, reason: Option<&str>) -> anyhow::Result<()> {
+    if let Some(reason) = reason {
+        check_reason(reason)?;
+    }
+    let is_ip = is_ip(target);
+    if !is_ip {
+        check_name(target)?;
+    }
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        let command = if is_ip { "ban-ip" } else { "ban" };
+        let reason = reason
+            .map(|reason| format!(" {reason}"))
+            .unwrap_or_default();
+        return control::command(&format!("{command} {target}{reason}")).await;
+    };
+
+    if is_ip {
+        let mut bans = load().await?;
+        insert(
+            &mut bans,
+            target,
+            IpBan {
+                ip: target.to_string(),
+                ban: Ban::new(reason),
+            },
+        );
+        save(&bans).await
+    } else {
+        let profile = resolve(profile_api_url, target).await?;
+        let mut bans = load().await?;
+        insert(
+            &mut bans,
+            target,
+            PlayerBan {
+                uuid: profile.uuid,
+                name: profile.name,
+                ban: Ban::new(reason),
+            },
+        );
+        save(&bans).await
+    }
+}
+
+/// Lift the ban on a player or IP address, through the console if the
+/// server is running.
+pub async fn pardon(target: &str) -> anyhow::Result<()> {
+    let is_ip = is_ip(target);
+    if !is_ip {
+        check_name(target)?;
+    }
+    let Some(_lock) = workspace::lock_unless_running()? else {
+        let command = if is_ip { "pardon-ip" } else { "pardon" };
+        return control::command(&format!("{command} {target}")).await;
+    };
+
+    let removed = if is_ip {
+        remove_saved::<IpBan>(target).await?
+    } else {
+        remove_saved::<PlayerBan>(target).await?
+    };
+    if !removed {
+        bail!("{target} isn't banned");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test]
+    fn test_ops_round_trip() {
+        let content = r#"[
+  {
+    "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+    "name": "Notch",
+    "level": 4,
+    "bypassesPlayerLimit": false
+  }
+]"#;
+        let ops: Vec<Op> = serde_json::from_str(content).unwrap();
+        assert_eq!(ops[0].level, 4);
+        assert_eq!(serde_json::to_string_pretty(&ops).unwrap(), content);
+    }
+
+    #[test]
+    fn test_bans_round_trip() {
+        let content = r#"[
+  {
+    "ip": "192.0.2.1",
+    "created": "2024-11-29 12:00:00 +0000",
+    "source": "Server",
+    "expires": "forever",
+    "reason": "Banned by an operator."
+  }
+]"#;
+        let bans: Vec<IpBan> = serde_json::from_str(content).unwrap();
+        assert_eq!(bans[0].ban.expires, "forever");
+        assert_eq!(serde_json::to_string_pretty(&bans).unwrap(), content);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let allowed = |name: &str, uuid: &str| Allowed {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+        };
+        let mut entries = vec![allowed("Notch", "a"), allowed("jeb_", "b")];
+        insert(&mut entries, "notch", allowed("Notch", "c"));
+        assert_eq!(entries, [allowed("jeb_", "b"), allowed("Notch", "c")]);
+        assert!(remove(&mut entries, "JEB_"));
+        assert!(!remove(&mut entries, "jeb_"));
+        assert_eq!(entries, [allowed("Notch", "c")]);
+    }
+
+    #[test_case("heavymetalpanda", true)]
+    #[test_case("jeb_", true)]
+    #[test_case("", false)]
+    #[test_case("op everyone", false)]
+    #[test_case("seventeen_letters", false)]
+    fn test_check_name(name: &str, valid: bool) {
+        assert_eq!(check_name(name).is_ok(), valid);
+    }
+
+    #[test_case("192.0.2.1", true)]
+    #[test_case("2001:db8::1", true)]
+    #[test_case("Notch", false)]
+    fn test_is_ip(target: &str, expected: bool) {
+        assert_eq!(is_ip(target), expected);
+    }
+}
@@ -0,0 +1,659 @@
+mod restore;
+mod store;
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    io::{self, ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use jiff::{Timestamp, civil::DateTime, tz::TimeZone};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{
+    control::{self, Request, Response},
+    metrics,
+    server::Console,
+    workspace,
+};
+
+pub use restore::{Restored, find, restore};
+
+/// Backups are named after the UTC time they were taken, to the millisecond
+/// so that backups taken in quick succession don't collide.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+/// Parses names with or without milliseconds, which older backups lack.
+const PARSE_FORMAT: &str = "%Y%m%dT%H%M%S%.fZ";
+const EXTENSION: &str = ".tar.zst";
+const COMPRESSION_LEVEL: i32 = 3;
+/// The first bytes of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// Checksums of every file in an archive, in `sha256sum` format. Written last.
+const MANIFEST_PATH: &str = "mc-backup.sha256";
+
+/// Where backups are written unless configured otherwise.
+pub const DEFAULT_DIRECTORY: &str = "backups";
+/// Daily backups kept unless configured otherwise.
+pub const DEFAULT_KEEP_DAILY: usize = 7;
+/// Weekly backups kept unless configured otherwise.
+pub const DEFAULT_KEEP_WEEKLY: usize = 4;
+
+/// Output the server prints once `save-all flush` completes.
+const SAVED_PATTERN: &str = "Saved the game";
+/// How long to wait for the server to flush the world to disk.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How backups are stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Format {
+    /// A self-contained `tar.zst` archive per backup.
+    #[default]
+    Archive,
+    /// A content-addressed store shared by all backups, where each backup is a
+    /// small index and unchanged files and region sectors are stored once.
+    Store,
+}
+
+impl Format {
+    /// Every format, in the order they're listed in `--help`.
+    pub const ALL: &[Format] = &[Format::Archive, Format::Store];
+
+    /// The name used in `mc.toml` and on the command line, e.g. `store`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Archive => "archive",
+            Format::Store => "store",
+        }
+    }
+
+    /// The extension of each backup's file in the backup directory.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Archive => EXTENSION,
+            Format::Store => store::EXTENSION,
+        }
+    }
+
+    /// Tell an archive from a snapshot index by its first bytes, whatever the
+    /// file is named.
+    fn detect(path: &Utf8Path) -> anyhow::Result<Self> {
+        let mut start = [0; 4];
+        let read = fs_err::File::open(path)?.read(&mut start)?;
+        match &start[..read] {
+            ZSTD_MAGIC => Ok(Format::Archive),
+            [b'{', ..] => Ok(Format::Store),
+            _ => bail!("{path} is not a backup archive or snapshot"),
+        }
+    }
+}
+
+/// Where and how often to back up the world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Directory backups are written to, relative to the workspace.
+    pub directory: Utf8PathBuf,
+    /// Whether to write archives or use the deduplicating backup store.
+    pub format: Format,
+    /// Number of most recent days to keep a backup for.
+    pub keep_daily: usize,
+    /// Number of most recent weeks to keep a backup for.
+    pub keep_weekly: usize,
+    /// How often to back up while the server runs, if at all.
+    pub interval: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            directory: DEFAULT_DIRECTORY.into(),
+            format: Format::default(),
+            keep_daily: DEFAULT_KEEP_DAILY,
+            keep_weekly: DEFAULT_KEEP_WEEKLY,
+            interval: None,
+        }
+    }
+}
+
+/// A backup archive or store snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Backup {
+    pub path: Utf8PathBuf,
+    pub timestamp: Timestamp,
+    /// Label given to [`create_labeled`], e.g. `pre-1.21.4`.
+    pub label: Option<String>,
+    /// Whether this is an archive or a snapshot in the backup store.
+    pub format: Format,
+    /// Size of the archive, or the snapshot's index, in bytes.
+    pub size: u64,
+}
+
+/// Back up the world directories to a timestamped `tar.zst` archive, or a
+/// snapshot in the backup store.
+///
+/// The world must not be written to while this runs, so either the server is
+/// stopped or saving is disabled, as [`take`] does for a running server.
+pub async fn create(config: &Config) -> anyhow::Result<Backup> {
+    write(config, None).await
+}
+
+/// Back up the world in the current workspace and prune old backups.
+///
+/// A running server has to pause saving, so it is asked to take the backup.
+/// Otherwise the workspace is locked while it's taken here.
+///
+/// Returns the path of the backup.
+pub async fn take(config: &Config) -> anyhow::Result<Utf8PathBuf> {
+    if let Some(response) = control::request(&Request::Backup).await? {
+        let Response::Backup { path } = response else {
+            bail!("Unexpected response from server: {response:?}");
+        };
+        return Ok(path);
+    }
+
+    let _lock = workspace::lock()?;
+    let backup = create(config).await?;
+    prune(config).await?;
+    Ok(backup.path)
+}
+
+/// Back up the world like [`create`], adding `label` to the backup's name.
+///
+/// Labeled backups are exempt from the retention policy and kept until
+/// deleted by hand.
+pub async fn create_labeled(config: &Config, label: &str) -> anyhow::Result<Backup> {
+    write(config, Some(label)).await
+}
+
+async fn write(config: &Config, label: Option<&str>) -> anyhow::Result<Backup> {
+    let worlds = workspace::world_directories().await?;
+    if worlds.is_empty() {
+        bail!("No world to back up");
+    }
+
+    fs::create_dir_all(&config.directory).await?;
+
+    let timestamp = Timestamp::now();
+    let label = label.map(str::to_string);
+    let suffix = label
+        .as_ref()
+        .map(|label| format!("-{label}"))
+        .unwrap_or_default();
+    let name = format!(
+        "{}{suffix}{}",
+        timestamp.strftime(TIMESTAMP_FORMAT),
+        config.format.extension()
+    );
+    let path = config.directory.join(&name);
+
+    let names: Vec<_> = worlds.iter().map(|world| world.as_str()).collect();
+    tracing::info!("Backing up {} to {path}", names.join(", "));
+    let started = Instant::now();
+    if config.format == Format::Store {
+        let index_path = path.clone();
+        let size =
+            tokio::task::spawn_blocking(move || store::write(&index_path, ".".into(), &worlds))
+                .await??;
+        metrics::record_backup(started.elapsed(), size);
+        return Ok(Backup {
+            path,
+            timestamp,
+            label,
+            format: Format::Store,
+            size,
+        });
+    }
+
+    let temp_path = config.directory.join(format!(".{name}.partial"));
+    let archive_path = temp_path.clone();
+    let result =
+        tokio::task::spawn_blocking(move || write_archive(&archive_path, ".".into(), &worlds))
+            .await?;
+    let size = match result {
+        Ok(size) => size,
+        Err(err) => {
+            if let Err(err) = fs::remove_file(&temp_path).await {
+                tracing::warn!("Failed to remove partial backup: {err}");
+            }
+            return Err(err);
+        }
+    };
+    let renamed = keep_new(&temp_path, &path).await;
+    if let Err(err) = fs::remove_file(&temp_path).await {
+        tracing::warn!("Failed to remove partial backup: {err}");
+    }
+    renamed?;
+    metrics::record_backup(started.elapsed(), size);
+
+    Ok(Backup {
+        path,
+        timestamp,
+        label,
+        format: Format::Archive,
+        size,
+    })
+}
+
+/// Move a finished backup into place, refusing to overwrite an existing one.
+async fn keep_new(temp_path: &Utf8Path, path: &Utf8Path) -> anyhow::Result<()> {
+    match fs::hard_link(temp_path, path).await {
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => bail!("Backup {path} already exists"),
+        result => Ok(result?),
+    }
+}
+
+/// Back up the world while the server is running.
+///
+/// Disables automatic saving and flushes the world to disk before archiving.
+/// Saving is always re-enabled afterwards, even if archiving fails.
+pub(crate) async fn create_online(console: &Console, config: &Config) -> anyhow::Result<Backup> {
+    let _saving = console.lock_saving().await;
+    console.send("save-off").await?;
+
+    let result = async {
+        console
+            .send_and_wait("save-all flush", SAVED_PATTERN, SAVE_TIMEOUT)
+            .await?;
+        create(config).await
+    }
+    .await;
+
+    if let Err(err) = console.send("save-on").await {
+        tracing::error!("Failed to re-enable saving: {err}");
+    }
+
+    result
+}
+
+/// Write the given directories, relative to `base`, to a zstd compressed tarball.
+///
+/// Returns the size of the archive in bytes.
+fn write_archive(
+    path: &Utf8Path,
+    base: &Utf8Path,
+    directories: &[Utf8PathBuf],
+) -> anyhow::Result<u64> {
+    let file = fs_err::File::create(path)?;
+    let encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    let mut manifest = String::new();
+    for directory in directories {
+        append_dir(&mut builder, &mut manifest, base, directory)
+            .with_context(|| format!("Failed to archive {directory}"))?;
+    }
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Timestamp::now().as_second().try_into()?);
+    builder.append_data(&mut header, MANIFEST_PATH, manifest.as_bytes())?;
+
+    let file = builder.into_inner()?.finish()?;
+    file.sync_all()?;
+    Ok(file.metadata()?.len())
+}
+
+/// Recursively append a directory, recording the checksum of each file.
+///
+/// Symlinks and other special files are skipped, since worlds don't use them.
+fn append_dir<W: Write>(
+    builder: &mut tar::Builder<W>,
+    manifest: &mut String,
+    base: &Utf8Path,
+    directory: &Utf8Path,
+) -> anyhow::Result<()> {
+    builder.append_dir(directory, base.join(directory))?;
+
+    let mut entries = fs_err::read_dir(base.join(directory))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow::anyhow!("Non UTF-8 file name: {name:?}"))?;
+        let path = directory.join(name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            append_dir(builder, manifest, base, &path)?;
+        } else if file_type.is_file() {
+            let file = fs_err::File::open(base.join(&path))?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&file.metadata()?);
+            let mut reader = Hashing::new(file);
+            builder.append_data(&mut header, &path, &mut reader)?;
+            manifest.push_str(&format!("{}  {path}\n", reader.finish()));
+        } else {
+            tracing::debug!("Skipping special file {path}");
+        }
+    }
+    Ok(())
+}
+
+/// Wraps a reader or writer, hashing everything that passes through it.
+struct Hashing<T> {
+    inner: T,
+    hasher: Sha256,
+}
+
+impl<T> Hashing<T> {
+    fn new(inner: T) -> Self {
+        Hashing {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// The SHA-256 of everything read or written, as hex.
+    fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// List backups in the backup directory, labeled ones included, newest first.
+///
+/// Backups in either format are listed, whichever is configured now.
+pub async fn list(config: &Config) -> anyhow::Result<Vec<Backup>> {
+    let mut entries = match fs::read_dir(&config.directory).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let Some((format, (timestamp, label))) = Format::ALL
+            .iter()
+            .find_map(|&format| Some((format, parse_name(&name, format.extension())?)))
+        else {
+            continue;
+        };
+        backups.push(Backup {
+            path: config.directory.join(name),
+            timestamp,
+            label,
+            format,
+            size: entry.metadata().await?.len(),
+        });
+    }
+    backups.sort_by_key(|backup| Reverse(backup.timestamp));
+    Ok(backups)
+}
+
+/// Parse the timestamp and any label from a backup's file name.
+fn parse_name(name: &str, extension: &str) -> Option<(Timestamp, Option<String>)> {
+    let stem = name.strip_suffix(extension)?;
+    let end = stem.find('Z')? + 1;
+    let (time, label) = stem.split_at(end);
+    let label = match label {
+        "" => None,
+        label => Some(label.strip_prefix('-')?.to_string()),
+    };
+    Some((parse_timestamp(time)?, label))
+}
+
+/// Parse a timestamp as backups are named, e.g. `20241129T213000Z`.
+fn parse_timestamp(value: &str) -> Option<Timestamp> {
+    let datetime = DateTime::strptime(PARSE_FORMAT, value).ok()?;
+    Some(datetime.to_zoned(TimeZone::UTC).ok()?.timestamp())
+}
+
+/// Delete backups outside the retention policy, along with any data in the
+/// backup store no longer used by a snapshot. Labeled backups are kept.
+///
+/// Returns the paths of the deleted backups.
+pub async fn prune(config: &Config) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let mut backups = list(config).await?;
+    backups.retain(|backup| backup.label.is_none());
+    let timestamps: Vec<_> = backups.iter().map(|backup| backup.timestamp).collect();
+    let retained = retained(
+        &timestamps,
+        config.keep_daily,
+        config.keep_weekly,
+        &TimeZone::system(),
+    );
+
+    let mut pruned = Vec::new();
+    let mut pruned_snapshot = false;
+    for backup in backups {
+        if retained.contains(&backup.timestamp) {
+            continue;
+        }
+        tracing::info!("Pruning backup {}", backup.path);
+        fs::remove_file(&backup.path).await?;
+        pruned_snapshot |= backup.format == Format::Store;
+        pruned.push(backup.path);
+    }
+
+    if pruned_snapshot {
+        let directory = config.directory.clone();
+        let freed =
+            tokio::task::spawn_blocking(move || store::collect_garbage(&directory)).await??;
+        tracing::info!("Freed {} from the backup store", ByteSize(freed));
+    }
+    Ok(pruned)
+}
+
+/// Pick the backups to keep: the newest overall, plus the newest of each of the
+/// most recent `keep_daily` days and `keep_weekly` ISO weeks.
+fn retained(
+    timestamps: &[Timestamp],
+    keep_daily: usize,
+    keep_weekly: usize,
+    tz: &TimeZone,
+) -> BTreeSet<Timestamp> {
+    let mut newest_first = timestamps.to_vec();
+    newest_first.sort_by_key(|timestamp| Reverse(*timestamp));
+
+    let mut retained = BTreeSet::new();
+    retained.extend(newest_first.first());
+
+    let mut days = BTreeSet::new();
+    let mut weeks = BTreeSet::new();
+    for timestamp in newest_first {
+        let date = timestamp.to_zoned(tz.clone()).date();
+        if days.len() < keep_daily && days.insert(date) {
+            retained.insert(timestamp);
+        }
+        let week = date.iso_week_date();
+        if weeks.len() < keep_weekly && weeks.insert((week.year(), week.week())) {
+            retained.insert(timestamp);
+        }
+    }
+    retained
+}
+
+/// Back up the world on an interval while the server runs.
+///
+/// Backups are skipped while the server is stopped for being idle, since
+/// nothing changes the world then. Failures are logged rather than stopping
+/// the schedule.
+pub(crate) async fn schedule(console: Console, config: Config, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        if !console.is_running() {
+            tracing::debug!("Skipping scheduled backup, the server isn't running");
+            continue;
+        }
+        match create_online(&console, &config).await {
+            Ok(backup) => tracing::info!("Backed up world to {}", backup.path),
+            Err(err) => {
+                tracing::error!("Scheduled backup failed: {err:#}");
+                continue;
+            }
+        }
+        if let Err(err) = prune(&config).await {
+            tracing::error!("Failed to prune backups: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// Create an empty directory for a test to work in.
+    pub(super) fn temp_dir() -> Utf8PathBuf {
+        let path = std::env::temp_dir().join(format!("mc-test-{:x}", rand::random::<u64>()));
+        fs_err::create_dir_all(&path).unwrap();
+        path.try_into().unwrap()
+    }
+
+    fn timestamps(values: &[&str]) -> Vec<Timestamp> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test_case("20241129T213000Z.tar.zst", Some(("2024-11-29T21:30:00Z", None)) ; "valid")]
+    #[test_case(
+        "20241129T213000.250Z.tar.zst",
+        Some(("2024-11-29T21:30:00.25Z", None)) ;
+        "milliseconds"
+    )]
+    #[test_case("20241129T213000Z.tar.gz", None ; "wrong extension")]
+    #[test_case("20241129T213000Z.snapshot.json", None ; "snapshot")]
+    #[test_case(".20241129T213000Z.tar.zst.partial", None ; "partial")]
+    #[test_case(
+        "20241129T213000Z-pre-1.21.4.tar.zst",
+        Some(("2024-11-29T21:30:00Z", Some("pre-1.21.4"))) ;
+        "labeled"
+    )]
+    #[test_case("20241129T213000Zpre.tar.zst", None ; "unseparated label")]
+    #[test_case("world.tar.zst", None ; "not a timestamp")]
+    fn test_parse_name(name: &str, expected: Option<(&str, Option<&str>)>) {
+        assert_eq!(
+            parse_name(name, EXTENSION),
+            expected.map(|(time, label)| (time.parse().unwrap(), label.map(str::to_string)))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let timestamp: Timestamp = "2024-11-29T21:30:00.25Z".parse().unwrap();
+        let name = timestamp.strftime(TIMESTAMP_FORMAT).to_string();
+        assert_eq!(name, "20241129T213000.250Z");
+        assert_eq!(parse_timestamp(&name), Some(timestamp));
+    }
+
+    #[test]
+    fn test_retained_daily() {
+        let all = timestamps(&[
+            "2024-11-29T12:00:00Z",
+            "2024-11-29T06:00:00Z",
+            "2024-11-28T12:00:00Z",
+            "2024-11-27T12:00:00Z",
+        ]);
+        let retained = retained(&all, 2, 0, &TimeZone::UTC);
+        assert_eq!(
+            retained,
+            timestamps(&["2024-11-29T12:00:00Z", "2024-11-28T12:00:00Z"])
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_retained_weekly() {
+        // 2024-11-25 is a Monday.
+        let all = timestamps(&[
+            "2024-11-26T12:00:00Z",
+            "2024-11-25T12:00:00Z",
+            "2024-11-24T12:00:00Z",
+            "2024-11-18T12:00:00Z",
+            "2024-11-11T12:00:00Z",
+        ]);
+        let retained = retained(&all, 1, 2, &TimeZone::UTC);
+        assert_eq!(
+            retained,
+            timestamps(&["2024-11-26T12:00:00Z", "2024-11-24T12:00:00Z"])
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_retained_keeps_newest() {
+        let all = timestamps(&["2024-11-29T12:00:00Z", "2024-11-28T12:00:00Z"]);
+        let retained = retained(&all, 0, 0, &TimeZone::UTC);
+        assert_eq!(
+            retained,
+            timestamps(&["2024-11-29T12:00:00Z"]).into_iter().collect()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune_both_formats() {
+        let config = Config {
+            directory: temp_dir(),
+            keep_daily: 0,
+            keep_weekly: 0,
+            ..Config::default()
+        };
+        let directory = &config.directory;
+        let hash = format!("{:x}", Sha256::digest(b"sector"));
+        let blob = directory.join("blobs").join(&hash[..2]).join(&hash[2..]);
+        fs_err::create_dir_all(blob.parent().unwrap()).unwrap();
+        fs_err::write(&blob, b"sector").unwrap();
+        fs_err::write(directory.join("20241127T120000Z.tar.zst"), ZSTD_MAGIC).unwrap();
+        fs_err::write(
+            directory.join("20241128T120000Z.snapshot.json"),
+            format!(
+                r#"{{"directories":[],"files":[{{"path":"world/level.dat","blobs":["{hash}"]}}]}}"#
+            ),
+        )
+        .unwrap();
+        fs_err::write(
+            directory.join("20241129T120000Z.snapshot.json"),
+            r#"{"directories":[],"files":[]}"#,
+        )
+        .unwrap();
+
+        let formats: Vec<_> = list(&config)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.format)
+            .collect();
+        assert_eq!(formats, [Format::Store, Format::Store, Format::Archive]);
+
+        assert_eq!(prune(&config).await.unwrap().len(), 2);
+        assert_eq!(list(&config).await.unwrap().len(), 1);
+        assert!(!blob.exists());
+
+        fs_err::remove_dir_all(directory).unwrap();
+    }
+}
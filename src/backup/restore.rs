@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read},
+};
+
+use anyhow::{Context, anyhow, bail};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use jiff::Timestamp;
+use tar::EntryType;
+
+use super::{
+    Config, Format, Hashing, MANIFEST_PATH, TIMESTAMP_FORMAT, list, parse_timestamp, store,
+};
+use crate::{fetch::SERVER_PATH, jar, world};
+
+/// Archives are extracted here before replacing the world.
+const STAGING_PATH: &str = ".mc-restore.partial";
+
+/// The outcome of [`restore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restored {
+    /// World directories restored from the archive.
+    pub worlds: Vec<Utf8PathBuf>,
+    /// Where the replaced world directories were moved to.
+    pub replaced: Vec<Utf8PathBuf>,
+}
+
+/// Find a backup by path, or one in the backup directory by name (e.g. `20241129T213000Z-pre-1.21.4`), by timestamp (e.g.
+/// `20241129T213000Z` or `2024-11-29T21:30:00Z`) or the most recent with
+/// `latest`.
+pub async fn find(config: &Config, name: &str) -> anyhow::Result<Utf8PathBuf> {
+    if fs::metadata(name)
+        .await
+        .is_ok_and(|metadata| metadata.is_file())
+    {
+        return Ok(name.into());
+    }
+
+    let backups = list(config).await?;
+    let timestamp = parse_timestamp(name).or_else(|| name.parse::<Timestamp>().ok());
+    let found = if name == "latest" {
+        backups.into_iter().next()
+    } else {
+        backups.into_iter().find(|backup| {
+            Some(backup.timestamp) == timestamp
+                || backup
+                    .path
+                    .file_name()
+                    .and_then(|file| file.strip_suffix(backup.format.extension()))
+                    == Some(name)
+        })
+    };
+
+    found
+        .map(|backup| backup.path)
+        .ok_or_else(|| anyhow!("No backup matching {name} in {}", config.directory))
+}
+
+/// Restore the world from a backup archive or store snapshot.
+///
+/// The backup is extracted to a staging directory and verified against its
+/// checksums. The restored world must be compatible with the installed
+/// server. Only then are the current world directories moved aside, never
+/// deleted, and replaced with the restored ones.
+///
+/// The server must not be running; callers should hold the
+/// [workspace lock](crate::workspace::lock).
+pub async fn restore(archive: &Utf8Path) -> anyhow::Result<Restored> {
+    let staging = Utf8PathBuf::from(STAGING_PATH);
+    if fs::try_exists(&staging).await? {
+        tracing::debug!("Removing stale {staging}");
+        fs::remove_dir_all(&staging).await?;
+    }
+    fs::create_dir(&staging).await?;
+
+    let result = async {
+        tracing::info!("Extracting {archive}");
+        let (archive, target) = (archive.to_owned(), staging.clone());
+        let worlds = tokio::task::spawn_blocking(move || match Format::detect(&archive)? {
+            Format::Store => store::extract(&archive, &target),
+            Format::Archive => extract(&archive, &target),
+        })
+        .await??;
+        check_compatible(&staging, &worlds).await?;
+        Ok(worlds)
+    }
+    .await;
+    let worlds = match result {
+        Ok(worlds) => worlds,
+        Err(err) => {
+            if let Err(err) = fs::remove_dir_all(&staging).await {
+                tracing::warn!("Failed to remove {staging}: {err}");
+            }
+            return Err(err);
+        }
+    };
+
+    let suffix = Timestamp::now().strftime(TIMESTAMP_FORMAT);
+    let mut replaced = Vec::new();
+    for world in &worlds {
+        if fs::try_exists(world).await? {
+            let aside = Utf8PathBuf::from(format!("{world}.replaced-{suffix}"));
+            tracing::info!("Moving {world} aside to {aside}");
+            fs::rename(world, &aside).await?;
+            replaced.push(aside);
+        }
+        fs::rename(staging.join(world), world).await?;
+    }
+    fs::remove_dir(&staging).await?;
+
+    Ok(Restored { worlds, replaced })
+}
+
+/// Refuse to restore a world newer than the installed server.
+async fn check_compatible(staging: &Utf8Path, worlds: &[Utf8PathBuf]) -> anyhow::Result<()> {
+    if !fs::try_exists(SERVER_PATH).await? {
+        tracing::debug!("No {SERVER_PATH} installed, skipping compatibility check");
+        return Ok(());
+    }
+    let installed = jar::read_version(SERVER_PATH.into()).await?;
+    for world in worlds {
+        let world = staging.join(world);
+        if fs::try_exists(world.join(world::LEVEL_PATH)).await? {
+            let level = world::read_level(&world).await?;
+            world::check_compatible(&level, &installed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extract an archive into `target`, verifying it against its checksum manifest.
+///
+/// Returns the top-level directories of the archive.
+fn extract(archive: &Utf8Path, target: &Utf8Path) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let file = fs_err::File::open(archive)?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+
+    let mut checksums = BTreeMap::new();
+    let mut manifest = None;
+    let mut worlds = BTreeSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = Utf8PathBuf::try_from(entry.path()?.into_owned())?;
+        if path == MANIFEST_PATH {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            manifest = Some(content);
+            continue;
+        }
+
+        worlds.insert(check_path(&path)?);
+
+        let destination = target.join(&path);
+        match entry.header().entry_type() {
+            EntryType::Directory => fs_err::create_dir_all(&destination)?,
+            EntryType::Regular => {
+                if let Some(parent) = destination.parent() {
+                    fs_err::create_dir_all(parent)?;
+                }
+                let mut writer = Hashing::new(fs_err::File::create(&destination)?);
+                io::copy(&mut entry, &mut writer)?;
+                checksums.insert(path.into_string(), writer.finish());
+            }
+            entry_type => bail!("Unexpected {entry_type:?} entry in archive: {path}"),
+        }
+    }
+
+    let manifest = manifest.ok_or(anyhow!("Archive has no checksum manifest"))?;
+    verify(&parse_manifest(&manifest)?, &checksums)?;
+    Ok(worlds.into_iter().collect())
+}
+
+/// Only allow plain relative paths, so nothing is written outside the target.
+///
+/// Returns the top-level directory of the path.
+pub(super) fn check_path(path: &Utf8Path) -> anyhow::Result<Utf8PathBuf> {
+    let mut components = path.components();
+    let Some(Utf8Component::Normal(world)) = components.next() else {
+        bail!("Unexpected path in backup: {path}");
+    };
+    if !components.all(|component| matches!(component, Utf8Component::Normal(_))) {
+        bail!("Unexpected path in backup: {path}");
+    }
+    Ok(world.into())
+}
+
+/// Parse a manifest in `sha256sum` format into paths and checksums.
+fn parse_manifest(content: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    content
+        .lines()
+        .map(|line| {
+            let (checksum, path) = line
+                .split_once("  ")
+                .with_context(|| format!("Invalid checksum manifest line: {line}"))?;
+            Ok((path.to_string(), checksum.to_string()))
+        })
+        .collect()
+}
+
+/// Check that the extracted files are exactly those in the manifest.
+fn verify(
+    expected: &BTreeMap<String, String>,
+    actual: &BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    for (path, checksum) in expected {
+        match actual.get(path) {
+            None => bail!("{path} is missing from the archive"),
+            Some(actual) if actual != checksum => {
+                bail!("Checksum mismatch for {path} (expected: {checksum}, actual: {actual})")
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(path) = actual.keys().find(|path| !expected.contains_key(*path)) {
+        bail!("{path} is not in the checksum manifest");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::{tests::temp_dir, write_archive};
+
+    #[test]
+    fn test_archive_round_trip() {
+        let source = temp_dir();
+        fs_err::create_dir_all(source.join("world/region")).unwrap();
+        fs_err::write(source.join("world/level.dat"), b"level").unwrap();
+        fs_err::write(source.join("world/region/r.0.0.mca"), vec![7; 8192]).unwrap();
+        fs_err::create_dir_all(source.join("world_nether")).unwrap();
+
+        let archive = source.join("backup.tar.zst");
+        let worlds = ["world".into(), "world_nether".into()];
+        write_archive(&archive, &source, &worlds).unwrap();
+        assert_eq!(Format::detect(&archive).unwrap(), Format::Archive);
+
+        let target = temp_dir();
+        assert_eq!(extract(&archive, &target).unwrap(), worlds);
+        assert_eq!(
+            fs_err::read(target.join("world/region/r.0.0.mca")).unwrap(),
+            vec![7; 8192]
+        );
+        assert!(target.join("world_nether").is_dir());
+
+        fs_err::remove_dir_all(source).unwrap();
+        fs_err::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn test_check_path() {
+        assert_eq!(
+            check_path("world/region/r.0.0.mca".into()).unwrap(),
+            "world"
+        );
+        assert!(check_path("../world".into()).is_err());
+        assert!(check_path("/etc/passwd".into()).is_err());
+        assert!(check_path("world/../../etc".into()).is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = parse_manifest("abc  world/level.dat\ndef  world/a b.dat\n").unwrap();
+        assert_eq!(manifest["world/level.dat"], "abc");
+        assert_eq!(manifest["world/a b.dat"], "def");
+        assert!(parse_manifest("abc world/level.dat").is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let checksums = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+                .collect()
+        };
+        let expected = checksums(&[("world/level.dat", "abc")]);
+        assert!(verify(&expected, &checksums(&[("world/level.dat", "abc")])).is_ok());
+        assert!(verify(&expected, &checksums(&[("world/level.dat", "xyz")])).is_err());
+        assert!(verify(&expected, &checksums(&[])).is_err());
+        assert!(
+            verify(
+                &expected,
+                &checksums(&[("world/level.dat", "abc"), ("world/extra", "def")])
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeSet,
+    io::{ErrorKind, Write},
+    sync::Mutex,
+};
+
+use anyhow::{Context, bail};
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::restore::check_path;
+
+/// Snapshot indexes are named like archives, with this extension.
+pub(super) const EXTENSION: &str = ".snapshot.json";
+/// Blobs live in this directory of the store, named by their SHA-256.
+const BLOBS_PATH: &str = "blobs";
+/// Region files are split into sectors, the unit chunks are allocated in, so
+/// unchanged chunks are stored once across snapshots.
+const SECTOR_SIZE: usize = 4096;
+const REGION_EXTENSIONS: &[&str] = &["mca", "mcr"];
+
+/// Garbage collection must not delete blobs a snapshot is being written with.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A snapshot: the directories and files of the world, with file contents as
+/// a list of blobs.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+struct Index {
+    directories: Vec<Utf8PathBuf>,
+    files: Vec<File>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct File {
+    path: Utf8PathBuf,
+    /// SHA-256 of each blob making up the file, in order.
+    blobs: Vec<String>,
+}
+
+/// Snapshot the given directories, relative to `base`, writing the index to
+/// `path` and any new blobs to the store it's in.
+///
+/// Returns the size of the index in bytes.
+pub(super) fn write(
+    path: &Utf8Path,
+    base: &Utf8Path,
+    directories: &[Utf8PathBuf],
+) -> anyhow::Result<u64> {
+    let store = path.parent().context("Snapshot has no parent directory")?;
+    let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    let mut index = Index::default();
+    let mut added = 0;
+    for directory in directories {
+        add_dir(&mut index, &mut added, store, base, directory)
+            .with_context(|| format!("Failed to snapshot {directory}"))?;
+    }
+    tracing::info!("Added {} to the backup store", ByteSize(added));
+
+    let name = path.file_name().context("Snapshot has no file name")?;
+    let temp_path = store.join(format!(".{name}.partial"));
+    let mut file = fs_err::File::create(&temp_path)?;
+    serde_json::to_writer(&mut file, &index)?;
+    file.sync_all()?;
+    let size = file.metadata()?.len();
+    // Link rather than rename, to refuse to overwrite an existing snapshot.
+    let linked = fs_err::hard_link(&temp_path, path);
+    fs_err::remove_file(&temp_path)?;
+    match linked {
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => bail!("Backup {path} already exists"),
+        result => Ok(result.map(|()| size)?),
+    }
+}
+
+/// Recursively add a directory to the index, storing blobs that are new.
+fn add_dir(
+    index: &mut Index,
+    added: &mut u64,
+    store: &Utf8Path,
+    base: &Utf8Path,
+    directory: &Utf8Path,
+) -> anyhow::Result<()> {
+    index.directories.push(directory.to_owned());
+
+    let mut entries = fs_err::read_dir(base.join(directory))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow::anyhow!("Non UTF-8 file name: {name:?}"))?;
+        let path = directory.join(name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_dir(index, added, store, base, &path)?;
+        } else if file_type.is_file() {
+            let data = fs_err::read(base.join(&path))?;
+            let region = path
+                .extension()
+                .is_some_and(|extension| REGION_EXTENSIONS.contains(&extension));
+            let chunks: Vec<&[u8]> = if region {
+                data.chunks(SECTOR_SIZE).collect()
+            } else {
+                vec![&data]
+            };
+            let blobs = chunks
+                .into_iter()
+                .map(|chunk| put_blob(store, chunk, added))
+                .collect::<anyhow::Result<_>>()?;
+            index.files.push(File { path, blobs });
+        } else {
+            tracing::debug!("Skipping special file {path}");
+        }
+    }
+    Ok(())
+}
+
+/// Read a snapshot's index, checking every blob is named by a SHA-256 before
+/// it's used as a path.
+fn read_index(path: &Utf8Path) -> anyhow::Result<Index> {
+    let index: Index = serde_json::from_slice(&fs_err::read(path)?)
+        .with_context(|| format!("Invalid snapshot index {path}"))?;
+    for hash in index.files.iter().flat_map(|file| &file.blobs) {
+        if hash.len() != 64
+            || !hash
+                .bytes()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            bail!("Invalid snapshot index {path}: {hash:?} is not a SHA-256");
+        }
+    }
+    Ok(index)
+}
+
+fn blob_path(store: &Utf8Path, hash: &str) -> Utf8PathBuf {
+    store.join(BLOBS_PATH).join(&hash[..2]).join(&hash[2..])
+}
+
+/// Store a blob unless it already exists, returning its hash.
+fn put_blob(store: &Utf8Path, data: &[u8], added: &mut u64) -> anyhow::Result<String> {
+    let hash = format!("{:x}", Sha256::digest(data));
+    let path = blob_path(store, &hash);
+    if path.exists() {
+        return Ok(hash);
+    }
+
+    let directory = path.parent().context("Blob has no parent directory")?;
+    fs_err::create_dir_all(directory)?;
+    let temp_path = directory.join(format!(".{hash}.partial"));
+    let mut file = fs_err::File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs_err::rename(&temp_path, &path)?;
+    *added += data.len() as u64;
+    Ok(hash)
+}
+
+/// Restore a snapshot into `target`, verifying every blob against its hash.
+///
+/// Returns the top-level directories of the snapshot.
+pub(super) fn extract(path: &Utf8Path, target: &Utf8Path) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let store = path.parent().context("Snapshot has no parent directory")?;
+    let index = read_index(path)?;
+
+    let mut worlds = BTreeSet::new();
+    for directory in &index.directories {
+        worlds.insert(check_path(directory)?);
+        fs_err::create_dir_all(target.join(directory))?;
+    }
+    for file in &index.files {
+        worlds.insert(check_path(&file.path)?);
+        let mut writer = fs_err::File::create(target.join(&file.path))?;
+        for hash in &file.blobs {
+            let data = fs_err::read(blob_path(store, hash))
+                .with_context(|| format!("Missing data for {}", file.path))?;
+            if format!("{:x}", Sha256::digest(&data)) != *hash {
+                bail!("Corrupt blob {hash} in {}", file.path);
+            }
+            writer.write_all(&data)?;
+        }
+    }
+    Ok(worlds.into_iter().collect())
+}
+
+/// Delete blobs that no snapshot in the store refers to.
+///
+/// Returns the number of bytes freed.
+pub(super) fn collect_garbage(store: &Utf8Path) -> anyhow::Result<u64> {
+    let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    let mut referenced = BTreeSet::new();
+    for entry in fs_err::read_dir(store)? {
+        let path = Utf8PathBuf::try_from(entry?.path())?;
+        if !path.as_str().ends_with(EXTENSION) {
+            continue;
+        }
+        let index = read_index(&path)?;
+        referenced.extend(index.files.into_iter().flat_map(|file| file.blobs));
+    }
+
+    let blobs = store.join(BLOBS_PATH);
+    let prefixes = match fs_err::read_dir(&blobs) {
+        Ok(prefixes) => prefixes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut freed = 0;
+    for prefix in prefixes {
+        let prefix = prefix?;
+        let Ok(prefix_name) = prefix.file_name().into_string() else {
+            continue;
+        };
+        for entry in fs_err::read_dir(prefix.path())? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            // Partial blobs are left over from interrupted snapshots.
+            if !name.ends_with(".partial") && referenced.contains(&format!("{prefix_name}{name}")) {
+                continue;
+            }
+            freed += entry.metadata()?.len();
+            fs_err::remove_file(entry.path())?;
+        }
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    use crate::backup::tests::temp_dir;
+
+    fn blob_count(store: &Utf8Path) -> usize {
+        fs_err::read_dir(store.join(BLOBS_PATH))
+            .unwrap()
+            .map(|prefix| fs_err::read_dir(prefix.unwrap().path()).unwrap().count())
+            .sum()
+    }
+
+    #[test]
+    fn test_snapshot_deduplicates_sectors() {
+        let base = temp_dir();
+        let store = temp_dir();
+        fs_err::create_dir_all(base.join("world/region")).unwrap();
+        fs_err::write(base.join("world/level.dat"), b"level").unwrap();
+        let mut region = [vec![1; SECTOR_SIZE], vec![2; SECTOR_SIZE], vec![1; 100]].concat();
+        fs_err::write(base.join("world/region/r.0.0.mca"), &region).unwrap();
+
+        let worlds = ["world".into()];
+        let first = store.join(format!("first{EXTENSION}"));
+        write(&first, &base, &worlds).unwrap();
+        // level.dat plus three distinct sectors.
+        assert_eq!(blob_count(&store), 4);
+
+        region[SECTOR_SIZE] = 3;
+        fs_err::write(base.join("world/region/r.0.0.mca"), &region).unwrap();
+        let second = store.join(format!("second{EXTENSION}"));
+        write(&second, &base, &worlds).unwrap();
+        assert_eq!(blob_count(&store), 5);
+
+        let target = temp_dir();
+        assert_eq!(extract(&second, &target).unwrap(), worlds);
+        assert_eq!(
+            fs_err::read(target.join("world/region/r.0.0.mca")).unwrap(),
+            region
+        );
+        assert_eq!(
+            fs_err::read(target.join("world/level.dat")).unwrap(),
+            b"level"
+        );
+
+        fs_err::remove_file(&first).unwrap();
+        assert_eq!(collect_garbage(&store).unwrap(), SECTOR_SIZE as u64);
+        assert_eq!(blob_count(&store), 4);
+        let again = temp_dir();
+        assert!(extract(&second, &again).is_ok());
+
+        for directory in [base, store, target, again] {
+            fs_err::remove_dir_all(directory).unwrap();
+        }
+    }
+
+    #[test_case("2e2b0d5b7ba0e8e1b0b4d6a1c2d1f3a4c5b6d7e8f9a0b1c2d3e4f5a6b7c8d9e0", true ; "sha256")]
+    #[test_case("a", false ; "short")]
+    #[test_case("2e2b0d5b7ba0e8e1b0b4d6a1c2d1f3a4c5b6d7e8f9a0b1c2d3e4f5a6b7c8d9e", false ; "truncated")]
+    #[test_case("2E2B0D5B7BA0E8E1B0B4D6A1C2D1F3A4C5B6D7E8F9A0B1C2D3E4F5A6B7C8D9E0", false ; "uppercase")]
+    #[test_case("../../../../../../../../../../../../../../../../../../../../etc/", false ; "path")]
+    fn test_read_index_checks_hashes(hash: &str, valid: bool) {
+        let store = temp_dir();
+        let snapshot = store.join(format!("snapshot{EXTENSION}"));
+        let index = format!(
+            r#"{{"directories":[],"files":[{{"path":"world/level.dat","blobs":["{hash}"]}}]}}"#
+        );
+        fs_err::write(&snapshot, index).unwrap();
+        assert_eq!(read_index(&snapshot).is_ok(), valid);
+
+        fs_err::remove_dir_all(store).unwrap();
+    }
+
+    #[test]
+    fn test_extract_detects_corruption() {
+        let base = temp_dir();
+        let store = temp_dir();
+        fs_err::create_dir_all(base.join("world")).unwrap();
+        fs_err::write(base.join("world/level.dat"), b"level").unwrap();
+
+        let snapshot = store.join(format!("snapshot{EXTENSION}"));
+        write(&snapshot, &base, &["world".into()]).unwrap();
+        let hash = format!("{:x}", Sha256::digest(b"level"));
+        fs_err::write(blob_path(&store, &hash), b"grief").unwrap();
+
+        let target = temp_dir();
+        assert!(extract(&snapshot, &target).is_err());
+
+        for directory in [base, store, target] {
+            fs_err::remove_dir_all(directory).unwrap();
+        }
+    }
+}
@@ -1,13 +1,20 @@
 mod env;
 mod logging;
+mod settings;
 
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
 use camino::Utf8PathBuf;
 use clap::{
-    Parser,
-    builder::styling::{AnsiColor, Effects, Styles},
+    Parser, Subcommand,
+    builder::{
+        PossibleValuesParser, TypedValueParser,
+        styling::{AnsiColor, Effects, Styles},
+    },
 };
+use mc::{backup, provider::ServerType, server::RestartPolicy};
+
+pub use settings::Settings;
 
 // Use a cargo-inspired colorscheme.
 const STYLE: Styles = Styles::styled()
@@ -24,6 +31,7 @@ pub struct Args {
     #[arg(
         long,
         value_enum,
+        global = true,
         env = env::LOG_LEVEL,
         default_value_t = logging::LogLevel::default()
     )]
@@ -31,31 +39,401 @@ pub struct Args {
 
     // https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/struct.SubscriberBuilder.html#method.with_env_filter
     /// Control logging filter, may override verbosity
-    #[arg(long, env = env::LOG_FILTER, default_value = logging::DEFAULT_FILTER )]
+    #[arg(long, global = true, env = env::LOG_FILTER, default_value = logging::DEFAULT_FILTER )]
     pub log_filter: String,
 
-    /// Server version
-    #[arg(long, env = env::SERVER_VERSION)]
-    pub server_version: Option<String>,
-
     /// Set workspace directory
-    #[arg(long, env = env::DIRECTORY)]
+    #[arg(long, global = true, env = env::DIRECTORY)]
     pub directory: Option<Utf8PathBuf>,
 
-    /// Seconds to wait for graceful shutdown before killing the server
+    /// Path to a config file [default: mc.toml in the workspace]
+    #[arg(long, global = true, env = env::CONFIG)]
+    pub config: Option<Utf8PathBuf>,
+
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Settings which may also be provided by the config file.
+///
+/// These are all optional so that an unset flag or env var falls through to
+/// the config file and then to the defaults in [`Settings`].
+#[derive(Debug, clap::Args)]
+pub struct ServerArgs {
+    /// Where the server comes from [default: vanilla]
+    #[arg(long, env = env::SERVER_TYPE, value_parser = one_of(ServerType::ALL, ServerType::name))]
+    pub server_type: Option<ServerType>,
+
+    /// Server version [default: latest release]
+    #[arg(long, env = env::SERVER_VERSION)]
+    pub server_version: Option<String>,
+
+    /// Seconds to wait for graceful shutdown before killing the server [default: 30]
     #[arg(
         long,
         env = env::SHUTDOWN_TIMEOUT,
-        default_value = "30",
         value_parser = |s: &str| s.parse::<u64>().map(Duration::from_secs)
     )]
-    pub shutdown_timeout: Duration,
+    pub shutdown_timeout: Option<Duration>,
+
+    /// Minimum heap size for the JVM (-Xms), e.g. 1G, 512M, 2048K [default: 1G]
+    #[arg(long, env = env::MIN_MEMORY)]
+    pub min_memory: Option<String>,
+
+    /// Maximum heap size for the JVM (-Xmx), e.g. 1G, 512M, 2048K [default: 1G]
+    #[arg(long, env = env::MAX_MEMORY)]
+    pub max_memory: Option<String>,
+
+    /// Extra argument for the JVM, may be repeated. The env var takes several
+    /// separated by spaces
+    #[arg(long = "jvm-arg", env = env::JVM_ARGS, allow_hyphen_values = true)]
+    pub jvm_args: Vec<String>,
+
+    /// When to restart the server after it exits on its own [default: never]
+    #[arg(long, env = env::RESTART, value_parser = one_of(RestartPolicy::ALL, RestartPolicy::name))]
+    pub restart: Option<RestartPolicy>,
+
+    /// Roll back the jar and world if an upgraded server fails to start
+    #[arg(long, env = env::AUTO_ROLLBACK)]
+    pub auto_rollback: bool,
+
+    /// Warn players and wait up to this long for them to leave before
+    /// stopping, e.g. 5m
+    #[arg(long, env = env::DRAIN_TIMEOUT, value_parser = mc::config::parse_duration)]
+    pub drain_timeout: Option<Duration>,
+
+    /// Stop draining as soon as the last player leaves
+    #[arg(long, env = env::DRAIN_UNTIL_EMPTY)]
+    pub drain_until_empty: bool,
+
+    /// Stop the server once nobody has been online this long, e.g. 30m, and
+    /// start it again when someone tries to join
+    #[arg(long, env = env::IDLE_TIMEOUT, value_parser = mc::config::parse_duration)]
+    pub idle_timeout: Option<Duration>,
+
+    /// Serve Prometheus metrics on this address, e.g. 0.0.0.0:9225
+    #[arg(long, env = env::METRICS_LISTEN)]
+    pub metrics_listen: Option<SocketAddr>,
+
+    /// Where to look up player UUIDs, for ops, whitelist and bans
+    /// [default: https://api.mojang.com]
+    #[arg(long, global = true, env = env::PROFILE_API_URL)]
+    pub profile_api_url: Option<String>,
+
+    /// Directory to write world backups to [default: backups]
+    #[arg(long, env = env::BACKUP_DIRECTORY)]
+    pub backup_directory: Option<Utf8PathBuf>,
+
+    /// How to store world backups [default: archive]
+    #[arg(long, env = env::BACKUP_FORMAT, value_parser = one_of(backup::Format::ALL, backup::Format::name))]
+    pub backup_format: Option<backup::Format>,
+
+    /// How often to back up the world while the server runs, e.g. 6h
+    #[arg(long, env = env::BACKUP_INTERVAL, value_parser = mc::config::parse_duration)]
+    pub backup_interval: Option<Duration>,
+
+    /// Rotate the log of server output once it reaches this size, e.g. 50MiB
+    /// [default: 10MiB]
+    #[arg(long, env = env::LOGS_MAX_SIZE, value_parser = mc::config::parse_size)]
+    pub logs_max_size: Option<u64>,
+
+    /// Also rotate the log of server output this often, e.g. 1d
+    #[arg(long, env = env::LOGS_INTERVAL, value_parser = mc::config::parse_duration)]
+    pub logs_interval: Option<Duration>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the server (the default when no command is given)
+    Run,
+    /// Resolve the server version again and update the lock file
+    ///
+    /// Without a configured version this moves to the latest release. Server
+    /// types with several builds per version move to the latest stable build.
+    /// The world is never downgraded past the version that last ran it.
+    Update,
+    /// Back up the world, coordinating with the server if it's running
+    Backup,
+    /// Restore the world from a backup, moving the current world aside
+    ///
+    /// The server must be stopped first.
+    Restore {
+        /// Path to a backup archive, its timestamp, or `latest`
+        archive: String,
+    },
+    /// Return to the server version replaced by the last upgrade
+    ///
+    /// The world is restored from the backup taken before the upgrade, and
+    /// the current world is moved aside. The server must be stopped first.
+    Rollback,
+    /// Check that a server is up with a Server List Ping
+    ///
+    /// Prints the server's version, players and MOTD, and fails if it
+    /// doesn't answer, so it can be used as a health check.
+    Ping {
+        /// Server address [default: localhost and the workspace's server-port]
+        address: Option<String>,
 
-    /// Minimum heap size for the JVM (-Xms), e.g. 1G, 512M, 2048K
-    #[arg(long, env = env::MIN_MEMORY, default_value = "1G")]
-    pub min_memory: String,
+        /// How long to wait for the server to answer
+        #[arg(long, default_value = "5s", value_parser = mc::config::parse_duration)]
+        timeout: Duration,
+    },
+    /// Query a server for every player online and its plugins
+    ///
+    /// The server needs `enable-query=true` in its server.properties.
+    Query {
+        /// Server address [default: localhost and the workspace's query.port]
+        address: Option<String>,
+
+        /// How long to wait for the server to answer
+        #[arg(long, default_value = "5s", value_parser = mc::config::parse_duration)]
+        timeout: Duration,
+    },
+    /// Run a console command and print the server's response
+    ///
+    /// Goes through the mc process supervising the server, or RCON if the
+    /// server isn't supervised and has enable-rcon=true. Fails if the server
+    /// doesn't know the command.
+    Exec {
+        /// The command, e.g. `op heavymetalpanda`
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+
+        /// Collect output up to the first line containing this, failing if it
+        /// doesn't appear in time, instead of only the first line
+        #[arg(long)]
+        until: Option<String>,
+
+        /// How long to wait for the response
+        #[arg(long, default_value = "1s", value_parser = mc::config::parse_duration)]
+        timeout: Duration,
+
+        /// Use RCON even if mc is supervising the server
+        #[arg(long)]
+        rcon: bool,
+    },
+    /// Show the server output logged by mc for one run of the server
+    ///
+    /// Output is kept across restarts and crashes in rotated logs under the
+    /// logs directory, along with when each run started and how it ended.
+    Logs {
+        /// The run to show, counting back from the latest with -1, or its id
+        #[arg(long, default_value_t = -1, allow_negative_numbers = true)]
+        run: i64,
+
+        /// List the recorded runs instead
+        #[arg(long)]
+        list: bool,
+    },
+    /// Show the version of the installed server
+    Version,
+    /// Inspect the world
+    #[command(subcommand)]
+    World(WorldCommand),
+    /// Manage mods or plugins from Modrinth, pinned in mods.lock
+    ///
+    /// Mods are resolved for the locked server type and Minecraft version,
+    /// and installed into mods/ or plugins/. The server must be stopped first.
+    #[command(subcommand)]
+    Mods(ModsCommand),
+    /// Install or export Modrinth modpacks (.mrpack)
+    #[command(subcommand)]
+    Modpack(ModpackCommand),
+    /// Manage the world's data packs
+    ///
+    /// Changes are applied to a running server with /datapack and /reload.
+    #[command(subcommand)]
+    Datapacks(DatapacksCommand),
+    /// Manage server operators in ops.json
+    ///
+    /// A running server is told with /op and /deop instead, so that it
+    /// doesn't overwrite the change.
+    #[command(subcommand)]
+    Ops(OpsCommand),
+    /// Manage the players allowed to join in whitelist.json
+    ///
+    /// A running server is told with /whitelist instead, so that it doesn't
+    /// overwrite the change.
+    #[command(subcommand)]
+    Whitelist(WhitelistCommand),
+    /// Manage banned players and IP addresses
+    ///
+    /// A running server is told with /ban and /pardon instead, so that it
+    /// doesn't overwrite the change.
+    #[command(subcommand)]
+    Bans(BansCommand),
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WorldCommand {
+    /// Print metadata from the world's level.dat
+    Info,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModsCommand {
+    /// List the installed mods
+    List,
+    /// Install mods and their required dependencies
+    Add {
+        /// Modrinth project slugs or ids
+        #[arg(required = true)]
+        projects: Vec<String>,
+    },
+    /// Uninstall mods, and dependencies nothing else requires
+    Remove {
+        /// Modrinth project slugs or ids
+        #[arg(required = true)]
+        projects: Vec<String>,
+    },
+    /// Move mods to their newest compatible versions
+    Update {
+        /// Modrinth project slugs or ids [default: every installed mod]
+        projects: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModpackCommand {
+    /// Install a modpack's server files and pin its Minecraft and loader versions
+    ///
+    /// Replaces the mods in mods.lock. The server must be stopped first.
+    Install {
+        /// Path to a .mrpack file
+        pack: Utf8PathBuf,
+    },
+    /// Export the workspace's mods and config as a modpack
+    Export {
+        /// Where to write the modpack [default: <name>-<pack version>.mrpack]
+        #[arg(long, short)]
+        output: Option<Utf8PathBuf>,
+
+        /// Name of the modpack [default: the workspace directory's name]
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Version of the modpack itself
+        #[arg(long, default_value = "1.0.0")]
+        pack_version: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DatapacksCommand {
+    /// List the installed data packs and whether they're enabled
+    List,
+    /// Install a data pack zip, checking it supports the server version
+    Add {
+        /// Path or http(s) URL of a data pack zip
+        source: String,
+
+        /// Install even if pack.mcmeta declares other pack formats
+        #[arg(long)]
+        force: bool,
+    },
+    /// Uninstall a data pack
+    Remove {
+        /// Data pack file name, with or without .zip
+        name: String,
+    },
+    /// Let the server load a data pack
+    Enable {
+        /// Data pack file name, with or without .zip
+        name: String,
+    },
+    /// Stop the server loading a data pack, without uninstalling it
+    Disable {
+        /// Data pack file name, with or without .zip
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OpsCommand {
+    /// List the operators and their permission levels
+    List,
+    /// Make a player an operator
+    Add {
+        /// Player name
+        name: String,
+
+        /// Permission level [default: op-permission-level from server.properties]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=4))]
+        level: Option<u8>,
+
+        /// Let the player join when the server is full
+        #[arg(long)]
+        bypass_player_limit: bool,
+    },
+    /// Stop a player being an operator
+    Remove {
+        /// Player name
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WhitelistCommand {
+    /// List the players allowed to join
+    List,
+    /// Allow players to join
+    Add {
+        /// Player names
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Stop players joining
+    Remove {
+        /// Player names
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BansCommand {
+    /// List the banned players and IP addresses
+    List,
+    /// Ban a player or IP address
+    Add {
+        /// Player name or IP address
+        target: String,
+
+        /// Why they're banned
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift a ban on a player or IP address
+    Remove {
+        /// Player name or IP address
+        target: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the resolved configuration and where each value came from
+    Show,
+}
 
-    /// Maximum heap size for the JVM (-Xmx), e.g. 1G, 512M, 2048K
-    #[arg(long, env = env::MAX_MEMORY, default_value = "1G")]
-    pub max_memory: String,
+/// Parse one of a library enum's values by name, listing them in `--help`.
+///
+/// The library doesn't depend on clap, so its enums name their values instead
+/// of deriving `ValueEnum`.
+fn one_of<T: Copy + Send + Sync + 'static>(
+    values: &'static [T],
+    name: fn(T) -> &'static str,
+) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(values.iter().map(|&value| name(value))).map(move |chosen| {
+        *values
+            .iter()
+            .find(|&&value| name(value) == chosen)
+            .expect("only possible values are parsed")
+    })
 }
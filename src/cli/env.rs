@@ -1,7 +1,22 @@
 pub(super) const LOG_LEVEL: &str = "MC_LOG_LEVEL";
 pub(super) const LOG_FILTER: &str = "MC_LOG_FILTER";
+pub(super) const SERVER_TYPE: &str = "MC_SERVER_TYPE";
 pub(super) const SERVER_VERSION: &str = "MC_SERVER_VERSION";
 pub(super) const DIRECTORY: &str = "MC_DIRECTORY";
 pub(super) const SHUTDOWN_TIMEOUT: &str = "MC_SHUTDOWN_TIMEOUT";
 pub(super) const MIN_MEMORY: &str = "MC_MIN_MEMORY";
 pub(super) const MAX_MEMORY: &str = "MC_MAX_MEMORY";
+pub(super) const CONFIG: &str = "MC_CONFIG";
+pub(super) const JVM_ARGS: &str = "MC_JVM_ARGS";
+pub(super) const RESTART: &str = "MC_RESTART";
+pub(super) const AUTO_ROLLBACK: &str = "MC_AUTO_ROLLBACK";
+pub(super) const DRAIN_TIMEOUT: &str = "MC_DRAIN_TIMEOUT";
+pub(super) const DRAIN_UNTIL_EMPTY: &str = "MC_DRAIN_UNTIL_EMPTY";
+pub(super) const IDLE_TIMEOUT: &str = "MC_IDLE_TIMEOUT";
+pub(super) const METRICS_LISTEN: &str = "MC_METRICS_LISTEN";
+pub(super) const BACKUP_DIRECTORY: &str = "MC_BACKUP_DIRECTORY";
+pub(super) const BACKUP_FORMAT: &str = "MC_BACKUP_FORMAT";
+pub(super) const BACKUP_INTERVAL: &str = "MC_BACKUP_INTERVAL";
+pub(super) const LOGS_MAX_SIZE: &str = "MC_LOGS_MAX_SIZE";
+pub(super) const LOGS_INTERVAL: &str = "MC_LOGS_INTERVAL";
+pub(super) const PROFILE_API_URL: &str = "MC_PROFILE_API_URL";
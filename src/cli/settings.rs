@@ -0,0 +1,471 @@
+use std::{collections::BTreeMap, fmt, net::SocketAddr, time::Duration};
+
+use camino::Utf8PathBuf;
+use clap::parser::ValueSource;
+use mc::{
+    backup,
+    config::{self, Source, Sourced},
+    logs,
+    profile::MOJANG_API_URL,
+    provider::ServerType,
+    server::{self, Action, Drain, Idle, RestartPolicy, Task, Trigger},
+};
+
+use super::ServerArgs;
+
+/// Settings resolved from the config file, env vars and flags.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub server_type: Sourced<ServerType>,
+    pub server_version: Sourced<Option<String>>,
+    pub min_memory: Sourced<String>,
+    pub max_memory: Sourced<String>,
+    pub jvm_args: Sourced<Vec<String>>,
+    pub shutdown_timeout: Sourced<Duration>,
+    pub restart: Sourced<RestartPolicy>,
+    pub auto_rollback: Sourced<bool>,
+    pub drain_timeout: Sourced<Option<Duration>>,
+    pub drain_message: Sourced<String>,
+    pub drain_until_empty: Sourced<bool>,
+    pub idle_timeout: Sourced<Option<Duration>>,
+    pub idle_motd: Sourced<String>,
+    pub metrics_listen: Sourced<Option<SocketAddr>>,
+    pub profile_api_url: Sourced<String>,
+    pub properties: BTreeMap<String, Sourced<String>>,
+    pub backup_directory: Sourced<Utf8PathBuf>,
+    pub backup_format: Sourced<backup::Format>,
+    pub keep_daily: Sourced<usize>,
+    pub keep_weekly: Sourced<usize>,
+    pub backup_interval: Sourced<Option<Duration>>,
+    pub logs_directory: Sourced<Utf8PathBuf>,
+    pub logs_max_size: Sourced<u64>,
+    pub logs_interval: Sourced<Option<Duration>>,
+    pub logs_keep: Sourced<usize>,
+    /// Scheduled tasks, which can only be set in the config file.
+    pub schedule: Vec<Task>,
+}
+
+/// Determine whether an argument came from an env var or a flag.
+fn arg<T>(matches: &clap::ArgMatches, id: &str, value: Option<T>) -> Option<(T, Source)> {
+    let source = match matches.value_source(id)? {
+        ValueSource::EnvVariable => Source::Env,
+        ValueSource::CommandLine => Source::Flag,
+        _ => return None,
+    };
+    value.map(|value| (value, source))
+}
+
+impl Settings {
+    /// Layer flags and env vars over the config file and defaults.
+    pub fn resolve(args: ServerArgs, matches: &clap::ArgMatches, file: config::File) -> Self {
+        let config::File {
+            server,
+            properties,
+            backup,
+            logs,
+            schedule,
+        } = file;
+
+        // Only the env var lists several arguments in one value.
+        let jvm_args = match matches.value_source("jvm_args") {
+            Some(ValueSource::EnvVariable) => args
+                .jvm_args
+                .iter()
+                .flat_map(|value| value.split_whitespace())
+                .map(str::to_string)
+                .collect(),
+            _ => args.jvm_args,
+        };
+        let jvm_args = Some(jvm_args).filter(|args| !args.is_empty());
+
+        Settings {
+            server_type: Sourced::resolve(
+                arg(matches, "server_type", args.server_type),
+                server.server_type,
+                ServerType::default,
+            ),
+            server_version: Sourced::resolve(
+                arg(matches, "server_version", args.server_version.map(Some)),
+                server.version.map(Some),
+                || None,
+            ),
+            min_memory: Sourced::resolve(
+                arg(matches, "min_memory", args.min_memory),
+                server.min_memory,
+                || server::DEFAULT_MEMORY.to_string(),
+            ),
+            max_memory: Sourced::resolve(
+                arg(matches, "max_memory", args.max_memory),
+                server.max_memory,
+                || server::DEFAULT_MEMORY.to_string(),
+            ),
+            jvm_args: Sourced::resolve(
+                arg(matches, "jvm_args", jvm_args),
+                server.jvm_args,
+                Vec::new,
+            ),
+            shutdown_timeout: Sourced::resolve(
+                arg(matches, "shutdown_timeout", args.shutdown_timeout),
+                server.shutdown_timeout.map(Duration::from_secs),
+                || server::DEFAULT_SHUTDOWN_TIMEOUT,
+            ),
+            restart: Sourced::resolve(
+                arg(matches, "restart", args.restart),
+                server.restart,
+                RestartPolicy::default,
+            ),
+            auto_rollback: Sourced::resolve(
+                arg(matches, "auto_rollback", Some(args.auto_rollback)),
+                server.auto_rollback,
+                || false,
+            ),
+            drain_timeout: Sourced::resolve(
+                arg(matches, "drain_timeout", args.drain_timeout.map(Some)),
+                server.drain_timeout.map(Some),
+                || None,
+            ),
+            drain_message: Sourced::resolve(None, server.drain_message, Drain::default_message),
+            drain_until_empty: Sourced::resolve(
+                arg(matches, "drain_until_empty", Some(args.drain_until_empty)),
+                server.drain_until_empty,
+                || false,
+            ),
+            idle_timeout: Sourced::resolve(
+                arg(matches, "idle_timeout", args.idle_timeout.map(Some)),
+                server.idle_timeout.map(Some),
+                || None,
+            ),
+            idle_motd: Sourced::resolve(None, server.idle_motd, Idle::default_motd),
+            metrics_listen: Sourced::resolve(
+                arg(matches, "metrics_listen", args.metrics_listen.map(Some)),
+                server.metrics_listen.map(Some),
+                || None,
+            ),
+            profile_api_url: Sourced::resolve(
+                arg(matches, "profile_api_url", args.profile_api_url),
+                server.profile_api_url,
+                || MOJANG_API_URL.to_string(),
+            ),
+            properties: properties
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = Sourced {
+                        value: value.to_string(),
+                        source: Source::File,
+                    };
+                    (key, value)
+                })
+                .collect(),
+            backup_directory: Sourced::resolve(
+                arg(matches, "backup_directory", args.backup_directory),
+                backup.directory,
+                || backup::DEFAULT_DIRECTORY.into(),
+            ),
+            backup_format: Sourced::resolve(
+                arg(matches, "backup_format", args.backup_format),
+                backup.format,
+                backup::Format::default,
+            ),
+            keep_daily: Sourced::resolve(None, backup.keep_daily, || backup::DEFAULT_KEEP_DAILY),
+            keep_weekly: Sourced::resolve(None, backup.keep_weekly, || backup::DEFAULT_KEEP_WEEKLY),
+            backup_interval: Sourced::resolve(
+                arg(matches, "backup_interval", args.backup_interval.map(Some)),
+                backup.interval.map(Some),
+                || None,
+            ),
+            logs_directory: Sourced::resolve(None, logs.directory, || {
+                logs::DEFAULT_DIRECTORY.into()
+            }),
+            logs_max_size: Sourced::resolve(
+                arg(matches, "logs_max_size", args.logs_max_size),
+                logs.max_size,
+                || logs::DEFAULT_MAX_SIZE,
+            ),
+            logs_interval: Sourced::resolve(
+                arg(matches, "logs_interval", args.logs_interval.map(Some)),
+                logs.interval.map(Some),
+                || None,
+            ),
+            logs_keep: Sourced::resolve(None, logs.keep, || logs::DEFAULT_KEEP),
+            schedule,
+        }
+    }
+
+    /// The server type, unless left to default to the locked one, e.g. after
+    /// installing a modpack.
+    pub fn configured_server_type(&self) -> Option<ServerType> {
+        (self.server_type.source != Source::Default).then_some(self.server_type.value)
+    }
+
+    /// Build the configuration for running the server.
+    pub fn server_config(&self, directory: Utf8PathBuf) -> server::Config {
+        let mut config = server::Config::new(directory);
+        config.shutdown_timeout = self.shutdown_timeout.value;
+        config.min_memory = self.min_memory.value.clone();
+        config.max_memory = self.max_memory.value.clone();
+        config.jvm_args = self.jvm_args.value.clone();
+        config.restart = self.restart.value;
+        config.backup = self.backup_config();
+        config.schedule = self.schedule.clone();
+        config.drain = self.drain_timeout.value.map(|timeout| {
+            let mut drain = Drain::new(timeout);
+            drain.message = self.drain_message.value.clone();
+            drain.until_empty = self.drain_until_empty.value;
+            drain
+        });
+        config.idle = self.idle_timeout.value.map(|timeout| {
+            let mut idle = Idle::new(timeout);
+            idle.motd = self.idle_motd.value.clone();
+            idle
+        });
+        config.metrics_listen = self.metrics_listen.value;
+        config.logs = self.logs_config();
+        config
+    }
+
+    /// Build the configuration for backing up the world.
+    pub fn backup_config(&self) -> backup::Config {
+        let mut config = backup::Config::default();
+        config.directory = self.backup_directory.value.clone();
+        config.format = self.backup_format.value;
+        config.keep_daily = self.keep_daily.value;
+        config.keep_weekly = self.keep_weekly.value;
+        config.interval = self.backup_interval.value;
+        config
+    }
+
+    /// Build the configuration for logging server output.
+    pub fn logs_config(&self) -> logs::Config {
+        let mut config = logs::Config::default();
+        config.directory = self.logs_directory.value.clone();
+        config.max_size = self.logs_max_size.value;
+        config.interval = self.logs_interval.value;
+        config.keep = self.logs_keep.value;
+        config
+    }
+
+    /// The `server.properties` overrides without their sources.
+    pub fn properties(&self) -> BTreeMap<String, String> {
+        self.properties
+            .iter()
+            .map(|(key, value)| (key.clone(), value.value.clone()))
+            .collect()
+    }
+}
+
+/// Write a `key = value  # source` line, quoting the value as TOML.
+fn line(
+    f: &mut fmt::Formatter<'_>,
+    key: &str,
+    value: impl Into<toml::Value>,
+    source: Source,
+) -> fmt::Result {
+    let bare = key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if bare {
+        writeln!(f, "{key} = {}  # {source}", value.into())
+    } else {
+        writeln!(f, "{key:?} = {}  # {source}", value.into())
+    }
+}
+
+/// Print the resolved settings as TOML, annotated with the source of each value.
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[server]")?;
+        line(
+            f,
+            "type",
+            self.server_type.value.to_string(),
+            self.server_type.source,
+        )?;
+        match &self.server_version.value {
+            Some(version) => line(f, "version", version.as_str(), self.server_version.source)?,
+            None => writeln!(f, "# version = <latest release>  # default")?,
+        }
+        line(
+            f,
+            "min-memory",
+            self.min_memory.value.as_str(),
+            self.min_memory.source,
+        )?;
+        line(
+            f,
+            "max-memory",
+            self.max_memory.value.as_str(),
+            self.max_memory.source,
+        )?;
+        line(
+            f,
+            "jvm-args",
+            self.jvm_args.value.clone(),
+            self.jvm_args.source,
+        )?;
+        line(
+            f,
+            "shutdown-timeout",
+            self.shutdown_timeout.value.as_secs() as i64,
+            self.shutdown_timeout.source,
+        )?;
+        line(f, "restart", self.restart.value.name(), self.restart.source)?;
+        match self.drain_timeout.value {
+            Some(timeout) => line(
+                f,
+                "drain-timeout",
+                config::format_duration(timeout),
+                self.drain_timeout.source,
+            )?,
+            None => writeln!(f, "# drain-timeout = <stop immediately>  # default")?,
+        }
+        line(
+            f,
+            "drain-message",
+            self.drain_message.value.as_str(),
+            self.drain_message.source,
+        )?;
+        line(
+            f,
+            "drain-until-empty",
+            self.drain_until_empty.value,
+            self.drain_until_empty.source,
+        )?;
+        match self.idle_timeout.value {
+            Some(timeout) => line(
+                f,
+                "idle-timeout",
+                config::format_duration(timeout),
+                self.idle_timeout.source,
+            )?,
+            None => writeln!(f, "# idle-timeout = <never stop>  # default")?,
+        }
+        line(
+            f,
+            "idle-motd",
+            self.idle_motd.value.as_str(),
+            self.idle_motd.source,
+        )?;
+        line(
+            f,
+            "auto-rollback",
+            self.auto_rollback.value,
+            self.auto_rollback.source,
+        )?;
+        match self.metrics_listen.value {
+            Some(address) => line(
+                f,
+                "metrics-listen",
+                address.to_string(),
+                self.metrics_listen.source,
+            )?,
+            None => writeln!(f, "# metrics-listen = <disabled>  # default")?,
+        }
+        line(
+            f,
+            "profile-api-url",
+            self.profile_api_url.value.as_str(),
+            self.profile_api_url.source,
+        )?;
+
+        writeln!(f, "\n[properties]")?;
+        for (key, value) in &self.properties {
+            line(f, key, value.value.as_str(), value.source)?;
+        }
+
+        writeln!(f, "\n[backup]")?;
+        line(
+            f,
+            "directory",
+            self.backup_directory.value.as_str(),
+            self.backup_directory.source,
+        )?;
+        line(
+            f,
+            "format",
+            self.backup_format.value.name(),
+            self.backup_format.source,
+        )?;
+        line(
+            f,
+            "keep-daily",
+            self.keep_daily.value as i64,
+            self.keep_daily.source,
+        )?;
+        line(
+            f,
+            "keep-weekly",
+            self.keep_weekly.value as i64,
+            self.keep_weekly.source,
+        )?;
+        match self.backup_interval.value {
+            Some(interval) => line(
+                f,
+                "interval",
+                config::format_duration(interval),
+                self.backup_interval.source,
+            )?,
+            None => writeln!(f, "# interval = <only on demand>  # default")?,
+        }
+
+        writeln!(f, "\n[logs]")?;
+        line(
+            f,
+            "directory",
+            self.logs_directory.value.as_str(),
+            self.logs_directory.source,
+        )?;
+        line(
+            f,
+            "max-size",
+            self.logs_max_size.value.to_string(),
+            self.logs_max_size.source,
+        )?;
+        match self.logs_interval.value {
+            Some(interval) => line(
+                f,
+                "interval",
+                config::format_duration(interval),
+                self.logs_interval.source,
+            )?,
+            None => writeln!(f, "# interval = <rotate by size only>  # default")?,
+        }
+        line(
+            f,
+            "keep",
+            self.logs_keep.value as i64,
+            self.logs_keep.source,
+        )?;
+
+        for task in &self.schedule {
+            writeln!(f, "\n[[schedule]]")?;
+            match &task.trigger {
+                Trigger::Daily { at, time_zone } => {
+                    line(f, "at", at.to_string(), Source::File)?;
+                    if let Some(name) = time_zone.iana_name() {
+                        line(f, "time-zone", name, Source::File)?;
+                    }
+                }
+                Trigger::Every(interval) => {
+                    line(f, "every", config::format_duration(*interval), Source::File)?;
+                }
+                trigger => writeln!(f, "# trigger = {trigger:?}")?,
+            }
+            match &task.action {
+                Action::Command(command) => line(f, "command", command.as_str(), Source::File)?,
+                Action::Restart => line(f, "restart", true, Source::File)?,
+                action => writeln!(f, "# action = {action:?}")?,
+            }
+            if !task.warnings.is_empty() {
+                let warnings: Vec<_> = task
+                    .warnings
+                    .iter()
+                    .map(|warning| config::format_duration(*warning))
+                    .collect();
+                line(f, "warnings", warnings, Source::File)?;
+                line(f, "message", task.message.as_str(), Source::File)?;
+            }
+        }
+
+        Ok(())
+    }
+}
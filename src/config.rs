@@ -0,0 +1,334 @@
+use std::{collections::BTreeMap, fmt, io::ErrorKind, net::SocketAddr, time::Duration};
+
+use anyhow::Context;
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use jiff::SignedDuration;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    backup,
+    provider::ServerType,
+    server::{RestartPolicy, Task},
+};
+
+/// Default location of the config file, relative to the workspace.
+pub const CONFIG_PATH: &str = "mc.toml";
+
+/// Contents of an `mc.toml` file.
+///
+/// Every setting is optional. Anything left unset falls back to environment
+/// variables, flags or built-in defaults.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct File {
+    /// Settings for the server process.
+    pub server: ServerSection,
+    /// Overrides applied to `server.properties` before each start.
+    pub properties: BTreeMap<String, Property>,
+    /// Settings for world backups.
+    pub backup: BackupSection,
+    /// Settings for the logs of server output.
+    pub logs: LogsSection,
+    /// Tasks to run while the server is up, as `[[schedule]]` tables.
+    pub schedule: Vec<Task>,
+}
+
+/// The `[server]` table.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ServerSection {
+    /// Where the server comes from, e.g. `paper`.
+    #[serde(rename = "type")]
+    pub server_type: Option<ServerType>,
+    /// Server version, e.g. `1.21.3`.
+    pub version: Option<String>,
+    /// Minimum heap size for the JVM (`-Xms`).
+    pub min_memory: Option<String>,
+    /// Maximum heap size for the JVM (`-Xmx`).
+    pub max_memory: Option<String>,
+    /// Extra arguments passed to the JVM before `-jar`.
+    pub jvm_args: Option<Vec<String>>,
+    /// Seconds to wait for graceful shutdown before killing the server.
+    pub shutdown_timeout: Option<u64>,
+    /// When to restart the server after it exits on its own.
+    pub restart: Option<RestartPolicy>,
+    /// Roll back the jar and world if an upgraded server fails to start.
+    pub auto_rollback: Option<bool>,
+    /// Longest to let players leave before stopping, e.g. `5m`. Enables draining.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub drain_timeout: Option<Duration>,
+    /// Broadcast while draining, where `{remaining}` is the time left.
+    pub drain_message: Option<String>,
+    /// Stop draining as soon as the last player leaves.
+    pub drain_until_empty: Option<bool>,
+    /// Stop the server once nobody has been online this long, e.g. `30m`, and
+    /// start it again when someone tries to join.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub idle_timeout: Option<Duration>,
+    /// MOTD shown in the server list while the server is stopped for being idle.
+    pub idle_motd: Option<String>,
+    /// Address to serve Prometheus metrics on, e.g. `0.0.0.0:9225`.
+    pub metrics_listen: Option<SocketAddr>,
+    /// Where to look up player UUIDs, e.g. a mirror of Mojang's API.
+    pub profile_api_url: Option<String>,
+}
+
+/// The `[backup]` table.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BackupSection {
+    /// Directory backups are written to, relative to the workspace.
+    pub directory: Option<Utf8PathBuf>,
+    /// Whether to write archives or use the deduplicating backup store.
+    pub format: Option<backup::Format>,
+    /// Number of daily backups to keep.
+    pub keep_daily: Option<usize>,
+    /// Number of weekly backups to keep.
+    pub keep_weekly: Option<usize>,
+    /// How often to back up while the server runs, e.g. `6h`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Option<Duration>,
+}
+
+/// The `[logs]` table.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LogsSection {
+    /// Directory logs are written to, relative to the workspace.
+    pub directory: Option<Utf8PathBuf>,
+    /// Size at which the current log is rotated, e.g. `10MiB`.
+    #[serde(deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// How often to rotate the current log regardless of size, e.g. `1d`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Option<Duration>,
+    /// Number of rotated logs to keep.
+    pub keep: Option<usize>,
+}
+
+/// Parse a duration like `90s`, `6h` or `1h 30m` (or ISO 8601, e.g. `PT6H`).
+pub fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    let duration: SignedDuration = value.parse()?;
+    Duration::try_from(duration).with_context(|| format!("Duration must be positive: {value}"))
+}
+
+/// Format a duration the way [`parse_duration`] accepts it, e.g. `6h`.
+pub fn format_duration(duration: Duration) -> String {
+    match SignedDuration::try_from(duration) {
+        Ok(duration) => format!("{duration:#}"),
+        Err(_) => format!("{}s", duration.as_secs()),
+    }
+}
+
+/// Parse a size in bytes like `512KiB`, `10MB` or `1048576`.
+pub fn parse_size(value: &str) -> anyhow::Result<u64> {
+    let size: ByteSize = value
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid size {value}: {err}"))?;
+    Ok(size.as_u64())
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_size(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// A `server.properties` value. TOML scalars are accepted for convenience.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum Property {
+    Boolean(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Property::Boolean(value) => write!(f, "{value}"),
+            Property::Integer(value) => write!(f, "{value}"),
+            Property::String(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl File {
+    /// Read and parse a config file.
+    ///
+    /// Returns `None` if the file doesn't exist.
+    pub async fn load(path: &Utf8Path) -> anyhow::Result<Option<Self>> {
+        let content = match fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let file = toml::from_str(&content).with_context(|| format!("Invalid config in {path}"))?;
+        Ok(Some(file))
+    }
+}
+
+/// Where a resolved setting came from, in increasing order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Default,
+    File,
+    Env,
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            Source::Default => "default",
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Flag => "flag",
+        };
+        f.write_str(source)
+    }
+}
+
+/// A resolved setting along with its [`Source`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sourced<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Sourced<T> {
+    /// Resolve a setting from the highest precedence layer that has a value.
+    pub fn resolve(arg: Option<(T, Source)>, file: Option<T>, default: impl FnOnce() -> T) -> Self {
+        match (arg, file) {
+            (Some((value, source)), _) => Sourced { value, source },
+            (None, Some(value)) => Sourced {
+                value,
+                source: Source::File,
+            },
+            (None, None) => Sourced {
+                value: default(),
+                source: Source::Default,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_file_full() {
+        let file: File = toml::from_str(
+            r#"
+            [server]
+            version = "1.21.3"
+            max-memory = "4G"
+            jvm-args = ["-XX:+UseG1GC"]
+            shutdown-timeout = 60
+            restart = "on-failure"
+            drain-timeout = "5m"
+
+            [properties]
+            motd = "Hello"
+            max-players = 10
+            pvp = false
+
+            [backup]
+            directory = "backups"
+            keep-daily = 7
+            interval = "6h"
+
+            [logs]
+            max-size = "10MiB"
+            keep = 5
+
+            [[schedule]]
+            every = "15m"
+            command = "save-all"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(file.server.version.as_deref(), Some("1.21.3"));
+        assert_eq!(file.server.min_memory, None);
+        assert_eq!(file.server.restart, Some(RestartPolicy::OnFailure));
+        assert_eq!(file.server.drain_timeout, Some(Duration::from_secs(300)));
+        assert_eq!(file.properties["max-players"].to_string(), "10");
+        assert_eq!(file.properties["pvp"].to_string(), "false");
+        assert_eq!(file.backup.keep_daily, Some(7));
+        assert_eq!(file.backup.interval, Some(Duration::from_secs(6 * 60 * 60)));
+        assert_eq!(file.logs.max_size, Some(10 * 1024 * 1024));
+        assert_eq!(file.logs.keep, Some(5));
+        assert_eq!(file.schedule.len(), 1);
+    }
+
+    #[test_case("90s", 90 ; "seconds")]
+    #[test_case("1h 30m", 5400 ; "friendly")]
+    #[test_case("PT6H", 21600 ; "iso 8601")]
+    fn test_parse_duration(value: &str, seconds: u64) {
+        let duration = parse_duration(value).unwrap();
+        assert_eq!(duration, Duration::from_secs(seconds));
+        assert_eq!(
+            parse_duration(&format_duration(duration)).unwrap(),
+            duration
+        );
+    }
+
+    #[test_case("-5m" ; "negative")]
+    #[test_case("soon" ; "invalid")]
+    fn test_parse_duration_invalid(value: &str) {
+        assert!(parse_duration(value).is_err());
+    }
+
+    #[test]
+    fn test_file_empty() {
+        let file: File = toml::from_str("").unwrap();
+        assert_eq!(file, File::default());
+    }
+
+    #[test]
+    fn test_file_unknown_key() {
+        assert!(toml::from_str::<File>("[server]\nmemory = \"1G\"").is_err());
+    }
+
+    #[test]
+    fn test_sourced_precedence() {
+        let resolved = Sourced::resolve(Some((3, Source::Env)), Some(2), || 1);
+        assert_eq!(
+            resolved,
+            Sourced {
+                value: 3,
+                source: Source::Env
+            }
+        );
+        let resolved = Sourced::resolve(None, Some(2), || 1);
+        assert_eq!(
+            resolved,
+            Sourced {
+                value: 2,
+                source: Source::File
+            }
+        );
+        let resolved = Sourced::resolve(None, None, || 1);
+        assert_eq!(
+            resolved,
+            Sourced {
+                value: 1,
+                source: Source::Default
+            }
+        );
+    }
+}
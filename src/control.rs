@@ -0,0 +1,259 @@
+use std::{io::ErrorKind, time::Duration};
+
+use anyhow::{Context, anyhow, bail};
+use camino::Utf8PathBuf;
+use fs_err::tokio as fs;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    backup,
+    rcon::Rcon,
+    server::{self, Console},
+    workspace,
+};
+
+/// Location of the control socket, relative to the workspace.
+pub const SOCKET_PATH: &str = "mc.sock";
+
+/// A request from another `mc` process to the one supervising the server.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Request {
+    /// Back up the world while the server is running.
+    Backup,
+    /// Send a command to the server console.
+    Command { command: String },
+    /// Send a command to the server console and collect its response, the
+    /// first line within `timeout_ms`, or lines until one containing `until`.
+    Exec {
+        command: String,
+        until: Option<String>,
+        timeout_ms: u64,
+    },
+}
+
+/// The response to a [`Request`].
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Response {
+    /// A backup was written to `path`.
+    Backup { path: Utf8PathBuf },
+    /// The command was sent to the server.
+    Sent,
+    /// Server output following an [`Request::Exec`] command.
+    Output { lines: Vec<String> },
+    /// The request failed.
+    Error { message: String },
+}
+
+/// Send a command to the console of the server supervising the current
+/// workspace.
+pub async fn command(command: &str) -> anyhow::Result<()> {
+    tracing::debug!("Sending {command}");
+    let sent = request(&Request::Command {
+        command: command.to_string(),
+    })
+    .await?;
+    match sent {
+        Some(Response::Sent) => Ok(()),
+        Some(response) => bail!("Unexpected response from server: {response:?}"),
+        None => bail!("The server isn't accepting commands"),
+    }
+}
+
+/// Run a command on the server in the current workspace and collect its
+/// response, as for [`Request::Exec`].
+///
+/// The supervising `mc` is asked, unless `rcon` is set or there is none, in
+/// which case the command is sent over RCON.
+pub async fn exec(
+    command: &str,
+    until: Option<&str>,
+    timeout: Duration,
+    rcon: bool,
+) -> anyhow::Result<Vec<String>> {
+    if command.contains(['\n', '\r']) {
+        bail!("Commands can't span lines");
+    }
+    let response = if rcon {
+        None
+    } else {
+        request(&Request::Exec {
+            command: command.to_string(),
+            until: until.map(String::from),
+            timeout_ms: timeout.as_millis().try_into()?,
+        })
+        .await?
+    };
+    match response {
+        Some(Response::Output { lines }) => Ok(lines),
+        Some(response) => bail!("Unexpected response from server: {response:?}"),
+        None => {
+            let (port, password) = workspace::rcon().await?.context(
+                "No server is supervised by mc here, and enable-rcon isn't set in server.properties",
+            )?;
+            let mut rcon = Rcon::connect("localhost", port, &password, timeout).await?;
+            let response = rcon.command(command).await?;
+            let lines: Vec<String> = response.lines().map(String::from).collect();
+            if let Some(pattern) = until
+                && !lines.iter().any(|line| line.contains(pattern))
+            {
+                bail!("Server response didn't include \"{pattern}\"");
+            }
+            Ok(lines)
+        }
+    }
+}
+
+/// Send a request to the server supervising the current workspace.
+///
+/// Returns `None` if no server is listening.
+pub async fn request(request: &Request) -> anyhow::Result<Option<Response>> {
+    let stream = match UnixStream::connect(SOCKET_PATH).await {
+        Ok(stream) => stream,
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::NotFound | ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(err) => return Err(err).context("Failed to connect to control socket"),
+    };
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    match serde_json::from_str(&line).context("Invalid control response")? {
+        Response::Error { message } => Err(anyhow!(message)),
+        response => Ok(Some(response)),
+    }
+}
+
+/// Bind the control socket, replacing any stale one.
+pub(crate) async fn bind() -> anyhow::Result<UnixListener> {
+    unbind().await;
+    UnixListener::bind(SOCKET_PATH).context("Failed to bind control socket")
+}
+
+/// Remove the control socket.
+pub(crate) async fn unbind() {
+    match fs::remove_file(SOCKET_PATH).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => tracing::warn!("Failed to remove control socket: {err}"),
+    }
+}
+
+/// Accept connections on the control socket, handling one request each.
+pub(crate) async fn serve(listener: UnixListener, console: Console, config: server::Config) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                tracing::warn!("Failed to accept control connection: {err}");
+                continue;
+            }
+        };
+        let console = console.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &console, &config).await {
+                tracing::warn!("Control connection failed: {err:#}");
+            }
+        });
+    }
+}
+
+async fn respond(
+    stream: UnixStream,
+    console: &Console,
+    config: &server::Config,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => handle(request, console, config)
+            .await
+            .unwrap_or_else(|err| Response::Error {
+                message: format!("{err:#}"),
+            }),
+        Err(err) => Response::Error {
+            message: format!("Invalid control request: {err}"),
+        },
+    };
+
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle(
+    request: Request,
+    console: &Console,
+    config: &server::Config,
+) -> anyhow::Result<Response> {
+    tracing::debug!("Handling control request: {request:?}");
+    match request {
+        Request::Backup => {
+            let backup = backup::create_online(console, &config.backup).await?;
+            backup::prune(&config.backup).await?;
+            Ok(Response::Backup { path: backup.path })
+        }
+        Request::Command { command } => {
+            console.send(command).await?;
+            Ok(Response::Sent)
+        }
+        Request::Exec {
+            command,
+            until,
+            timeout_ms,
+        } => {
+            let lines = console
+                .exec(command, until.as_deref(), Duration::from_millis(timeout_ms))
+                .await?;
+            Ok(Response::Output { lines })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_wire_format() {
+        let request = serde_json::to_string(&Request::Backup).unwrap();
+        assert_eq!(request, r#"{"type":"backup"}"#);
+
+        let request = serde_json::to_string(&Request::Command {
+            command: "reload".to_string(),
+        })
+        .unwrap();
+        assert_eq!(request, r#"{"type":"command","command":"reload"}"#);
+    }
+
+    #[test]
+    fn test_response_wire_format() {
+        let response: Response =
+            serde_json::from_str(r#"{"type":"error","message":"No world"}"#).unwrap();
+        assert_eq!(
+            response,
+            Response::Error {
+                message: "No world".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    fmt,
+    io::{Cursor, ErrorKind, Read},
+    ops::RangeInclusive,
+};
+
+use anyhow::{Context, anyhow, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use crate::{
+    control, fetch, jar,
+    nbt::{self, Tag},
+    workspace,
+    world::LEVEL_PATH,
+};
+
+/// Where the server loads data packs from, relative to the world directory.
+pub const DATAPACKS_DIRECTORY: &str = "datapacks";
+/// Where disabled data packs are kept, relative to the world directory.
+///
+/// The server doesn't see packs here, so they stay disabled without editing
+/// `level.dat`.
+pub const DISABLED_DIRECTORY: &str = "datapacks-disabled";
+const MCMETA_PATH: &str = "pack.mcmeta";
+
+/// The contents of a data pack's `pack.mcmeta`.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct McMeta {
+    pack: PackMeta,
+}
+
+/// The `pack` section of `pack.mcmeta`, declaring the formats it supports.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct PackMeta {
+    /// The format the pack was made for, replaced by `min_format` and
+    /// `max_format` in 1.21.9.
+    pub pack_format: Option<u32>,
+    /// Other formats the pack also works with, since 1.20.2.
+    pub supported_formats: Option<FormatRange>,
+    pub min_format: Option<Format>,
+    pub max_format: Option<Format>,
+}
+
+/// A pack format, either a major version or `[major, minor]`.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum Format {
+    Major(u32),
+    Full(Vec<u32>),
+}
+
+impl Format {
+    fn major(&self) -> Option<u32> {
+        match self {
+            Format::Major(major) => Some(*major),
+            Format::Full(parts) => parts.first().copied(),
+        }
+    }
+}
+
+/// A range of pack formats, in any of the forms `supported_formats` takes.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum FormatRange {
+    Single(u32),
+    Pair([u32; 2]),
+    Bounds {
+        min_inclusive: u32,
+        max_inclusive: u32,
+    },
+}
+
+impl PackMeta {
+    /// The data pack formats the pack declares support for.
+    pub fn formats(&self) -> anyhow::Result<RangeInclusive<u32>> {
+        if let (Some(min), Some(max)) = (&self.min_format, &self.max_format) {
+            let min = min.major().context("Empty min_format")?;
+            let max = max.major().context("Empty max_format")?;
+            return Ok(min..=max);
+        }
+        match (&self.supported_formats, self.pack_format) {
+            (Some(FormatRange::Single(format)), _) => Ok(*format..=*format),
+            (Some(FormatRange::Pair([min, max])), _) => Ok(*min..=*max),
+            (
+                Some(FormatRange::Bounds {
+                    min_inclusive,
+                    max_inclusive,
+                }),
+                _,
+            ) => Ok(*min_inclusive..=*max_inclusive),
+            (None, Some(format)) => Ok(format..=format),
+            (None, None) => bail!("{MCMETA_PATH} declares no pack_format"),
+        }
+    }
+
+    /// Refuse a pack made for other versions than the server's data pack
+    /// `format`.
+    pub fn check(&self, format: u32) -> anyhow::Result<()> {
+        let formats = self.formats()?;
+        if !formats.contains(&format) {
+            let supported = if formats.start() == formats.end() {
+                formats.start().to_string()
+            } else {
+                format!("{}-{}", formats.start(), formats.end())
+            };
+            bail!("Data pack supports pack format {supported}, but the server uses {format}");
+        }
+        Ok(())
+    }
+}
+
+/// Whether the server loads an installed data pack.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum State {
+    Enabled,
+    Disabled,
+    /// Not yet seen by the server, which enables it on the next load.
+    New,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Enabled => write!(f, "enabled"),
+            State::Disabled => write!(f, "disabled"),
+            State::New => write!(f, "new"),
+        }
+    }
+}
+
+/// A data pack installed in a world.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Installed {
+    /// File or directory name, which the server identifies as `file/<name>`.
+    pub name: String,
+    pub path: Utf8PathBuf,
+    pub state: State,
+}
+
+impl Installed {
+    /// Whether the pack was moved aside by `mc` rather than disabled in-game.
+    pub fn is_moved_aside(&self) -> bool {
+        self.path
+            .parent()
+            .and_then(Utf8Path::file_name)
+            .is_some_and(|parent| parent == DISABLED_DIRECTORY)
+    }
+}
+
+/// The argument identifying a data pack in `/datapack` commands.
+pub(crate) fn command_id(name: &str) -> String {
+    let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"file/{escaped}\"")
+}
+
+/// Read `pack.mcmeta` from a data pack zip or directory.
+pub async fn read_meta(path: &Utf8Path) -> anyhow::Result<PackMeta> {
+    let content = if fs::metadata(path).await?.is_dir() {
+        fs::read_to_string(path.join(MCMETA_PATH)).await?
+    } else {
+        let data = fs::read(path).await?;
+        tokio::task::spawn_blocking(move || read_zip_meta(&data)).await??
+    };
+    parse_meta(&content).with_context(|| format!("Invalid data pack {path}"))
+}
+
+fn read_zip_meta(data: &[u8]) -> anyhow::Result<String> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let mut entry = archive
+        .by_name(MCMETA_PATH)
+        .map_err(|_| anyhow!("Missing {MCMETA_PATH}"))?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn parse_meta(content: &str) -> anyhow::Result<PackMeta> {
+    let meta: McMeta =
+        serde_json::from_str(content).with_context(|| format!("Invalid {MCMETA_PATH}"))?;
+    Ok(meta.pack)
+}
+
+/// The packs enabled and disabled in `level.dat`, empty before the world
+/// is created.
+async fn selection(world: &Utf8Path) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let path = world.join(LEVEL_PATH);
+    let data = match fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err.into()),
+    };
+    tokio::task::spawn_blocking(move || parse_selection(&data))
+        .await?
+        .with_context(|| format!("Unable to read {path}"))
+}
+
+fn parse_selection(data: &[u8]) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let (_, root) = nbt::from_gzip(data)?;
+    let packs = root
+        .get("Data")
+        .ok_or(anyhow!("Missing Data tag"))?
+        .get("DataPacks");
+    let names = |name: &str| match packs.and_then(|packs| packs.get(name)) {
+        Some(Tag::List(tags)) => tags
+            .iter()
+            .filter_map(Tag::as_str)
+            .map(String::from)
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok((names("Enabled"), names("Disabled")))
+}
+
+async fn entries(directory: &Utf8Path) -> anyhow::Result<Vec<(String, Utf8PathBuf)>> {
+    let mut read = match fs::read_dir(directory).await {
+        Ok(read) => read,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = read.next_entry().await? {
+        let path: Utf8PathBuf = entry.path().try_into()?;
+        let Some(name) = path.file_name().map(String::from) else {
+            continue;
+        };
+        // The server only loads zips and directories.
+        if entry.file_type().await?.is_dir() || name.ends_with(".zip") {
+            entries.push((name, path));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// The data packs installed in `world`, enabled or not.
+pub async fn list(world: &Utf8Path) -> anyhow::Result<Vec<Installed>> {
+    let (enabled, disabled) = selection(world).await?;
+    let mut installed = Vec::new();
+    for (name, path) in entries(&world.join(DATAPACKS_DIRECTORY)).await? {
+        let id = format!("file/{name}");
+        let state = if enabled.contains(&id) {
+            State::Enabled
+        } else if disabled.contains(&id) {
+            State::Disabled
+        } else {
+            State::New
+        };
+        installed.push(Installed { name, path, state });
+    }
+    for (name, path) in entries(&world.join(DISABLED_DIRECTORY)).await? {
+        installed.push(Installed {
+            name,
+            path,
+            state: State::Disabled,
+        });
+    }
+    Ok(installed)
+}
+
+/// Find an installed data pack by name, with or without `.zip`.
+pub async fn find(world: &Utf8Path, name: &str) -> anyhow::Result<Installed> {
+    let installed = list(world).await?;
+    let zip = format!("{name}.zip");
+    installed
+        .iter()
+        .find(|pack| pack.name == name)
+        .or_else(|| installed.iter().find(|pack| pack.name == zip))
+        .cloned()
+        .ok_or_else(|| anyhow!("No data pack named {name} in {world}"))
+}
+
+/// Install a data pack zip from a local path or an http(s) URL.
+///
+/// The pack is validated against the server's data pack `format` before it
+/// is moved into place. Returns the pack's name.
+pub(crate) async fn add(
+    world: &Utf8Path,
+    source: &str,
+    format: Option<u32>,
+) -> anyhow::Result<String> {
+    let is_url = source.starts_with("https://") || source.starts_with("http://");
+    let name = if is_url {
+        source
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.rsplit('/').next())
+    } else {
+        Utf8Path::new(source).file_name()
+    }
+    .filter(|name| name.ends_with(".zip"))
+    .with_context(|| format!("{source} isn't a data pack zip"))?
+    .to_string();
+    if let Ok(existing) = find(world, &name).await {
+        bail!("Data pack {} is already installed", existing.name);
+    }
+
+    let directory = world.join(DATAPACKS_DIRECTORY);
+    fs::create_dir_all(&directory).await?;
+    // Download next to the datapacks directory, where a `/reload` meanwhile
+    // can't pick up a partial pack.
+    let temp = world.join(fetch::temp_path(&name));
+    if is_url {
+        tracing::info!("Downloading {source}");
+        fetch::download(&fetch::client()?, source, temp.as_str(), None).await?;
+    } else {
+        fs::copy(source, &temp).await?;
+    }
+
+    let checked = match read_meta(&temp).await {
+        Ok(meta) => match format {
+            Some(format) => meta.check(format),
+            None => Ok(()),
+        },
+        Err(err) => Err(err),
+    };
+    if let Err(err) = checked {
+        fs::remove_file(&temp).await?;
+        return Err(err.context(format!("Unable to install {name}")));
+    }
+    fs::rename(&temp, directory.join(&name)).await?;
+    Ok(name)
+}
+
+/// Uninstall a data pack.
+pub(crate) async fn remove(pack: &Installed) -> anyhow::Result<()> {
+    if fs::metadata(&pack.path).await?.is_dir() {
+        fs::remove_dir_all(&pack.path).await?;
+    } else {
+        fs::remove_file(&pack.path).await?;
+    }
+    Ok(())
+}
+
+/// Move a data pack between the datapacks directory, where the server can
+/// load it, and the disabled directory, where it can't.
+pub(crate) async fn move_pack(
+    world: &Utf8Path,
+    pack: &Installed,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    let directory = world.join(if enabled {
+        DATAPACKS_DIRECTORY
+    } else {
+        DISABLED_DIRECTORY
+    });
+    let destination = directory.join(&pack.name);
+    if destination == pack.path {
+        return Ok(());
+    }
+    fs::create_dir_all(&directory).await?;
+    fs::rename(&pack.path, destination).await?;
+    Ok(())
+}
+
+/// Install a data pack with [`add`], enabling it on the server if it's
+/// running.
+///
+/// Unless `force` is set, the pack has to support the data pack format of
+/// the workspace's `server.jar`. Returns the pack's name.
+pub async fn install(world: &Utf8Path, source: &str, force: bool) -> anyhow::Result<String> {
+    let lock = workspace::lock_unless_running()?;
+    let running = lock.is_none();
+    let format = if force {
+        None
+    } else {
+        let jar = jar::read_version(Utf8Path::new(fetch::SERVER_PATH))
+            .await
+            .context("Unable to check the data pack format, pass --force to skip it")?;
+        Some(jar.pack_version.data())
+    };
+    let name = add(world, source, format).await?;
+    if running {
+        control::command("reload").await?;
+        control::command(&format!("datapack enable {}", command_id(&name))).await?;
+    }
+    Ok(name)
+}
+
+/// Uninstall a data pack by name, disabling it on the server first if it's
+/// running. Returns the pack's full name.
+pub async fn uninstall(world: &Utf8Path, name: &str) -> anyhow::Result<String> {
+    let lock = workspace::lock_unless_running()?;
+    let running = lock.is_none();
+    let pack = find(world, name).await?;
+    if running && pack.state == State::Enabled {
+        control::command(&format!("datapack disable {}", command_id(&pack.name))).await?;
+    }
+    remove(&pack).await?;
+    if running {
+        control::command("reload").await?;
+    }
+    Ok(pack.name)
+}
+
+/// Enable a data pack by name, moving it back into the datapacks directory
+/// and telling the server if it's running. Returns the pack's full name.
+pub async fn enable(world: &Utf8Path, name: &str) -> anyhow::Result<String> {
+    let lock = workspace::lock_unless_running()?;
+    let running = lock.is_none();
+    let pack = find(world, name).await?;
+    if !running && pack.state == State::Disabled && !pack.is_moved_aside() {
+        bail!(
+            "{} was disabled in-game, start the server and enable it again",
+            pack.name
+        );
+    }
+    move_pack(world, &pack, true).await?;
+    if running {
+        // Rescan so the server sees a pack moved back into place.
+        control::command("reload").await?;
+        control::command(&format!("datapack enable {}", command_id(&pack.name))).await?;
+    }
+    Ok(pack.name)
+}
+
+/// Disable a data pack by name, telling the server if it's running and
+/// moving the pack aside. Returns the pack's full name.
+pub async fn disable(world: &Utf8Path, name: &str) -> anyhow::Result<String> {
+    let lock = workspace::lock_unless_running()?;
+    let running = lock.is_none();
+    let pack = find(world, name).await?;
+    if running && pack.state == State::Enabled {
+        control::command(&format!("datapack disable {}", command_id(&pack.name))).await?;
+    }
+    move_pack(world, &pack, false).await?;
+    if running {
+        // Rescan so the server forgets the pack moved aside.
+        control::command("reload").await?;
+    }
+    Ok(pack.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+    use test_case::test_case;
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::nbt::build::{compound, named, strings};
+
+    #[test_case(r#"{"pack": {"pack_format": 48, "description": "x"}}"#, 48..=48; "pack format")]
+    #[test_case(r#"{"pack": {"pack_format": 48, "supported_formats": [48, 61]}}"#, 48..=61; "pair")]
+    #[test_case(r#"{"pack": {"pack_format": 48, "supported_formats": 57}}"#, 57..=57; "single")]
+    #[test_case(
+        r#"{"pack": {"pack_format": 48, "supported_formats": {"min_inclusive": 41, "max_inclusive": 57}}}"#,
+        41..=57;
+        "bounds"
+    )]
+    #[test_case(r#"{"pack": {"min_format": [88, 0], "max_format": 94}}"#, 88..=94; "min and max")]
+    fn test_formats(content: &str, expected: RangeInclusive<u32>) {
+        assert_eq!(parse_meta(content).unwrap().formats().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_check() {
+        let meta =
+            parse_meta(r#"{"pack": {"pack_format": 48, "supported_formats": [48, 61]}}"#).unwrap();
+        assert!(meta.check(57).is_ok());
+        assert!(meta.check(71).is_err());
+        assert!(parse_meta(r#"{"pack": {}}"#).unwrap().check(57).is_err());
+    }
+
+    #[test]
+    fn test_read_zip_meta() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(MCMETA_PATH, SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .write_all(br#"{"pack": {"pack_format": 61}}"#)
+            .unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        let meta = parse_meta(&read_zip_meta(&data).unwrap()).unwrap();
+        assert_eq!(meta.pack_format, Some(61));
+
+        let empty = ZipWriter::new(Cursor::new(Vec::new()))
+            .finish()
+            .unwrap()
+            .into_inner();
+        assert!(read_zip_meta(&empty).is_err());
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let packs = compound(&[
+            named(9, "Enabled", &strings(&["vanilla", "file/a.zip"])),
+            named(9, "Disabled", &strings(&["file/b"])),
+        ]);
+        let data = compound(&[named(10, "DataPacks", &packs)]);
+        let root = named(10, "", &compound(&[named(10, "Data", &data)]));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&root).unwrap();
+
+        let (enabled, disabled) = parse_selection(&encoder.finish().unwrap()).unwrap();
+        assert_eq!(enabled, ["vanilla", "file/a.zip"]);
+        assert_eq!(disabled, ["file/b"]);
+    }
+
+    #[test_case("tweaks.zip", r#""file/tweaks.zip""#)]
+    #[test_case(r#"odd "name""#, r#""file/odd \"name\"""#)]
+    fn test_command_id(name: &str, expected: &str) {
+        assert_eq!(command_id(name), expected);
+    }
+}
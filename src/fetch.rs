@@ -8,74 +8,228 @@ use reqwest::{Client, tls};
 use sha1::{Digest, Sha1};
 use tokio::io::AsyncWriteExt;
 
-use crate::manifest::{Downloads, Type, VERSION_MANIFEST_URL, VersionManifest, VersionMetadata};
+use crate::{
+    jar,
+    lock::{Lock, LockedServer, PreviousServer},
+    manifest::{HashAlgorithm, Type},
+    metrics,
+    provider::{Build, ServerType},
+};
 
-static SERVER_PATH: &str = "server.jar";
+/// Location of the server jar, relative to the workspace.
+pub static SERVER_PATH: &str = "server.jar";
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
-fn sha1_hex(data: &[u8]) -> String {
+/// Where a replaced `server.jar` is kept, so it can be rolled back to.
+pub(crate) fn kept_path(version: &str) -> String {
+    format!("{SERVER_PATH}.{version}")
+}
+
+pub(crate) fn sha1_hex(data: &[u8]) -> String {
     format!("{:x}", Sha1::digest(data))
 }
 
+/// An HTTP client for downloading servers and their metadata.
+pub(crate) fn client() -> reqwest::Result<Client> {
+    // TODO: Should this use a default User-Agent?
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .use_rustls_tls()
+        .min_tls_version(tls::Version::TLS_1_3)
+        .build()
+}
+
+/// A unique temporary name to download `path` to before renaming it.
+pub(crate) fn temp_path(path: &str) -> String {
+    let prefix: u64 = rand::random();
+    format!("{prefix:x}-{path}")
+}
+
+/// Stream `url` into `path`, returning the SHA-1 of what was written.
+///
+/// Given the publisher's checksum, `path` is removed again unless the
+/// download matches it.
+pub(crate) async fn download(
+    client: &Client,
+    url: &str,
+    path: &str,
+    checksum: Option<(HashAlgorithm, &str)>,
+) -> anyhow::Result<String> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .await?;
+    let mut stream = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes_stream();
+
+    let mut hasher = checksum.map(|(algorithm, _)| algorithm.hasher());
+    let mut sha1 = Sha1::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&chunk);
+        }
+        sha1.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    if let (Some((algorithm, expected)), Some(hasher)) = (checksum, hasher) {
+        let computed = hasher.finalize();
+        if computed != expected {
+            tracing::error!(
+                "{algorithm} checksum is invalid (expected: {expected}, actual: {computed})"
+            );
+            fs::remove_file(path).await?;
+            return Err(anyhow!(
+                "Checksum mismatch: expected {expected}, got {computed}"
+            ));
+        }
+        tracing::debug!("{algorithm} checksum is valid");
+    }
+    Ok(format!("{:x}", sha1.finalize()))
+}
+
+/// Which server version to download into the current directory.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Fetch {
+    /// A specific version by id, e.g. `1.21.3`, at its latest stable build.
     Version(String),
+    /// A specific build of a version, for server types with several builds.
+    Build { version: String, build: String },
+    /// The latest version of the given type.
     Latest(Type),
 }
 
 impl Fetch {
-    // TODO: Consider using trace logging for some finer details like versions, SHA1, sizes, URLs, etc.
-    pub async fn execute(&self) -> anyhow::Result<()> {
-        // TODO: Should this use a default User-Agent?
-        let client = Client::builder()
-            .user_agent(USER_AGENT)
-            .use_rustls_tls()
-            .min_tls_version(tls::Version::TLS_1_3)
-            .build()?;
-
-        tracing::debug!("Fetching version manifest");
-        let manifest: VersionManifest = client
-            .get(VERSION_MANIFEST_URL)
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        // TODO: Consider logging whether a version is requested or is latest.
-        let version = match self {
-            Fetch::Version(version) => manifest
-                .version(version)
-                .ok_or(anyhow!("No such version: {version}"))?,
-            Fetch::Latest(r#type) => match r#type {
-                Type::Release => manifest.version(&manifest.latest.release).ok_or(anyhow!(
-                    "Latest release is inexplicably missing from the manifest"
-                ))?,
-                _ => unimplemented!("Other server types not yet implemented"),
+    /// Fetch exactly the locked server again.
+    pub fn pinned(server: &LockedServer) -> Self {
+        match &server.build {
+            Some(build) => Fetch::Build {
+                version: server.version.clone(),
+                build: build.clone(),
             },
+            None => Fetch::Version(server.version.clone()),
+        }
+    }
+
+    /// Whether this asks for exactly the server described by `server`.
+    fn is_pinned_to(&self, server_type: ServerType, server: &LockedServer) -> bool {
+        if server.server_type != server_type {
+            return false;
+        }
+        match self {
+            Fetch::Version(version) => *version == server.version && server.build.is_none(),
+            Fetch::Build { version, build } => {
+                *version == server.version && server.build.as_ref() == Some(build)
+            }
+            Fetch::Latest(_) => false,
+        }
+    }
+
+    /// Resolve the requested version and ensure `server.jar` matches it.
+    ///
+    /// An existing `server.jar` with the expected checksum is left alone.
+    /// Otherwise the server is downloaded to a temporary file, verified and
+    /// renamed into place. A replaced `server.jar` is kept at [`kept_path`],
+    /// and recorded in the lock as the previous server if it was the pinned one.
+    ///
+    /// Given a [`Lock`], the pinned `server.jar` is reused without touching the
+    /// network if it is intact. Checksums of the pinned build must match the
+    /// lock, and the world is never downgraded past its last run version.
+    /// Returns the lock describing the installed server.
+    // TODO: Consider using trace logging for some finer details like versions, SHA1, sizes, URLs, etc.
+    pub async fn execute(
+        &self,
+        server_type: ServerType,
+        lock: Option<&Lock>,
+    ) -> anyhow::Result<Lock> {
+        if let Some(lock) = lock
+            && self.is_pinned_to(server_type, &lock.server)
+        {
+            let version = &lock.server.version;
+            match fs::read(SERVER_PATH).await {
+                Ok(data) if sha1_hex(&data) == lock.server.sha1 => {
+                    tracing::debug!("Found locked {SERVER_PATH} for {version}, skipping fetch");
+                    metrics::record_download(true);
+                    return Ok(lock.clone());
+                }
+                Ok(_) => tracing::debug!("Existing {SERVER_PATH} doesn't match lock"),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    tracing::debug!("Existing {SERVER_PATH} not found");
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let client = client()?;
+
+        let Build {
+            version,
+            build,
+            metadata_sha1,
+            download: server,
+        } = server_type.resolve(&client, self, lock).await?;
+        let name = match &build {
+            Some(build) => format!("{server_type} {version} build {build}"),
+            None => format!("{server_type} {version}"),
         };
 
-        tracing::debug!("Fetching version {} metadata", version.id);
-        let version_metadata: VersionMetadata =
-            client.get(version.url).send().await?.json().await?;
+        let locked = lock.filter(|lock| {
+            lock.server.server_type == server_type
+                && lock.server.version == version
+                && lock.server.build == build
+        });
+        let mut installed = Lock {
+            server: LockedServer {
+                server_type,
+                version: version.clone(),
+                build,
+                // Filled in once the jar is in hand.
+                sha1: String::new(),
+                metadata_sha1,
+                last_run: lock.and_then(|lock| lock.server.last_run.clone()),
+            },
+            // Kept for the loader to check against the new version.
+            loader: lock
+                .filter(|lock| lock.server.server_type == server_type)
+                .and_then(|lock| lock.loader.clone()),
+            previous: lock.and_then(|lock| lock.previous.clone()),
+        };
 
-        let VersionMetadata {
-            downloads: Downloads { server },
-        } = version_metadata;
+        let mut replaced = None;
 
         match fs::read(SERVER_PATH).await {
             Ok(data) => {
                 tracing::debug!("Found existing {SERVER_PATH}, verifying checksum");
-                let actual = sha1_hex(&data);
-                if actual == server.sha1 {
+                let actual = server.algorithm.hex(&data);
+                if actual == server.hash {
                     tracing::debug!("Checksum matches, skipping download");
-                    return Ok(());
+                    metrics::record_download(true);
+                    installed.server.sha1 = sha1_hex(&data);
+                    check_locked(locked, &installed.server, &name)?;
+                    return Ok(installed);
                 }
                 tracing::debug!(
                     "Checksum mismatch (expected: {}, actual: {})",
-                    server.sha1,
+                    server.hash,
                     actual
                 );
+                replaced = replaced_version(lock, &data);
+                if let Some(existing) = &replaced {
+                    tracing::info!("Found {SERVER_PATH} for {existing}, replacing with {name}");
+                }
+                if let Some(previous) = previous_server(lock, &version, &data) {
+                    installed.previous = Some(previous);
+                }
             }
             Err(err) if err.kind() == ErrorKind::NotFound => {
                 tracing::debug!("Existing {SERVER_PATH} not found");
@@ -84,45 +238,85 @@ impl Fetch {
         }
 
         // Download to temporary file first, then move on success
-        let prefix: u64 = rand::random();
-        let temp_path = format!("{prefix:x}-{SERVER_PATH}");
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&temp_path)
-            .await?;
-
-        tracing::debug!("Fetching server version {}", version.id);
-        let mut stream = client.get(server.url).send().await?.bytes_stream();
-
-        tracing::debug!("Writing {} to {temp_path}", ByteSize(server.size));
-        let mut hasher = Sha1::new();
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            hasher.update(&chunk);
-            file.write_all(&chunk).await?;
+        let temp_path = temp_path(SERVER_PATH);
+        tracing::debug!("Fetching {name}");
+        match server.size {
+            Some(size) => tracing::debug!("Writing {} to {temp_path}", ByteSize(size)),
+            None => tracing::debug!("Writing to {temp_path}"),
         }
-
-        let computed = format!("{:x}", hasher.finalize());
-        if computed != server.sha1 {
-            tracing::error!(
-                "SHA-1 checksum is invalid (expected: {}, actual: {})",
-                server.sha1,
-                computed
-            );
+        installed.server.sha1 = download(
+            &client,
+            &server.url,
+            &temp_path,
+            Some((server.algorithm, &server.hash)),
+        )
+        .await?;
+        if let Err(err) = check_locked(locked, &installed.server, &name) {
             fs::remove_file(&temp_path).await?;
-            return Err(anyhow!(
-                "Checksum mismatch: expected {}, got {}",
-                server.sha1,
-                computed
-            ));
+            return Err(err);
         }
 
-        tracing::debug!("SHA-1 checksum is valid");
+        if let Some(replaced) = replaced {
+            let kept = kept_path(&replaced);
+            tracing::info!("Keeping previous {SERVER_PATH} as {kept}");
+            fs::rename(SERVER_PATH, kept).await?;
+        }
         tracing::debug!("Renaming {temp_path} to {SERVER_PATH}");
         fs::rename(&temp_path, SERVER_PATH).await?;
+        metrics::record_download(false);
+
+        Ok(installed)
+    }
+}
+
+/// The version to keep a replaced `server.jar` as.
+///
+/// The locked jar is kept as the locked version, which is where
+/// [`rollback`](crate::upgrade::rollback) looks for it. Any other jar is named
+/// after the version it declares, if it declares one.
+pub(crate) fn replaced_version(lock: Option<&Lock>, data: &[u8]) -> Option<String> {
+    if let Some(lock) = lock
+        && lock.server.sha1 == sha1_hex(data)
+    {
+        return Some(lock.server.version.clone());
+    }
+    match jar::parse_version(data) {
+        Ok(existing) => Some(existing.id),
+        Err(err) => {
+            tracing::debug!("Unable to read {SERVER_PATH} version: {err:#}");
+            None
+        }
+    }
+}
+
+/// The locked server to record for rolling back to, if `data` is its jar
+/// being replaced by another `version`.
+pub(crate) fn previous_server(
+    lock: Option<&Lock>,
+    version: &str,
+    data: &[u8],
+) -> Option<PreviousServer> {
+    let lock =
+        lock.filter(|lock| lock.server.version != version && lock.server.sha1 == sha1_hex(data))?;
+    Some(PreviousServer {
+        server_type: lock.server.server_type,
+        version: lock.server.version.clone(),
+        build: lock.server.build.clone(),
+        sha1: lock.server.sha1.clone(),
+        metadata_sha1: lock.server.metadata_sha1.clone(),
+        loader: lock.loader.clone(),
+        backup: None,
+    })
+}
 
-        Ok(())
+/// Refuse a jar that differs from the one locked for the same build.
+fn check_locked(locked: Option<&Lock>, server: &LockedServer, name: &str) -> anyhow::Result<()> {
+    match locked {
+        Some(lock) if lock.server.sha1 != server.sha1 => Err(anyhow!(
+            "{SERVER_PATH} for {name} changed since it was locked (expected: {}, actual: {})",
+            lock.server.sha1,
+            server.sha1
+        )),
+        _ => Ok(()),
     }
 }
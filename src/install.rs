@@ -0,0 +1,197 @@
+use anyhow::{Context, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    backup,
+    fetch::{Fetch, SERVER_PATH},
+    jar,
+    loader::{self, Want},
+    lock::Lock,
+    manifest::Type,
+    modpack::{self, Index},
+    modrinth::{MODRINTH_API_URL, Modrinth},
+    mods::{self, ModsLock, Target},
+    provider::ServerType,
+    upgrade, workspace, world,
+};
+
+/// A server installed by [`prepare`], ready to run.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Prepared {
+    /// The saved lock, with the installed version as the last to run the world.
+    pub lock: Lock,
+    /// Whether the installed version is about to run the world for the first
+    /// time after another version did.
+    pub upgrade: bool,
+}
+
+/// A modpack installed by [`modpack`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct InstalledPack {
+    pub index: Index,
+    /// The saved lock, with the pack's Minecraft version and loader.
+    pub lock: Lock,
+    /// The saved mods lock, with the pack's files.
+    pub mods: ModsLock,
+    /// Paths of the override files written, relative to the workspace.
+    pub overrides: Vec<Utf8PathBuf>,
+}
+
+/// Install the server to run in the current workspace, with its loader and
+/// mods.
+///
+/// The locked server is kept, build and all, unless another `server_type` or
+/// `version` is asked for. Without a lock or `version`, the latest release is
+/// installed. A server older than the world is refused, and the world is
+/// backed up before a new version first runs it.
+///
+/// The caller holds the workspace lock, and keeps it while the server runs.
+pub async fn prepare(
+    server_type: Option<ServerType>,
+    version: Option<&str>,
+    backup: &backup::Config,
+) -> anyhow::Result<Prepared> {
+    let lock = Lock::load().await?;
+    let server_type = server_type
+        .or(lock.as_ref().map(|lock| lock.server.server_type))
+        .unwrap_or_default();
+    let fetch = match (version, &lock) {
+        (version, Some(lock))
+            if lock.server.server_type == server_type
+                && version.is_none_or(|version| version == lock.server.version) =>
+        {
+            Fetch::pinned(&lock.server)
+        }
+        (Some(version), _) => Fetch::Version(version.to_string()),
+        (None, Some(lock)) => Fetch::Version(lock.server.version.clone()),
+        (None, None) => Fetch::Latest(Type::Release),
+    };
+
+    let mut lock = fetch.execute(server_type, lock.as_ref()).await?;
+    lock.loader = loader::install(&lock.server, lock.loader.as_ref(), Want::Locked).await?;
+    sync_mods(&lock).await?;
+    check_world().await?;
+    let upgrade = upgrade::is_upgrade(&lock);
+    if let Some(backup) = upgrade::backup(&mut lock, backup).await? {
+        tracing::info!(
+            "Backed up world to {} before upgrading to {}",
+            backup.path,
+            lock.server.version
+        );
+    }
+    lock.server.last_run = Some(lock.server.version.clone());
+    lock.save().await?;
+    Ok(Prepared { lock, upgrade })
+}
+
+/// Install the latest release, or `version`, with the latest loader for it,
+/// replacing the locked server.
+///
+/// Returns the lock from before, if there was one, and the saved new one.
+pub async fn update(
+    server_type: Option<ServerType>,
+    version: Option<&str>,
+) -> anyhow::Result<(Option<Lock>, Lock)> {
+    let _lock = workspace::lock()?;
+    let lock = Lock::load().await?;
+    let server_type = server_type
+        .or(lock.as_ref().map(|lock| lock.server.server_type))
+        .unwrap_or_default();
+    let fetch = match version {
+        Some(version) => Fetch::Version(version.to_string()),
+        None => Fetch::Latest(Type::Release),
+    };
+
+    let mut updated = fetch.execute(server_type, lock.as_ref()).await?;
+    updated.loader =
+        loader::install(&updated.server, updated.loader.as_ref(), Want::Latest).await?;
+    updated.save().await?;
+    Ok((lock, updated))
+}
+
+/// Install the `.mrpack` at `path` with the server, loader and mods it is for,
+/// replacing the locked ones.
+///
+/// A `server_type` other than the pack's is refused.
+pub async fn modpack(
+    path: &Utf8Path,
+    server_type: Option<ServerType>,
+) -> anyhow::Result<InstalledPack> {
+    let pack = modpack::read(path).await?;
+    let (pack_type, minecraft_version, loader_version) = pack.index.server()?;
+    if let Some(server_type) = server_type
+        && server_type != pack_type
+    {
+        bail!(
+            "{} is for {pack_type} servers, but the server type is configured as {server_type}",
+            pack.index.name
+        );
+    }
+
+    let _lock = workspace::lock().context("Stop the server before installing a modpack")?;
+    let current = Lock::load().await?;
+    let fetch = match &current {
+        Some(lock)
+            if lock.server.server_type == pack_type && lock.server.version == minecraft_version =>
+        {
+            Fetch::pinned(&lock.server)
+        }
+        _ => Fetch::Version(minecraft_version),
+    };
+    let mut lock = fetch.execute(pack_type, current.as_ref()).await?;
+    let want = match &loader_version {
+        Some(version) => Want::Version(version),
+        None => Want::Latest,
+    };
+    lock.loader = loader::install(&lock.server, lock.loader.as_ref(), want).await?;
+
+    let target = Target::new(&lock.server);
+    let mods = pack.mods_lock(&target)?;
+    let modrinth = Modrinth::new(MODRINTH_API_URL)?;
+    mods::sync(&modrinth, &target, ModsLock::load().await?.as_ref(), &mods).await?;
+    let overrides = pack
+        .apply_overrides()
+        .await?
+        .into_iter()
+        .map(Utf8Path::to_path_buf)
+        .collect();
+    lock.save().await?;
+    mods.save().await?;
+
+    Ok(InstalledPack {
+        index: pack.index,
+        lock,
+        mods,
+        overrides,
+    })
+}
+
+/// Download any locked mods that are missing, e.g. in a fresh checkout.
+async fn sync_mods(lock: &Lock) -> anyhow::Result<()> {
+    let Some(installed) = ModsLock::load().await? else {
+        return Ok(());
+    };
+    let target = Target::new(&lock.server);
+    if installed.is_stale(&target) {
+        tracing::warn!(
+            "Mods were resolved for {} {}, run `mc mods update`",
+            installed.server_type,
+            installed.minecraft_version
+        );
+    }
+    let modrinth = Modrinth::new(MODRINTH_API_URL)?;
+    mods::sync(&modrinth, &target, None, &installed).await
+}
+
+/// Refuse to start a server older than the world it would load.
+async fn check_world() -> anyhow::Result<()> {
+    let world = workspace::world_directory().await?;
+    if !fs_err::tokio::try_exists(world.join(world::LEVEL_PATH)).await? {
+        return Ok(());
+    }
+    let level = world::read_level(&world).await?;
+    let installed = jar::read_version(SERVER_PATH.into()).await?;
+    world::check_compatible(&level, &installed)
+}
@@ -0,0 +1,137 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{Context, anyhow};
+use camino::Utf8Path;
+use fs_err::tokio as fs;
+use serde::Deserialize;
+use zip::ZipArchive;
+
+const VERSION_PATH: &str = "version.json";
+
+/// Data and resource pack formats supported by a server.
+///
+/// Older servers publish a single number for both, newer ones split them into
+/// major and minor versions.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(untagged)]
+pub enum PackVersion {
+    Split {
+        #[serde(alias = "resource_major")]
+        resource: u32,
+        #[serde(alias = "data_major")]
+        data: u32,
+    },
+    Single(u32),
+}
+
+impl PackVersion {
+    /// The data pack format.
+    pub fn data(self) -> u32 {
+        match self {
+            PackVersion::Split { data, .. } => data,
+            PackVersion::Single(version) => version,
+        }
+    }
+
+    /// The resource pack format.
+    pub fn resource(self) -> u32 {
+        match self {
+            PackVersion::Split { resource, .. } => resource,
+            PackVersion::Single(version) => version,
+        }
+    }
+}
+
+/// The contents of `version.json` inside a `server.jar`.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub struct JarVersion {
+    /// Version id, e.g. `1.21.3`.
+    pub id: String,
+    /// Human readable version name, usually the same as the id.
+    pub name: String,
+    /// Data version of worlds written by this server.
+    pub world_version: u32,
+    /// Network protocol version.
+    pub protocol_version: u32,
+    /// Supported data and resource pack formats.
+    pub pack_version: PackVersion,
+    /// Major Java version required to run the server.
+    pub java_version: Option<u32>,
+    /// Whether this is a stable release.
+    pub stable: bool,
+}
+
+/// Read the version information embedded in a server jar.
+pub async fn read_version(path: &Utf8Path) -> anyhow::Result<JarVersion> {
+    let data = fs::read(path).await?;
+    tokio::task::spawn_blocking(move || parse_version(&data))
+        .await?
+        .with_context(|| format!("Unable to read version from {path}"))
+}
+
+/// Parse the version information from an in-memory server jar.
+pub(crate) fn parse_version(data: &[u8]) -> anyhow::Result<JarVersion> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let mut entry = archive
+        .by_name(VERSION_PATH)
+        .map_err(|_| anyhow!("Missing {VERSION_PATH}"))?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid {VERSION_PATH}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use test_case::test_case;
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    fn jar(version: &str) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(VERSION_PATH, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(version.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test_case(r#"{"resource": 42, "data": 57}"#, 42, 57 ; "split")]
+    #[test_case(r#"{"resource_major": 69, "resource_minor": 0, "data_major": 88, "data_minor": 0}"#, 69, 88 ; "major and minor")]
+    #[test_case("15", 15, 15 ; "single")]
+    fn test_parse_version(pack_version: &str, resource: u32, data: u32) {
+        let version = format!(
+            r#"{{
+                "id": "1.21.3",
+                "name": "1.21.3",
+                "world_version": 4082,
+                "series_id": "main",
+                "protocol_version": 768,
+                "pack_version": {pack_version},
+                "build_time": "2024-10-23T12:28:15+00:00",
+                "java_component": "java-runtime-delta",
+                "java_version": 21,
+                "stable": true,
+                "use_editor": false
+            }}"#
+        );
+        let version = parse_version(&jar(&version)).unwrap();
+        assert_eq!(version.id, "1.21.3");
+        assert_eq!(version.world_version, 4082);
+        assert_eq!(version.protocol_version, 768);
+        assert_eq!(version.pack_version.resource(), resource);
+        assert_eq!(version.pack_version.data(), data);
+    }
+
+    #[test]
+    fn test_parse_version_missing() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("other.json", SimpleFileOptions::default())
+            .unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert!(parse_version(&data).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+//! Minecraft server management toolkit.
+//!
+//! The `mc` binary is a thin client of this library. Embedders can use the
+//! same building blocks directly:
+//!
+//! - [`manifest`] describes Mojang's version manifest and version metadata.
+//! - [`fetch`] resolves a version and downloads a verified `server.jar`.
+//! - [`provider`] resolves versions for vanilla, Paper, Folia and Purpur servers.
+//! - [`loader`] installs the Fabric, Quilt, Forge and NeoForge mod loaders.
+//! - [`modrinth`] is a client for the Modrinth API.
+//! - [`mods`] installs mods and plugins from Modrinth, pinned in `mods.lock`.
+//! - [`modpack`] installs and exports Modrinth `.mrpack` modpacks.
+//! - [`datapack`] installs and toggles the world's data packs.
+//! - [`config`] reads the declarative `mc.toml` server definition.
+//! - [`jar`] reads the version information embedded in a `server.jar`.
+//! - [`lock`] pins the resolved server version in `mc.lock`.
+//! - [`workspace`] prepares a server directory (creation, EULA, etc.).
+//! - [`install`] installs the server to run, with its loader and mods.
+//! - [`world`] reads world metadata from `level.dat`.
+//! - [`access`] edits the server's ops, whitelist and ban lists, live or on disk.
+//! - [`profile`] resolves player names to UUIDs, online or offline.
+//! - [`server`] supervises a running server process.
+//! - [`protocol`] speaks enough of the network protocol for server list pings.
+//! - [`ping`] checks a server is up with a Server List Ping.
+//! - [`query`] lists every player online with the UDP Query protocol.
+//! - [`rcon`] runs commands on a server with `enable-rcon=true`.
+//! - [`logs`] keeps rotated logs of server output and a record of each run.
+//! - [`control`] lets other processes make requests of a supervised server.
+//! - [`backup`] archives the world, coordinating with a running server.
+//! - [`upgrade`] backs up the world before a version upgrade and rolls back.
+
+pub mod access;
+pub mod backup;
+pub mod config;
+pub mod control;
+pub mod datapack;
+pub mod fetch;
+pub mod install;
+pub mod jar;
+pub mod loader;
+pub mod lock;
+pub mod logs;
+pub mod manifest;
+pub(crate) mod metrics;
+pub mod modpack;
+pub mod modrinth;
+pub mod mods;
+pub(crate) mod nbt;
+pub mod ping;
+pub mod profile;
+pub mod protocol;
+pub mod provider;
+pub mod query;
+pub mod rcon;
+pub mod server;
+pub mod upgrade;
+pub mod workspace;
+pub mod world;
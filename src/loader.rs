@@ -0,0 +1,632 @@
+use std::{collections::BTreeMap, fmt, io::ErrorKind};
+
+use anyhow::{Context, anyhow, bail};
+use camino::Utf8PathBuf;
+use fs_err::tokio as fs;
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::{
+    fetch,
+    lock::{Lock, LockedLoader, LockedServer},
+    manifest::HashAlgorithm,
+    provider::{ServerType, compare_releases, parse_release},
+    server::Launch,
+};
+
+/// Location of the Fabric meta API.
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2";
+/// Location of the Quilt meta API.
+const QUILT_META_URL: &str = "https://meta.quiltmc.org/v3";
+/// Maven repository Quilt publishes its installer to.
+const QUILT_MAVEN_URL: &str = "https://maven.quiltmc.org/repository/release";
+/// Forge's artifacts in its Maven repository.
+const FORGE_MAVEN_URL: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge";
+/// Forge's recommended and latest version for each Minecraft version.
+const FORGE_PROMOTIONS_URL: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+/// NeoForge's artifacts in its Maven repository.
+const NEOFORGE_MAVEN_URL: &str = "https://maven.neoforged.net/releases/net/neoforged/neoforge";
+
+/// A mod loader that launches Mojang's server with mods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Loader {
+    Fabric,
+    Quilt,
+    Forge,
+    NeoForge,
+}
+
+impl Loader {
+    /// The loader a server type runs through, if any.
+    pub fn for_server(server_type: ServerType) -> Option<Self> {
+        match server_type {
+            ServerType::Fabric => Some(Loader::Fabric),
+            ServerType::Quilt => Some(Loader::Quilt),
+            ServerType::Forge => Some(Loader::Forge),
+            ServerType::NeoForge => Some(Loader::NeoForge),
+            _ => None,
+        }
+    }
+
+    /// What `java` runs to start a server with this loader version.
+    pub fn launch(self, version: &str) -> Launch {
+        match self {
+            Loader::Fabric | Loader::Quilt => Launch::Jar(self.launch_path(version)),
+            Loader::Forge | Loader::NeoForge => Launch::ArgFile(self.launch_path(version)),
+        }
+    }
+
+    /// Location of the launcher jar or argument file, relative to the workspace.
+    pub fn launch_path(self, version: &str) -> Utf8PathBuf {
+        match self {
+            Loader::Fabric => "fabric-server-launch.jar".into(),
+            Loader::Quilt => "quilt-server-launch.jar".into(),
+            Loader::Forge => {
+                format!("libraries/net/minecraftforge/forge/{version}/unix_args.txt").into()
+            }
+            Loader::NeoForge => {
+                format!("libraries/net/neoforged/neoforge/{version}/unix_args.txt").into()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Loader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loader::Fabric => write!(f, "Fabric"),
+            Loader::Quilt => write!(f, "Quilt"),
+            Loader::Forge => write!(f, "Forge"),
+            Loader::NeoForge => write!(f, "NeoForge"),
+        }
+    }
+}
+
+/// A loader or installer version, as listed by the meta APIs.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct MetaVersion {
+    version: String,
+    /// Only listed by Fabric.
+    #[serde(default)]
+    stable: Option<bool>,
+}
+
+impl MetaVersion {
+    fn is_stable(&self) -> bool {
+        // Quilt marks unstable versions with a pre-release suffix instead.
+        self.stable.unwrap_or_else(|| !self.version.contains('-'))
+    }
+}
+
+/// A loader version compatible with a Minecraft version.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct LoaderEntry {
+    loader: MetaVersion,
+}
+
+/// The first stable version in a list ordered newest first.
+fn latest_stable<'a>(versions: impl IntoIterator<Item = &'a MetaVersion>) -> Option<&'a str> {
+    versions
+        .into_iter()
+        .find(|version| version.is_stable())
+        .map(|version| version.version.as_str())
+}
+
+/// Forge's `promotions_slim.json`.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Promotions {
+    /// Versions by Minecraft version and kind, e.g. `1.21.4-recommended`.
+    promos: BTreeMap<String, String>,
+}
+
+impl Promotions {
+    /// The recommended Forge version for a Minecraft version, or the latest if
+    /// none is recommended yet, e.g. `1.21.4-54.1.0`.
+    fn promoted(&self, minecraft_version: &str) -> Option<String> {
+        ["recommended", "latest"]
+            .iter()
+            .find_map(|kind| self.promos.get(&format!("{minecraft_version}-{kind}")))
+            .map(|version| maven_version(Loader::Forge, minecraft_version, version))
+    }
+}
+
+/// The versions listed in a Maven `maven-metadata.xml`.
+fn parse_maven_versions(xml: &str) -> Vec<&str> {
+    let Some((_, versions)) = xml.split_once("<versions>") else {
+        return Vec::new();
+    };
+    let (versions, _) = versions.split_once("</versions>").unwrap_or((versions, ""));
+    versions
+        .split("<version>")
+        .filter_map(|version| version.split_once("</version>"))
+        .map(|(version, _)| version.trim())
+        .collect()
+}
+
+/// The newest Forge or NeoForge version built for a Minecraft version, skipping
+/// NeoForge betas.
+///
+/// Forge versions are prefixed with the Minecraft version, e.g. `1.21.4-54.0.26`.
+/// NeoForge drops the leading `1.`, e.g. `21.4.136` for 1.21.4 and `21.0.167`
+/// for 1.21, and marks unstable versions with a `-beta` suffix.
+fn latest_for_minecraft<'a>(
+    loader: Loader,
+    versions: &[&'a str],
+    minecraft_version: &str,
+) -> Option<&'a str> {
+    let prefix = match loader {
+        Loader::NeoForge => {
+            let release = parse_release(minecraft_version)?;
+            let [1, minor, rest @ ..] = &release[..] else {
+                return None;
+            };
+            format!("{minor}.{}.", rest.first().copied().unwrap_or(0))
+        }
+        _ => format!("{minecraft_version}-"),
+    };
+    versions
+        .iter()
+        .copied()
+        .filter(|version| {
+            version
+                .strip_prefix(&prefix)
+                .is_some_and(|rest| parse_release(rest).is_some())
+        })
+        .max_by(|a, b| {
+            compare_releases(&a[prefix.len()..], &b[prefix.len()..])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
+
+async fn get<T: serde::de::DeserializeOwned>(client: &Client, url: String) -> anyhow::Result<T> {
+    let response = client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        bail!("Not found: {url}");
+    }
+    Ok(response.error_for_status()?.json().await?)
+}
+
+async fn get_text(client: &Client, url: String) -> anyhow::Result<String> {
+    let response = client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        bail!("Not found: {url}");
+    }
+    Ok(response.error_for_status()?.text().await?)
+}
+
+/// Resolve the loader and installer for a Minecraft version.
+///
+/// Without a requested loader `version`, the latest stable one is used, or
+/// Forge's recommended one. The installer is always the latest stable one,
+/// except for Forge and NeoForge whose installers share the loader's version.
+async fn resolve(
+    client: &Client,
+    loader: Loader,
+    minecraft_version: &str,
+    version: Option<&str>,
+) -> anyhow::Result<(String, String)> {
+    let meta = match loader {
+        Loader::Fabric => FABRIC_META_URL,
+        Loader::Quilt => QUILT_META_URL,
+        Loader::Forge | Loader::NeoForge => {
+            let maven = maven_url(loader);
+            tracing::debug!("Fetching {loader} versions");
+            let metadata = get_text(client, format!("{maven}/maven-metadata.xml")).await?;
+            let versions = parse_maven_versions(&metadata);
+            let version = match version {
+                Some(version) => {
+                    let version = maven_version(loader, minecraft_version, version);
+                    if !versions.contains(&version.as_str()) {
+                        bail!("{loader} {version} doesn't exist");
+                    }
+                    version
+                }
+                None if loader == Loader::Forge => {
+                    tracing::debug!("Fetching {loader} promotions");
+                    let promotions: Promotions =
+                        get(client, FORGE_PROMOTIONS_URL.to_string()).await?;
+                    // Versions too old or new to be promoted get the newest.
+                    promotions
+                        .promoted(minecraft_version)
+                        .or_else(|| {
+                            latest_for_minecraft(loader, &versions, minecraft_version)
+                                .map(String::from)
+                        })
+                        .ok_or_else(|| anyhow!("{loader} has no version for {minecraft_version}"))?
+                }
+                None => latest_for_minecraft(loader, &versions, minecraft_version)
+                    .ok_or_else(|| {
+                        anyhow!("{loader} has no stable version for {minecraft_version}")
+                    })?
+                    .to_string(),
+            };
+            return Ok((version.clone(), version));
+        }
+    };
+    tracing::debug!("Fetching {loader} loader versions for {minecraft_version}");
+    let loaders: Vec<LoaderEntry> = get(
+        client,
+        format!("{meta}/versions/loader/{minecraft_version}"),
+    )
+    .await?;
+    let version = match version {
+        Some(version) if loaders.iter().any(|entry| entry.loader.version == version) => version,
+        Some(version) => bail!("{loader} {version} doesn't support {minecraft_version}"),
+        None => latest_stable(loaders.iter().map(|entry| &entry.loader))
+            .ok_or_else(|| anyhow!("{loader} has no stable loader for {minecraft_version}"))?,
+    };
+
+    tracing::debug!("Fetching {loader} installer versions");
+    let installers: Vec<MetaVersion> = get(client, format!("{meta}/versions/installer")).await?;
+    let installer =
+        latest_stable(&installers).ok_or_else(|| anyhow!("{loader} has no stable installer"))?;
+
+    Ok((version.to_string(), installer.to_string()))
+}
+
+/// The Maven version of a Forge release, which modpacks list without the
+/// Minecraft version prefix, e.g. `54.0.26` for `1.21.4-54.0.26`.
+pub(crate) fn maven_version(loader: Loader, minecraft_version: &str, version: &str) -> String {
+    let prefix = format!("{minecraft_version}-");
+    match loader {
+        Loader::Forge if !version.starts_with(&prefix) => format!("{prefix}{version}"),
+        _ => version.to_string(),
+    }
+}
+
+fn maven_url(loader: Loader) -> &'static str {
+    match loader {
+        Loader::NeoForge => NEOFORGE_MAVEN_URL,
+        _ => FORGE_MAVEN_URL,
+    }
+}
+
+/// What `java` runs for the locked server.
+pub fn launch(lock: &Lock) -> Launch {
+    match (Loader::for_server(lock.server.server_type), &lock.loader) {
+        (Some(loader), Some(locked)) => loader.launch(&locked.version),
+        _ => Launch::default(),
+    }
+}
+
+/// Which loader version [`install`] should install.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Want<'a> {
+    /// The locked version, or the latest stable one if there's none.
+    Locked,
+    /// The latest stable version, or Forge's recommended one.
+    Latest,
+    /// A specific loader version, e.g. one pinned by a modpack.
+    Version(&'a str),
+}
+
+/// Ensure the loader for `server` is installed, returning the loader to lock.
+///
+/// The `locked` loader and installer versions only count if they were
+/// installed for the same Minecraft version. An intact launcher for the chosen
+/// versions is left alone. Server types without a loader return `None`.
+pub async fn install(
+    server: &LockedServer,
+    locked: Option<&LockedLoader>,
+    want: Want<'_>,
+) -> anyhow::Result<Option<LockedLoader>> {
+    let Some(loader) = Loader::for_server(server.server_type) else {
+        return Ok(None);
+    };
+    let minecraft_version = &server.version;
+    let locked = locked.filter(|locked| locked.minecraft_version == *minecraft_version);
+
+    let client = fetch::client()?;
+    let (version, installer) = match (want, locked) {
+        (Want::Locked, Some(locked)) => (locked.version.clone(), locked.installer.clone()),
+        (Want::Version(version), Some(locked))
+            if locked.version == maven_version(loader, minecraft_version, version) =>
+        {
+            (locked.version.clone(), locked.installer.clone())
+        }
+        (Want::Version(version), _) => {
+            resolve(&client, loader, minecraft_version, Some(version)).await?
+        }
+        _ => resolve(&client, loader, minecraft_version, None).await?,
+    };
+    let launcher = loader.launch_path(&version);
+
+    if let Some(locked) = locked
+        && locked.version == version
+        && locked.installer == installer
+    {
+        match fs::read(&launcher).await {
+            Ok(data) if fetch::sha1_hex(&data) == locked.sha1 => {
+                tracing::debug!("Found locked {launcher} for {loader} {version}, skipping install");
+                return Ok(Some(locked.clone()));
+            }
+            Ok(_) => tracing::debug!("Existing {launcher} doesn't match lock"),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                tracing::debug!("Existing {launcher} not found");
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    tracing::info!("Installing {loader} {version} for {minecraft_version}");
+    match loader {
+        Loader::Fabric => install_fabric(&client, minecraft_version, &version, &installer).await?,
+        Loader::Quilt => install_quilt(&client, minecraft_version, &version, &installer).await?,
+        Loader::Forge | Loader::NeoForge => install_forge(&client, loader, &version).await?,
+    }
+
+    let data = fs::read(&launcher)
+        .await
+        .with_context(|| format!("{loader} installer didn't create {launcher}"))?;
+    Ok(Some(LockedLoader {
+        version,
+        installer,
+        minecraft_version: minecraft_version.clone(),
+        sha1: fetch::sha1_hex(&data),
+    }))
+}
+
+/// Download the server launcher Fabric's meta API builds for the given versions.
+async fn install_fabric(
+    client: &Client,
+    minecraft_version: &str,
+    version: &str,
+    installer: &str,
+) -> anyhow::Result<()> {
+    let launcher = Loader::Fabric.launch_path(version);
+    let url = format!(
+        "{FABRIC_META_URL}/versions/loader/{minecraft_version}/{version}/{installer}/server/jar"
+    );
+    let temp_path = fetch::temp_path(launcher.as_str());
+    tracing::debug!("Writing {launcher} to {temp_path}");
+    // Built on demand by the meta API, so there's no published checksum.
+    fetch::download(client, &url, &temp_path, None).await?;
+    fs::rename(&temp_path, &launcher).await?;
+    Ok(())
+}
+
+/// Run Quilt's installer headless to set up its launcher and libraries.
+async fn install_quilt(
+    client: &Client,
+    minecraft_version: &str,
+    version: &str,
+    installer: &str,
+) -> anyhow::Result<()> {
+    let url = format!(
+        "{QUILT_MAVEN_URL}/org/quiltmc/quilt-installer/{installer}/quilt-installer-{installer}.jar"
+    );
+    let installer_path = download_installer(client, &url).await?;
+    run_installer(
+        Loader::Quilt,
+        &installer_path,
+        &[
+            "install",
+            "server",
+            minecraft_version,
+            version,
+            "--install-dir=.",
+        ],
+    )
+    .await
+}
+
+/// Run the Forge or NeoForge installer headless to set up `libraries/`.
+///
+/// The installer also writes `run.sh` and `user_jvm_args.txt`, which are left
+/// alone since `mc` passes its own JVM arguments.
+async fn install_forge(client: &Client, loader: Loader, version: &str) -> anyhow::Result<()> {
+    let maven = maven_url(loader);
+    let artifact = match loader {
+        Loader::NeoForge => "neoforge",
+        _ => "forge",
+    };
+    let url = format!("{maven}/{version}/{artifact}-{version}-installer.jar");
+    let installer_path = download_installer(client, &url).await?;
+    run_installer(loader, &installer_path, &["--installServer", "."]).await
+}
+
+/// Download an installer jar, checked against its Maven `.sha1` sidecar.
+///
+/// Returns the temporary path it was downloaded to.
+async fn download_installer(client: &Client, url: &str) -> anyhow::Result<String> {
+    let checksum = get_text(client, format!("{url}.sha1")).await?;
+    let checksum = checksum.split_whitespace().next().unwrap_or_default();
+    let (_, name) = url.rsplit_once('/').unwrap_or(("", url));
+    let installer_path = fetch::temp_path(name);
+    tracing::debug!("Writing {name} to {installer_path}");
+    fetch::download(
+        client,
+        url,
+        &installer_path,
+        Some((HashAlgorithm::Sha1, checksum)),
+    )
+    .await?;
+    Ok(installer_path)
+}
+
+/// Run an installer jar headless in the workspace, then remove it.
+///
+/// The installer is done once it exits successfully.
+async fn run_installer(loader: Loader, installer_path: &str, args: &[&str]) -> anyhow::Result<()> {
+    tracing::debug!(
+        "Running {loader} installer: java -jar {installer_path} {}",
+        args.join(" ")
+    );
+    let output = Command::new("java")
+        .args(["-jar", installer_path])
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {loader} installer"));
+    fs::remove_file(installer_path).await?;
+    let output = output?;
+    tracing::debug!(
+        "{loader} installer output:\n{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    if !output.status.success() {
+        bail!(
+            "{loader} installer failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_latest_stable_fabric() {
+        let loaders: Vec<LoaderEntry> = serde_json::from_str(
+            r#"[
+                {"loader": {"separator": ".", "build": 10, "maven": "net.fabricmc:fabric-loader:0.17.0-beta.1", "version": "0.17.0-beta.1", "stable": false}, "intermediary": {"maven": "net.fabricmc:intermediary:1.21.4", "version": "1.21.4", "stable": true}},
+                {"loader": {"separator": ".", "build": 9, "maven": "net.fabricmc:fabric-loader:0.16.9", "version": "0.16.9", "stable": true}, "intermediary": {"maven": "net.fabricmc:intermediary:1.21.4", "version": "1.21.4", "stable": true}},
+                {"loader": {"separator": ".", "build": 8, "maven": "net.fabricmc:fabric-loader:0.16.8", "version": "0.16.8", "stable": false}, "intermediary": {"maven": "net.fabricmc:intermediary:1.21.4", "version": "1.21.4", "stable": true}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            latest_stable(loaders.iter().map(|entry| &entry.loader)),
+            Some("0.16.9")
+        );
+    }
+
+    #[test]
+    fn test_latest_stable_quilt() {
+        let loaders: Vec<LoaderEntry> = serde_json::from_str(
+            r#"[
+                {"loader": {"separator": ".", "build": 0, "maven": "org.quiltmc:quilt-loader:0.28.0-beta.1", "version": "0.28.0-beta.1"}},
+                {"loader": {"separator": ".", "build": 0, "maven": "org.quiltmc:quilt-loader:0.27.1", "version": "0.27.1"}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            latest_stable(loaders.iter().map(|entry| &entry.loader)),
+            Some("0.27.1")
+        );
+    }
+
+    #[test]
+    fn test_latest_stable_installer() {
+        let installers: Vec<MetaVersion> = serde_json::from_str(
+            r#"[
+                {"url": "https://maven.fabricmc.net/net/fabricmc/fabric-installer/1.0.2/fabric-installer-1.0.2.jar", "maven": "net.fabricmc:fabric-installer:1.0.2", "version": "1.0.2", "stable": false},
+                {"url": "https://maven.fabricmc.net/net/fabricmc/fabric-installer/1.0.1/fabric-installer-1.0.1.jar", "maven": "net.fabricmc:fabric-installer:1.0.1", "version": "1.0.1", "stable": true}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(latest_stable(&installers), Some("1.0.1"));
+    }
+
+    #[test]
+    fn test_latest_stable_unsupported() {
+        let loaders: Vec<LoaderEntry> = serde_json::from_str("[]").unwrap();
+        assert_eq!(
+            latest_stable(loaders.iter().map(|entry| &entry.loader)),
+            None
+        );
+    }
+
+    const FORGE_METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>net.minecraftforge</groupId>
+  <artifactId>forge</artifactId>
+  <versioning>
+    <latest>1.21.5-55.0.3</latest>
+    <release>1.21.5-55.0.3</release>
+    <versions>
+      <version>1.7.10-10.13.4.1614-1.7.10</version>
+      <version>1.21.4-54.0.9</version>
+      <version>1.21.4-54.0.26</version>
+      <version>1.21.4-54.0.10</version>
+      <version>1.21.5-55.0.3</version>
+    </versions>
+    <lastUpdated>20250301120000</lastUpdated>
+  </versioning>
+</metadata>"#;
+
+    const NEOFORGE_METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>net.neoforged</groupId>
+  <artifactId>neoforge</artifactId>
+  <versioning>
+    <versions>
+      <version>21.0.167</version>
+      <version>21.1.84</version>
+      <version>21.4.120</version>
+      <version>21.4.136</version>
+      <version>21.4.137-beta</version>
+    </versions>
+  </versioning>
+</metadata>"#;
+
+    #[test]
+    fn test_parse_maven_versions() {
+        let versions = parse_maven_versions(FORGE_METADATA);
+        assert_eq!(versions.len(), 5);
+        assert_eq!(versions[1], "1.21.4-54.0.9");
+        assert!(parse_maven_versions("<metadata/>").is_empty());
+    }
+
+    #[test_case(Loader::Forge, FORGE_METADATA, "1.21.4", Some("1.21.4-54.0.26") ; "forge")]
+    #[test_case(Loader::Forge, FORGE_METADATA, "1.20.1", None ; "forge unsupported")]
+    #[test_case(Loader::NeoForge, NEOFORGE_METADATA, "1.21.4", Some("21.4.136") ; "neoforge skips beta")]
+    #[test_case(Loader::NeoForge, NEOFORGE_METADATA, "1.21", Some("21.0.167") ; "neoforge without patch")]
+    #[test_case(Loader::NeoForge, NEOFORGE_METADATA, "24w45a", None ; "neoforge snapshot")]
+    fn test_latest_for_minecraft(
+        loader: Loader,
+        metadata: &str,
+        minecraft_version: &str,
+        expected: Option<&str>,
+    ) {
+        let versions = parse_maven_versions(metadata);
+        assert_eq!(
+            latest_for_minecraft(loader, &versions, minecraft_version),
+            expected
+        );
+    }
+
+    #[test_case("1.21.4", Some("1.21.4-54.1.0") ; "recommended")]
+    #[test_case("1.21.5", Some("1.21.5-55.0.3") ; "latest")]
+    #[test_case("1.20.1", None ; "not promoted")]
+    fn test_forge_promoted(minecraft_version: &str, expected: Option<&str>) {
+        let promotions: Promotions = serde_json::from_str(
+            r#"{
+                "homepage": "https://files.minecraftforge.net/net/minecraftforge/forge/",
+                "promos": {
+                    "1.21.4-latest": "54.1.3",
+                    "1.21.4-recommended": "54.1.0",
+                    "1.21.5-latest": "55.0.3"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(promotions.promoted(minecraft_version).as_deref(), expected);
+    }
+
+    #[test_case(Loader::Forge, "54.0.26", "1.21.4-54.0.26" ; "forge")]
+    #[test_case(Loader::Forge, "1.21.4-54.0.26", "1.21.4-54.0.26" ; "forge with prefix")]
+    #[test_case(Loader::NeoForge, "21.4.136", "21.4.136" ; "neoforge")]
+    fn test_maven_version(loader: Loader, version: &str, expected: &str) {
+        assert_eq!(maven_version(loader, "1.21.4", version), expected);
+    }
+
+    #[test]
+    fn test_launch() {
+        assert_eq!(
+            Loader::Fabric.launch("0.16.9"),
+            Launch::Jar("fabric-server-launch.jar".into())
+        );
+        assert_eq!(
+            Loader::NeoForge.launch("21.4.136"),
+            Launch::ArgFile("libraries/net/neoforged/neoforge/21.4.136/unix_args.txt".into())
+        );
+    }
+}
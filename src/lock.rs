@@ -0,0 +1,243 @@
+use std::{fmt, io::ErrorKind};
+
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use fs_err::tokio as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::provider::ServerType;
+
+/// Location of the lock file, relative to the workspace.
+pub const LOCK_PATH: &str = "mc.lock";
+
+const LOCK_HEADER: &str =
+    "# This file is generated by mc. Run `mc update` to change the server version.";
+
+/// Contents of `mc.lock`, pinning the resolved server for reproducible restarts.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub struct Lock {
+    pub server: LockedServer,
+    /// The mod loader launching the server, for modded servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<LockedLoader>,
+    /// The server replaced by the last upgrade, to roll back to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<PreviousServer>,
+}
+
+/// The pinned server version and the checksums it was resolved with.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct LockedServer {
+    /// Where the server comes from.
+    #[serde(
+        rename = "type",
+        default,
+        skip_serializing_if = "ServerType::is_vanilla"
+    )]
+    pub server_type: ServerType,
+    /// Version id, e.g. `1.21.3`.
+    pub version: String,
+    /// Build of the version, for server types with several builds per version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    /// SHA-1 of `server.jar`.
+    pub sha1: String,
+    /// SHA-1 of the version metadata, as listed in Mojang's version manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_sha1: Option<String>,
+    /// The version that most recently ran the world, used to prevent downgrades.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<String>,
+}
+
+impl LockedServer {
+    /// A server jar with its SHA-1, of a version that hasn't run the world yet.
+    pub fn new(server_type: ServerType, version: String, sha1: String) -> Self {
+        LockedServer {
+            server_type,
+            version,
+            build: None,
+            sha1,
+            metadata_sha1: None,
+            last_run: None,
+        }
+    }
+}
+
+impl fmt::Display for LockedServer {
+    /// Describe the server, e.g. `paper 1.21.4 build 231`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.server_type, self.version)?;
+        if let Some(build) = &self.build {
+            write!(f, " build {build}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The pinned mod loader and the launcher installed for it.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct LockedLoader {
+    /// Loader version, e.g. `0.16.9`.
+    pub version: String,
+    /// Version of the installer the launcher came from.
+    pub installer: String,
+    /// Minecraft version the launcher was installed for.
+    pub minecraft_version: String,
+    /// SHA-1 of the launcher jar or argument file.
+    pub sha1: String,
+}
+
+impl LockedLoader {
+    /// A launcher installed for a loader and Minecraft version.
+    pub fn new(
+        version: String,
+        installer: String,
+        minecraft_version: String,
+        sha1: String,
+    ) -> Self {
+        LockedLoader {
+            version,
+            installer,
+            minecraft_version,
+            sha1,
+        }
+    }
+}
+
+/// A server version replaced by an upgrade, kept as `server.jar.<version>`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct PreviousServer {
+    #[serde(
+        rename = "type",
+        default,
+        skip_serializing_if = "ServerType::is_vanilla"
+    )]
+    pub server_type: ServerType,
+    /// Version id, e.g. `1.21.3`.
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    /// SHA-1 of the kept jar.
+    pub sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_sha1: Option<String>,
+    /// The mod loader the kept version last ran with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<LockedLoader>,
+    /// The backup taken before the new version first ran the world.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<Utf8PathBuf>,
+}
+
+impl Lock {
+    /// Pin `server`, with no loader and nothing to roll back to.
+    pub fn new(server: LockedServer) -> Self {
+        Lock {
+            server,
+            loader: None,
+            previous: None,
+        }
+    }
+
+    /// Read the lock file from the current directory.
+    ///
+    /// Returns `None` if the file doesn't exist.
+    pub async fn load() -> anyhow::Result<Option<Self>> {
+        let content = match fs::read_to_string(LOCK_PATH).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let lock = toml::from_str(&content).with_context(|| format!("Invalid {LOCK_PATH}"))?;
+        Ok(Some(lock))
+    }
+
+    /// Write the lock file to the current directory.
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let content = format!("{LOCK_HEADER}\n{}", toml::to_string(self)?);
+        tracing::debug!("Writing {LOCK_PATH}");
+        fs::write(LOCK_PATH, content).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_round_trip() {
+        let lock = Lock {
+            server: LockedServer {
+                server_type: ServerType::Vanilla,
+                version: "1.21.3".to_string(),
+                build: None,
+                sha1: "45810d238246d90e811d896f87b14695b7fb6839".to_string(),
+                metadata_sha1: Some("b7cbc0e4a3e8fd1d1bd3ed1e5cc8e1bde2bb1a56".to_string()),
+                last_run: Some("1.21.3".to_string()),
+            },
+            loader: None,
+            previous: Some(PreviousServer {
+                server_type: ServerType::Vanilla,
+                version: "1.21.1".to_string(),
+                build: None,
+                sha1: "59353fb40c36d304f2035d51e7d6e6baa98dc05c".to_string(),
+                metadata_sha1: Some("0b33cbbd2b7d16a2aa0cc8e6ad7da0da2a96a4ba".to_string()),
+                loader: None,
+                backup: Some("backups/20241129T213000Z-pre-1.21.3.tar.zst".into()),
+            }),
+        };
+        let content = toml::to_string(&lock).unwrap();
+        assert!(content.contains("metadata-sha1 = "));
+        assert!(!content.contains("type = "));
+        assert_eq!(toml::from_str::<Lock>(&content).unwrap(), lock);
+    }
+
+    #[test]
+    fn test_lock_with_build() {
+        let lock: Lock = toml::from_str(
+            r#"
+            [server]
+            type = "paper"
+            version = "1.21.4"
+            build = "232"
+            sha1 = "59353fb40c36d304f2035d51e7d6e6baa98dc05c"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(lock.server.server_type, ServerType::Paper);
+        assert_eq!(lock.server.build.as_deref(), Some("232"));
+        assert_eq!(lock.server.metadata_sha1, None);
+    }
+
+    #[test]
+    fn test_lock_with_loader() {
+        let lock: Lock = toml::from_str(
+            r#"
+            [server]
+            type = "fabric"
+            version = "1.21.4"
+            sha1 = "4707d00eb834b446575d89a61a11b5d548d8c001"
+
+            [loader]
+            version = "0.16.9"
+            installer = "1.0.1"
+            minecraft-version = "1.21.4"
+            sha1 = "a1b7e5c3f1a0d2e4b6c8d0f2a4b6c8e0d2f4a6b8"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(lock.server.server_type, ServerType::Fabric);
+        let loader = lock.loader.unwrap();
+        assert_eq!(loader.version, "0.16.9");
+        assert_eq!(loader.installer, "1.0.1");
+    }
+}
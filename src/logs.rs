@@ -0,0 +1,477 @@
+use std::{
+    fmt,
+    io::{ErrorKind, Read},
+    os::unix::process::ExitStatusExt,
+    process::ExitStatus,
+    time::Duration,
+};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use fs_err::tokio as fs;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// The log being written to, relative to the logs directory.
+pub const CURRENT_PATH: &str = "server.log";
+/// The record of each run, relative to the logs directory.
+pub const RUNS_PATH: &str = "runs.json";
+const ROTATED_PREFIX: &str = "server-";
+const ROTATED_SUFFIX: &str = ".log.gz";
+/// Rotated logs are renamed to this before they're compressed, and left so
+/// if compressing fails.
+const UNCOMPRESSED_SUFFIX: &str = ".log";
+/// Where logs are written unless configured otherwise.
+pub const DEFAULT_DIRECTORY: &str = "mc-logs";
+/// The size at which the current log is rotated unless configured otherwise.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Rotated logs kept unless configured otherwise.
+pub const DEFAULT_KEEP: usize = 10;
+/// Runs recorded beyond this many are forgotten, oldest first.
+const MAX_RUNS: usize = 100;
+
+/// Where and when to rotate the logs of server output.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// Directory logs are written to, relative to the workspace.
+    pub directory: Utf8PathBuf,
+    /// Size in bytes at which the current log is rotated.
+    pub max_size: u64,
+    /// How long the current log is written to before it's rotated, if limited.
+    pub interval: Option<Duration>,
+    /// Number of rotated logs to keep.
+    pub keep: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            directory: DEFAULT_DIRECTORY.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            interval: None,
+            keep: DEFAULT_KEEP,
+        }
+    }
+}
+
+/// One run of the server process, from spawning it until it exited.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Run {
+    /// Increases with every run, starting from 1.
+    pub id: u64,
+    pub start: Timestamp,
+    /// When the server exited, unless `mc` itself stopped first.
+    pub stop: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// The server that ran, e.g. `paper 1.21.4 build 231`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl Run {
+    /// When the run's output ends: when the server exited or, if `mc` was
+    /// interrupted first, when the next run started.
+    fn end(&self, runs: &[Run]) -> Option<Timestamp> {
+        self.stop.or_else(|| {
+            runs.iter()
+                .find(|next| next.id > self.id)
+                .map(|next| next.start)
+        })
+    }
+}
+
+/// Whether `time` falls between `start` and `end`, if the run has ended.
+fn contains(start: Timestamp, end: Option<Timestamp>, time: Timestamp) -> bool {
+    start <= time && end.is_none_or(|end| time <= end)
+}
+
+/// How the run ended, e.g. `exit code 1`.
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.stop, self.code, self.signal) {
+            (None, _, _) => write!(f, "running or interrupted"),
+            (Some(_), Some(code), _) => write!(f, "exit code {code}"),
+            (Some(_), None, Some(signal)) => write!(f, "signal {signal}"),
+            (Some(_), None, None) => write!(f, "stopped"),
+        }
+    }
+}
+
+/// Read the record of each run, oldest first.
+pub async fn runs(config: &Config) -> anyhow::Result<Vec<Run>> {
+    let path = config.directory.join(RUNS_PATH);
+    match fs::read_to_string(&path).await {
+        Ok(content) => serde_json::from_str(&content).with_context(|| format!("Invalid {path}")),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn save_runs(config: &Config, runs: &[Run]) -> anyhow::Result<()> {
+    let path = config.directory.join(RUNS_PATH);
+    fs::write(&path, serde_json::to_string_pretty(runs)?).await?;
+    Ok(())
+}
+
+/// Record that the server started, returning the run's id.
+pub(crate) async fn start_run(config: &Config, version: Option<String>) -> anyhow::Result<u64> {
+    let mut runs = runs(config).await?;
+    let id = runs.last().map_or(1, |run| run.id + 1);
+    runs.push(Run {
+        id,
+        start: Timestamp::now(),
+        stop: None,
+        code: None,
+        signal: None,
+        version,
+    });
+    let excess = runs.len().saturating_sub(MAX_RUNS);
+    runs.drain(..excess);
+    save_runs(config, &runs).await?;
+    Ok(id)
+}
+
+/// Record how a run ended.
+pub(crate) async fn stop_run(config: &Config, id: u64, status: ExitStatus) -> anyhow::Result<()> {
+    let mut runs = runs(config).await?;
+    if let Some(run) = runs.iter_mut().find(|run| run.id == id) {
+        run.stop = Some(Timestamp::now());
+        run.code = status.code();
+        run.signal = status.signal();
+    }
+    save_runs(config, &runs).await
+}
+
+/// Pick a run by id, or counting back from the latest with `-1`.
+pub fn select(runs: &[Run], index: i64) -> Option<&Run> {
+    if index < 0 {
+        let back = usize::try_from(index.unsigned_abs()).ok()?;
+        runs.len().checked_sub(back).map(|index| &runs[index])
+    } else {
+        runs.iter().find(|run| i64::try_from(run.id) == Ok(index))
+    }
+}
+
+/// The log of server output, rotated by size or age and compressed.
+///
+/// Each line is prefixed with the time it was written, which lets
+/// [`read_run`] find a run's output across rotations.
+#[derive(Debug)]
+pub(crate) struct Log {
+    config: Config,
+    file: fs::File,
+    size: u64,
+    /// When the current log was started.
+    opened: Timestamp,
+    /// Whether a write has failed, so the failure is only reported once.
+    failed: bool,
+}
+
+impl Log {
+    /// Open the current log, creating the logs directory if needed.
+    pub async fn open(config: &Config) -> anyhow::Result<Self> {
+        fs::create_dir_all(&config.directory).await?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config.directory.join(CURRENT_PATH))
+            .await?;
+        let metadata = file.metadata().await?;
+        let opened = metadata
+            .created()
+            .ok()
+            .and_then(|created| Timestamp::try_from(created).ok())
+            .unwrap_or_else(Timestamp::now);
+        Ok(Log {
+            config: config.clone(),
+            file,
+            size: metadata.len(),
+            opened,
+            failed: false,
+        })
+    }
+
+    /// Append a line of output, rotating the log first if it's due.
+    ///
+    /// Failures are logged rather than returned, since they shouldn't stop
+    /// the server.
+    pub async fn write(&mut self, line: &[u8]) {
+        match self.try_write(line).await {
+            Ok(()) => self.failed = false,
+            Err(err) if !self.failed => {
+                tracing::warn!("Failed to write server log: {err:#}");
+                self.failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    async fn try_write(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        let now = Timestamp::now();
+        let expired = self.config.interval.is_some_and(|interval| {
+            now.duration_since(self.opened)
+                .try_into()
+                .is_ok_and(|age: Duration| age >= interval)
+        });
+        if self.size > 0 && (self.size >= self.config.max_size || expired) {
+            self.rotate().await?;
+        }
+
+        let mut entry = format!("{now} ").into_bytes();
+        entry.extend(line.strip_suffix(b"\n").unwrap_or(line));
+        entry.push(b'\n');
+        self.file.write_all(&entry).await?;
+        self.file.flush().await?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    /// Begin a new log, then compress the current one under the time it was
+    /// started and remove the oldest rotated logs beyond the ones to keep.
+    ///
+    /// Compressing happens in the background, so that the server's output
+    /// isn't held up meanwhile.
+    async fn rotate(&mut self) -> anyhow::Result<()> {
+        let current = self.config.directory.join(CURRENT_PATH);
+        let name = self.opened.strftime("%Y%m%dT%H%M%S%.3fZ");
+        let plain = self
+            .config
+            .directory
+            .join(format!("{ROTATED_PREFIX}{name}{UNCOMPRESSED_SUFFIX}"));
+        tracing::debug!("Rotating {current} to {plain}");
+        fs::rename(&current, &plain).await?;
+        self.file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)
+            .await?;
+        self.size = 0;
+        self.opened = Timestamp::now();
+
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            match tokio::task::spawn_blocking(move || compress(&plain)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::warn!("Failed to compress rotated log: {err:#}"),
+                Err(err) => tracing::warn!("Failed to compress rotated log: {err}"),
+            }
+            if let Err(err) = prune(&config).await {
+                tracing::warn!("Failed to remove old logs: {err:#}");
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Compress a rotated log next to it, removing the uncompressed one.
+fn compress(plain: &Utf8Path) -> anyhow::Result<()> {
+    let stem = plain
+        .as_str()
+        .strip_suffix(UNCOMPRESSED_SUFFIX)
+        .context("Not a rotated log")?;
+    let compressed = Utf8PathBuf::from(format!("{stem}{ROTATED_SUFFIX}"));
+    let partial = Utf8PathBuf::from(format!("{compressed}.partial"));
+    let result =
+        write_compressed(plain, &partial).and_then(|()| Ok(fs_err::rename(&partial, &compressed)?));
+    if let Err(err) = result {
+        let _ = fs_err::remove_file(&partial);
+        return Err(err);
+    }
+    fs_err::remove_file(plain)?;
+    Ok(())
+}
+
+fn write_compressed(plain: &Utf8Path, compressed: &Utf8Path) -> anyhow::Result<()> {
+    let mut input = fs_err::File::open(plain)?;
+    let mut encoder = GzEncoder::new(fs_err::File::create(compressed)?, Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Rotated logs, oldest first, including any left uncompressed.
+async fn rotated(config: &Config) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let mut read = match fs::read_dir(&config.directory).await {
+        Ok(read) => read,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut rotated = Vec::new();
+    while let Some(entry) = read.next_entry().await? {
+        let path: Utf8PathBuf = entry.path().try_into()?;
+        if path.file_name().is_some_and(|name| {
+            name.starts_with(ROTATED_PREFIX)
+                && (name.ends_with(ROTATED_SUFFIX) || name.ends_with(UNCOMPRESSED_SUFFIX))
+        }) {
+            rotated.push(path);
+        }
+    }
+    // Names sort by the time the logs were started.
+    rotated.sort();
+    Ok(rotated)
+}
+
+async fn prune(config: &Config) -> anyhow::Result<()> {
+    let rotated = rotated(config).await?;
+    let excess = rotated.len().saturating_sub(config.keep);
+    for path in &rotated[..excess] {
+        tracing::debug!("Removing old log {path}");
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// Split a logged line into the time it was written and the output itself.
+fn parse_line(line: &str) -> Option<(Timestamp, &str)> {
+    let (time, output) = line.split_once(' ')?;
+    Some((time.parse().ok()?, output))
+}
+
+/// The server output logged during `run`, one of `runs`, across rotated logs.
+pub async fn read_run(config: &Config, runs: &[Run], run: &Run) -> anyhow::Result<Vec<String>> {
+    let end = run.end(runs);
+    let mut paths = rotated(config).await?;
+    paths.push(config.directory.join(CURRENT_PATH));
+
+    let mut lines = Vec::new();
+    for path in paths {
+        let data = match fs::read(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let content = if path.as_str().ends_with(".gz") {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(&data[..])
+                .read_to_end(&mut decompressed)
+                .with_context(|| format!("Unable to decompress {path}"))?;
+            decompressed
+        } else {
+            data
+        };
+        let content = String::from_utf8_lossy(&content);
+        lines.extend(
+            content
+                .lines()
+                .filter_map(parse_line)
+                .filter(|(time, _)| contains(run.start, end, *time))
+                .map(|(_, output)| output.to_string()),
+        );
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(id: u64, start: &str, stop: Option<&str>) -> Run {
+        Run {
+            id,
+            start: start.parse().unwrap(),
+            stop: stop.map(|stop| stop.parse().unwrap()),
+            code: stop.map(|_| 1),
+            signal: None,
+            version: Some("vanilla 1.21.4".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let runs = [
+            run(3, "2024-11-29T10:00:00Z", Some("2024-11-29T11:00:00Z")),
+            run(4, "2024-11-29T11:00:05Z", Some("2024-11-29T12:00:00Z")),
+            run(5, "2024-11-29T12:00:05Z", None),
+        ];
+        assert_eq!(select(&runs, -1).map(|run| run.id), Some(5));
+        assert_eq!(select(&runs, -3).map(|run| run.id), Some(3));
+        assert_eq!(select(&runs, -4), None);
+        assert_eq!(select(&runs, 4).map(|run| run.id), Some(4));
+        assert_eq!(select(&runs, 1), None);
+    }
+
+    #[test]
+    fn test_run_display() {
+        let crashed = run(1, "2024-11-29T10:00:00Z", Some("2024-11-29T11:00:00Z"));
+        assert_eq!(crashed.to_string(), "exit code 1");
+        let running = run(2, "2024-11-29T11:00:00Z", None);
+        assert_eq!(running.to_string(), "running or interrupted");
+    }
+
+    #[test]
+    fn test_contains() {
+        let run = run(1, "2024-11-29T10:00:00Z", Some("2024-11-29T11:00:00Z"));
+        let (time, output) =
+            parse_line("2024-11-29T10:30:00.5Z [10:30:00] [Server thread/INFO]: Done (4.2s)!")
+                .unwrap();
+        assert_eq!(output, "[10:30:00] [Server thread/INFO]: Done (4.2s)!");
+        assert!(contains(run.start, run.stop, time));
+        let later = "2024-11-29T11:00:01Z".parse().unwrap();
+        assert!(!contains(run.start, run.stop, later));
+        assert_eq!(parse_line("Starting minecraft server"), None);
+    }
+
+    #[test]
+    fn test_run_end() {
+        let runs = [
+            run(1, "2024-11-29T10:00:00Z", None),
+            run(2, "2024-11-29T11:00:05Z", Some("2024-11-29T12:00:00Z")),
+            run(3, "2024-11-29T12:00:05Z", None),
+        ];
+        // An interrupted run ends where the next one starts.
+        assert_eq!(runs[0].end(&runs), Some(runs[1].start));
+        assert_eq!(runs[1].end(&runs), runs[1].stop);
+        assert_eq!(runs[2].end(&runs), None);
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let directory = std::env::temp_dir().join(format!("mc-test-{:x}", rand::random::<u64>()));
+        let config = Config {
+            directory: directory.try_into().unwrap(),
+            max_size: 1,
+            interval: None,
+            keep: 10,
+        };
+        let mut log = Log::open(&config).await.unwrap();
+        log.write(b"first\n").await;
+        log.write(b"second\n").await;
+
+        // Compressing finishes in the background.
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while rotated(&config)
+            .await
+            .unwrap()
+            .iter()
+            .any(|path| !path.as_str().ends_with(ROTATED_SUFFIX))
+            && tokio::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(rotated(&config).await.unwrap().len(), 1);
+
+        let run = run(1, "2024-11-29T10:00:00Z", None);
+        assert_eq!(
+            read_run(&config, &[], &run).await.unwrap(),
+            ["first", "second"]
+        );
+        fs_err::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[test]
+    fn test_runs_wire_format() {
+        let runs: Vec<Run> = serde_json::from_str(
+            r#"[{"id": 1, "start": "2024-11-29T10:00:00Z", "stop": "2024-11-29T11:00:00Z", "signal": 9}]"#,
+        )
+        .unwrap();
+        assert_eq!(runs[0].signal, Some(9));
+        assert_eq!(runs[0].to_string(), "signal 9");
+    }
+}
@@ -1,49 +1,608 @@
 mod cli;
-mod fetch;
-mod manifest;
-mod server;
-mod workspace;
 
-use std::env::current_dir;
+use std::{env::current_dir, time::Duration};
 
-use clap::Parser;
+use anyhow::{Context, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{CommandFactory, FromArgMatches};
 
-use fetch::Fetch;
-use manifest::Type;
+use cli::{
+    BansCommand, Command, ConfigCommand, DatapacksCommand, ModpackCommand, ModsCommand, OpsCommand,
+    Settings, WhitelistCommand, WorldCommand,
+};
+use mc::{
+    access::{self, Allowed, IpBan, Op, PlayerBan},
+    backup, config, control, datapack,
+    fetch::SERVER_PATH,
+    install, jar, loader,
+    lock::Lock,
+    logs, modpack,
+    modrinth::{MODRINTH_API_URL, Modrinth},
+    mods::{self, Change, Changed, ModsLock},
+    ping, protocol, query, server, upgrade, workspace, world,
+};
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = cli::Args::parse();
+    let matches = cli::Args::command().get_matches();
+    let args = cli::Args::from_arg_matches(&matches)?;
     tracing_subscriber::fmt()
         .with_max_level(args.log_level)
         // TODO: env filter seems to override max_level
-        .with_env_filter(EnvFilter::try_new(args.log_filter)?)
+        .with_env_filter(EnvFilter::try_new(&args.log_filter)?)
         .init();
 
-    // ---- Initial workspace preparation ----
+    // ---- Resolving configuration ----
 
-    let directory = args.directory.unwrap_or(current_dir()?.try_into()?);
+    let directory: Utf8PathBuf = match args.directory {
+        Some(directory) => directory,
+        None => current_dir()?.try_into()?,
+    };
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| directory.join(config::CONFIG_PATH));
+    let file = match config::File::load(&config_path).await? {
+        Some(file) => {
+            tracing::debug!("Loaded config from {config_path}");
+            file
+        }
+        None if args.config.is_some() => bail!("Config file not found: {config_path}"),
+        None => config::File::default(),
+    };
+    let settings = Settings::resolve(args.server, &matches, file);
+
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => run(directory, &settings).await,
+        Command::Update => update(directory, &settings).await,
+        Command::Backup => backup(directory, &settings).await,
+        Command::Restore { archive } => restore(directory, &settings, &archive).await,
+        Command::Rollback => rollback(directory).await,
+        Command::Ping { address, timeout } => ping(directory, address, timeout).await,
+        Command::Query { address, timeout } => query(directory, address, timeout).await,
+        Command::Exec {
+            command,
+            until,
+            timeout,
+            rcon,
+        } => exec(directory, command.join(" "), until, timeout, rcon).await,
+        Command::Logs { run, list } => logs(directory, &settings, run, list).await,
+        Command::Version => version(directory).await,
+        Command::World(WorldCommand::Info) => world_info(directory).await,
+        Command::Mods(command) => mods(directory, command).await,
+        Command::Modpack(ModpackCommand::Install { pack }) => {
+            modpack_install(directory, &settings, absolute(&pack)?).await
+        }
+        Command::Modpack(ModpackCommand::Export {
+            output,
+            name,
+            pack_version,
+        }) => {
+            let output = output.as_deref().map(absolute).transpose()?;
+            modpack_export(directory, output, name, pack_version).await
+        }
+        Command::Datapacks(command) => datapacks(directory, command).await,
+        Command::Ops(command) => ops(directory, &settings.profile_api_url.value, command).await,
+        Command::Whitelist(command) => {
+            whitelist(directory, &settings.profile_api_url.value, command).await
+        }
+        Command::Bans(command) => bans(directory, &settings.profile_api_url.value, command).await,
+        Command::Config(ConfigCommand::Show) => {
+            print!("{settings}");
+            Ok(())
+        }
+    }
+}
+
+async fn run(directory: Utf8PathBuf, settings: &Settings) -> anyhow::Result<()> {
+    workspace::prepare(&directory).await?;
+    let _lock = workspace::lock()?;
+    workspace::apply_properties(&settings.properties()).await?;
+
+    let prepared = install::prepare(
+        settings.configured_server_type(),
+        settings.server_version.value.as_deref(),
+        &settings.backup_config(),
+    )
+    .await?;
+
+    let mut config = settings.server_config(directory);
+    config.launch = loader::launch(&prepared.lock);
+    config.version = Some(prepared.lock.server.to_string());
+    upgrade::run(&config, &prepared, settings.auto_rollback.value).await
+}
+
+async fn update(directory: Utf8PathBuf, settings: &Settings) -> anyhow::Result<()> {
     workspace::prepare(&directory).await?;
+    let (lock, updated) = install::update(
+        settings.configured_server_type(),
+        settings.server_version.value.as_deref(),
+    )
+    .await?;
+
+    match &lock {
+        Some(lock) if lock.server.to_string() == updated.server.to_string() => {
+            tracing::info!("Server {} is up to date", updated.server);
+        }
+        Some(lock) => tracing::info!("Updated server from {} to {}", lock.server, updated.server),
+        None => tracing::info!("Locked server {}", updated.server),
+    }
+    if let Some(loader) = &updated.loader
+        && lock.as_ref().and_then(|lock| lock.loader.as_ref()) != Some(loader)
+    {
+        tracing::info!(
+            "Locked {} loader {} (installer {})",
+            updated.server.server_type,
+            loader.version,
+            loader.installer
+        );
+    }
+
+    Ok(())
+}
+
+async fn backup(directory: Utf8PathBuf, settings: &Settings) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    let path = backup::take(&settings.backup_config()).await?;
+    println!("{path}");
+
+    Ok(())
+}
+
+async fn restore(directory: Utf8PathBuf, settings: &Settings, name: &str) -> anyhow::Result<()> {
+    // Resolve a path before leaving the current directory, names and
+    // timestamps are looked up in the workspace's backup directory.
+    let name = match Utf8Path::new(name) {
+        path if path.exists() => absolute(path)?.into_string(),
+        _ => name.to_string(),
+    };
+    workspace::enter(&directory)?;
+    let _lock = workspace::lock().context("Stop the server before restoring")?;
+
+    let archive = backup::find(&settings.backup_config(), &name).await?;
+    let restored = backup::restore(&archive).await?;
+    for world in &restored.worlds {
+        println!("Restored {world} from {archive}");
+    }
+    for replaced in &restored.replaced {
+        println!("Moved previous world to {replaced}");
+    }
+
+    Ok(())
+}
 
-    // ---- Getting the server ----
+async fn rollback(directory: Utf8PathBuf) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    let _lock = workspace::lock().context("Stop the server before rolling back")?;
 
-    let fetch = match args.server_version {
-        Some(version) => Fetch::Version(version),
-        None => Fetch::Latest(Type::Release),
+    let lock = Lock::load()
+        .await?
+        .context("No mc.lock, nothing to roll back")?;
+    let lock = upgrade::rollback(&lock).await?;
+    lock.save().await?;
+    println!("Rolled back to {}", lock.server.version);
+
+    Ok(())
+}
+
+async fn mods(directory: Utf8PathBuf, command: ModsCommand) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    let change = match command {
+        ModsCommand::List => {
+            for locked in ModsLock::load().await?.iter().flat_map(|lock| &lock.mods) {
+                let dependency = if locked.dependency {
+                    " (dependency)"
+                } else {
+                    ""
+                };
+                println!("{} {}{dependency}", locked.slug, locked.version);
+            }
+            return Ok(());
+        }
+        ModsCommand::Add { projects } => Change::Add(projects),
+        ModsCommand::Remove { projects } => Change::Remove(projects),
+        ModsCommand::Update { projects } => Change::Update(projects),
     };
 
-    fetch.execute().await?;
+    let modrinth = Modrinth::new(MODRINTH_API_URL)?;
+    let (current, updated) = mods::apply(&modrinth, &change).await?;
+    for changed in current.changes(&updated) {
+        match changed {
+            Changed::Added(locked) => println!("Added {} {}", locked.slug, locked.version),
+            Changed::Updated { from, to } => {
+                println!(
+                    "Updated {} from {} to {}",
+                    to.slug, from.version, to.version
+                )
+            }
+            Changed::Removed(old) => println!("Removed {} {}", old.slug, old.version),
+        }
+    }
 
-    // ---- Running the server ----
+    Ok(())
+}
 
-    let config = server::Config {
-        directory,
-        shutdown_timeout: args.shutdown_timeout,
-        min_memory: args.min_memory,
-        max_memory: args.max_memory,
+async fn datapacks(directory: Utf8PathBuf, command: DatapacksCommand) -> anyhow::Result<()> {
+    // Resolve local sources before leaving the current directory.
+    let command = match command {
+        DatapacksCommand::Add { source, force } if !source.contains("://") => {
+            DatapacksCommand::Add {
+                source: absolute(Utf8Path::new(&source))?.into_string(),
+                force,
+            }
+        }
+        command => command,
     };
-    server::run(&config).await?;
+    workspace::enter(&directory)?;
+    let world = workspace::world_directory().await?;
+
+    match command {
+        DatapacksCommand::List => {
+            for pack in datapack::list(&world).await? {
+                println!("{} ({})", pack.name, pack.state);
+            }
+        }
+        DatapacksCommand::Add { source, force } => {
+            let name = datapack::install(&world, &source, force).await?;
+            println!("Added {name}");
+        }
+        DatapacksCommand::Remove { name } => {
+            let name = datapack::uninstall(&world, &name).await?;
+            println!("Removed {name}");
+        }
+        DatapacksCommand::Enable { name } => {
+            let name = datapack::enable(&world, &name).await?;
+            println!("Enabled {name}");
+        }
+        DatapacksCommand::Disable { name } => {
+            let name = datapack::disable(&world, &name).await?;
+            println!("Disabled {name}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn ops(
+    directory: Utf8PathBuf,
+    profile_api_url: &str,
+    command: OpsCommand,
+) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    match command {
+        OpsCommand::List => {
+            for op in access::load::<Op>().await? {
+                println!("{} (level {})", op.name, op.level);
+            }
+        }
+        OpsCommand::Add {
+            name,
+            level,
+            bypass_player_limit,
+        } => match access::op(profile_api_url, &name, level, bypass_player_limit).await? {
+            Some(op) => println!("Opped {} (level {})", op.name, op.level),
+            None => println!("Opped {name}"),
+        },
+        OpsCommand::Remove { name } => {
+            access::deop(&name).await?;
+            println!("Deopped {name}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn whitelist(
+    directory: Utf8PathBuf,
+    profile_api_url: &str,
+    command: WhitelistCommand,
+) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    match command {
+        WhitelistCommand::List => {
+            for allowed in access::load::<Allowed>().await? {
+                println!("{}", allowed.name);
+            }
+        }
+        WhitelistCommand::Add { names } => {
+            access::allow(profile_api_url, &names).await?;
+            for name in names {
+                println!("Allowed {name}");
+            }
+        }
+        WhitelistCommand::Remove { names } => {
+            access::disallow(&names).await?;
+            for name in names {
+                println!("Disallowed {name}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn bans(
+    directory: Utf8PathBuf,
+    profile_api_url: &str,
+    command: BansCommand,
+) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    match command {
+        BansCommand::List => {
+            for ban in access::load::<PlayerBan>().await? {
+                println!("{}: {}", ban.name, ban.ban.reason);
+            }
+            for ban in access::load::<IpBan>().await? {
+                println!("{}: {}", ban.ip, ban.ban.reason);
+            }
+        }
+        BansCommand::Add { target, reason } => {
+            access::ban(profile_api_url, &target, reason.as_deref()).await?;
+            println!("Banned {target}");
+        }
+        BansCommand::Remove { target } => {
+            access::pardon(&target).await?;
+            println!("Pardoned {target}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve a path given on the command line before entering the workspace.
+fn absolute(path: &Utf8Path) -> anyhow::Result<Utf8PathBuf> {
+    Ok(std::path::absolute(path)?.try_into()?)
+}
+
+async fn modpack_install(
+    directory: Utf8PathBuf,
+    settings: &Settings,
+    path: Utf8PathBuf,
+) -> anyhow::Result<()> {
+    workspace::prepare(&directory).await?;
+    let installed = install::modpack(&path, settings.configured_server_type()).await?;
+    println!(
+        "Installed {} {} for {} {} ({} files, {} overrides)",
+        installed.index.name,
+        installed.index.version_id,
+        installed.lock.server.server_type,
+        installed.lock.server.version,
+        installed.mods.files.len(),
+        installed.overrides.len()
+    );
+
+    Ok(())
+}
+
+async fn modpack_export(
+    directory: Utf8PathBuf,
+    output: Option<Utf8PathBuf>,
+    name: Option<String>,
+    pack_version: String,
+) -> anyhow::Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => directory
+            .file_name()
+            .context("Unable to name the modpack, pass --name")?
+            .to_string(),
+    };
+    workspace::enter(&directory)?;
+
+    let lock = Lock::load()
+        .await?
+        .context("No mc.lock, run `mc update` to pick a server first")?;
+    let mods = ModsLock::load().await?;
+    let pack = modpack::export(&lock, mods.as_ref(), &name, &pack_version).await?;
+    let output = output.unwrap_or_else(|| format!("{name}-{pack_version}.mrpack").into());
+    fs_err::tokio::write(&output, pack).await?;
+    println!("{output}");
+
+    Ok(())
+}
+
+async fn ping(
+    directory: Utf8PathBuf,
+    address: Option<String>,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    // The workspace may not exist when pinging another server.
+    let default_port = match workspace::enter(&directory) {
+        Ok(()) => workspace::server_port().await?,
+        Err(_) => protocol::DEFAULT_PORT,
+    };
+    let (host, port) = match &address {
+        Some(address) => ping::parse_address(address, default_port)?,
+        None => ("localhost".to_string(), default_port),
+    };
+
+    let pong = ping::ping(&host, port, timeout).await?;
+    let status = pong.status;
+    println!("{host}:{port}:");
+    println!(
+        "  version: {} (protocol {})",
+        status.version.name, status.version.protocol
+    );
+    if let Some(players) = &status.players {
+        println!("  players: {}/{}", players.online, players.max);
+        for player in &players.sample {
+            println!("    {}", player.name);
+        }
+    }
+    println!("  motd: {}", status.motd());
+    println!("  latency: {}ms", pong.latency.as_millis());
+
+    Ok(())
+}
+
+async fn exec(
+    directory: Utf8PathBuf,
+    command: String,
+    until: Option<String>,
+    timeout: Duration,
+    rcon: bool,
+) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+    let lines = control::exec(&command, until.as_deref(), timeout, rcon).await?;
+
+    for line in &lines {
+        println!("{line}");
+    }
+    // Only the first line is sure to be the response, not other output.
+    if lines
+        .first()
+        .is_some_and(|line| server::is_unknown_command(line))
+    {
+        bail!("Unknown command: {command}");
+    }
+
+    Ok(())
+}
+
+async fn query(
+    directory: Utf8PathBuf,
+    address: Option<String>,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    // The workspace may not exist when querying another server.
+    let query_port = match workspace::enter(&directory) {
+        Ok(()) => workspace::query_port().await?,
+        Err(_) => None,
+    };
+    let (host, port) = match &address {
+        Some(address) => {
+            ping::parse_address(address, query_port.unwrap_or(protocol::DEFAULT_PORT))?
+        }
+        None => {
+            let port = query_port.context("Set enable-query=true in server.properties")?;
+            ("localhost".to_string(), port)
+        }
+    };
+
+    let stat = query::full_stat(&host, port, timeout).await?;
+    println!("{host}:{port}:");
+    println!("  version: {}", stat.version);
+    if let Some(server) = &stat.plugins.server {
+        println!("  server: {server}");
+    }
+    println!("  game type: {}", stat.game_type);
+    println!("  map: {}", stat.map);
+    println!("  players: {}/{}", stat.online_players, stat.max_players);
+    for player in &stat.players {
+        println!("    {player}");
+    }
+    if !stat.plugins.plugins.is_empty() {
+        println!("  plugins:");
+        for plugin in &stat.plugins.plugins {
+            println!("    {plugin}");
+        }
+    }
+    println!("  motd: {}", stat.motd);
+
+    Ok(())
+}
+
+async fn logs(
+    directory: Utf8PathBuf,
+    settings: &Settings,
+    run: i64,
+    list: bool,
+) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+
+    let config = settings.logs_config();
+    let runs = logs::runs(&config).await?;
+    if list {
+        for run in &runs {
+            let stop = run
+                .stop
+                .map_or_else(|| "-".to_string(), |stop| stop.to_string());
+            println!(
+                "{:>4}  {}  {stop}  {run}  {}",
+                run.id,
+                run.start,
+                run.version.as_deref().unwrap_or("-")
+            );
+        }
+        return Ok(());
+    }
+
+    let Some(selected) = logs::select(&runs, run) else {
+        bail!("No run {run} in {}", config.directory.join(logs::RUNS_PATH));
+    };
+    for line in logs::read_run(&config, &runs, selected).await? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+async fn version(directory: Utf8PathBuf) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+
+    let installed = jar::read_version(SERVER_PATH.into()).await?;
+    println!("{SERVER_PATH}: {}", installed.name);
+    println!("  id: {}", installed.id);
+    println!("  stable: {}", installed.stable);
+    println!("  world version: {}", installed.world_version);
+    println!("  protocol version: {}", installed.protocol_version);
+    println!("  data pack format: {}", installed.pack_version.data());
+    println!(
+        "  resource pack format: {}",
+        installed.pack_version.resource()
+    );
+    if let Some(java_version) = installed.java_version {
+        println!("  java version: {java_version}");
+    }
+
+    if let Some(lock) = Lock::load().await? {
+        println!("locked: {}", lock.server);
+        if let Some(loader) = &lock.loader {
+            println!(
+                "loader: {} (installer {})",
+                loader.version, loader.installer
+            );
+        }
+        if let Some(last_run) = lock.server.last_run {
+            println!("last run: {last_run}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Format an optional value, or `unknown` if it's missing.
+fn or_unknown(value: Option<impl std::fmt::Display>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |value| value.to_string())
+}
+
+async fn world_info(directory: Utf8PathBuf) -> anyhow::Result<()> {
+    workspace::enter(&directory)?;
+
+    let world = workspace::world_directory().await?;
+    let level = world::read_level(&world).await?;
+
+    println!("{world}:");
+    println!("  level name: {}", or_unknown(level.level_name.as_ref()));
+    println!("  seed: {}", or_unknown(level.seed));
+    println!(
+        "  version: {} (data version {})",
+        or_unknown(level.version_name.as_ref()),
+        or_unknown(level.data_version)
+    );
+    println!("  game type: {}", or_unknown(level.game_type));
+    println!(
+        "  spawn: {}",
+        or_unknown(
+            level
+                .spawn
+                .map(|spawn| format!("{} {} {}", spawn.x, spawn.y, spawn.z))
+        )
+    );
+    if let (Some(day), Some(time)) = (level.day(), level.time_of_day()) {
+        println!("  day time: day {day}, tick {time}");
+    }
+    println!("  last played: {}", or_unknown(level.last_played));
 
     Ok(())
 }
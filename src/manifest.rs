@@ -1,9 +1,16 @@
+use std::fmt;
+
 use jiff::Timestamp;
-use serde::Deserialize;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
 
+/// Location of Mojang's version manifest.
 pub const VERSION_MANIFEST_URL: &str =
     "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
 
+/// The most recent release and snapshot version ids.
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 pub struct Latest {
     pub release: String,
@@ -12,8 +19,10 @@ pub struct Latest {
 
 // TODO: Is making this an enum too strict? What if a new type is added? Use a
 // string instead?
+/// The kind of a version listed in the manifest.
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum Type {
     Release,
     Snapshot,
@@ -21,6 +30,7 @@ pub enum Type {
     OldAlpha,
 }
 
+/// A single version entry in the manifest.
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 pub struct Version {
     pub id: String,
@@ -42,27 +52,184 @@ pub struct VersionManifest {
 }
 
 impl VersionManifest {
+    /// Look up a version by id.
     pub fn version(&self, version: impl AsRef<str>) -> Option<Version> {
         self.versions
             .iter()
             .find(|v| v.id == version.as_ref())
             .cloned()
     }
+
+    /// The id of the most recent version of a type, if the manifest tracks
+    /// one. Old betas and alphas have none.
+    pub fn latest(&self, r#type: &Type) -> Option<&str> {
+        match r#type {
+            Type::Release => Some(&self.latest.release),
+            Type::Snapshot => Some(&self.latest.snapshot),
+            Type::OldBeta | Type::OldAlpha => None,
+        }
+    }
+}
+
+/// A checksum algorithm published alongside downloads.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    /// Used by Mojang.
+    #[default]
+    Sha1,
+    /// Used by Paper.
+    Sha256,
+    /// Used by Purpur.
+    Md5,
+    /// Used by Modrinth.
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Start hashing incrementally.
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    /// Hash `data`, returning the lowercase hex digest.
+    pub fn hex(self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
 }
 
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Sha512 => write!(f, "SHA-512"),
+        }
+    }
+}
+
+/// An incremental hash for any [`HashAlgorithm`].
+#[derive(Debug, Clone)]
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Md5(Md5),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finish hashing, returning the lowercase hex digest.
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// A downloadable artifact with its expected checksum and size.
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+#[non_exhaustive]
 pub struct Download {
-    pub sha1: String,
-    pub size: u64,
+    /// Mojang's metadata only lists SHA-1 checksums, so that's the default.
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    /// Hex digest of the artifact, which Mojang's metadata calls `sha1`.
+    #[serde(rename = "sha1")]
+    pub hash: String,
+    /// Size in bytes, if published.
+    #[serde(default)]
+    pub size: Option<u64>,
     pub url: String,
 }
 
+impl Download {
+    /// An artifact of unknown size at `url`, with a hex digest to verify.
+    pub fn new(algorithm: HashAlgorithm, hash: String, url: String) -> Self {
+        Download {
+            algorithm,
+            hash,
+            size: None,
+            url,
+        }
+    }
+}
+
+/// Downloads available for a version. Only the server is of interest.
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 pub struct Downloads {
     pub server: Download,
 }
 
+/// Per-version metadata, as linked from [`Version::url`].
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 pub struct VersionMetadata {
     pub downloads: Downloads,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const VERSION_MANIFEST: &str = include_str!("../tests/fixtures/version_manifest_v2.json");
+    const VERSION_METADATA: &str = include_str!("../tests/fixtures/1.21.3.json");
+
+    #[test]
+    fn test_version_manifest_latest_release() {
+        let manifest: VersionManifest = serde_json::from_str(VERSION_MANIFEST).unwrap();
+        let version = manifest.version(&manifest.latest.release).unwrap();
+        assert_eq!(version.id, "1.21.3");
+        assert_eq!(version.r#type, Type::Release);
+    }
+
+    #[test_case(Type::Release, Some("1.21.3") ; "release")]
+    #[test_case(Type::Snapshot, Some("1.21.4-rc3") ; "snapshot")]
+    #[test_case(Type::OldBeta, None ; "old beta")]
+    fn test_version_manifest_latest(r#type: Type, expected: Option<&str>) {
+        let manifest: VersionManifest = serde_json::from_str(VERSION_MANIFEST).unwrap();
+        assert_eq!(manifest.latest(&r#type), expected);
+    }
+
+    #[test]
+    fn test_version_manifest_missing_version() {
+        let manifest: VersionManifest = serde_json::from_str(VERSION_MANIFEST).unwrap();
+        assert_eq!(manifest.version("0.0.0"), None);
+    }
+
+    #[test]
+    fn test_version_metadata_server_download() {
+        let metadata: VersionMetadata = serde_json::from_str(VERSION_METADATA).unwrap();
+        let server = metadata.downloads.server;
+        assert_eq!(server.algorithm, HashAlgorithm::Sha1);
+        assert_eq!(server.hash, "45810d238246d90e811d896f87b14695b7fb6839");
+        assert_eq!(server.size, Some(56122038));
+    }
+
+    #[test_case(HashAlgorithm::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d" ; "sha1")]
+    #[test_case(HashAlgorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad" ; "sha256")]
+    #[test_case(HashAlgorithm::Md5, "900150983cd24fb0d6963f7d28e17f72" ; "md5")]
+    #[test_case(HashAlgorithm::Sha512, "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f" ; "sha512")]
+    fn test_hash_algorithm_hex(algorithm: HashAlgorithm, expected: &str) {
+        assert_eq!(algorithm.hex(b"abc"), expected);
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fmt::Write as _,
+    net::SocketAddr,
+    os::unix::process::ExitStatusExt,
+    process::ExitStatus,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use fs_err::tokio as fs;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::server::Console;
+
+/// Output the server prints when ticks take too long.
+const TICK_LAG_PATTERN: &str = "Can't keep up!";
+/// How long a scraper has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest request head accepted, which is plenty for a `GET /metrics`.
+const MAX_REQUEST_LENGTH: usize = 8 * 1024;
+/// Clock ticks per second in `/proc/<pid>/stat`, fixed by the kernel ABI.
+const USER_HZ: f64 = 100.0;
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Everything measured by this process, shared by all its tasks.
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+#[derive(Debug, Clone)]
+struct Metrics {
+    /// The running server process and when it was spawned.
+    process: Option<(u32, Instant)>,
+    ready: bool,
+    spawns: u64,
+    last_exit_code: Option<i32>,
+    tick_lag_events: u64,
+    download_hits: u64,
+    download_misses: u64,
+    backups: u64,
+    last_backup: Option<(Duration, u64)>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            process: None,
+            ready: false,
+            spawns: 0,
+            last_exit_code: None,
+            tick_lag_events: 0,
+            download_hits: 0,
+            download_misses: 0,
+            backups: 0,
+            last_backup: None,
+        }
+    }
+}
+
+fn update(f: impl FnOnce(&mut Metrics)) {
+    f(&mut METRICS.lock().unwrap_or_else(|err| err.into_inner()));
+}
+
+/// Record that the server process was spawned, restarts included.
+pub(crate) fn record_spawn(pid: Option<u32>) {
+    update(|metrics| {
+        metrics.spawns += 1;
+        metrics.process = pid.map(|pid| (pid, Instant::now()));
+        metrics.ready = false;
+    });
+}
+
+/// Record that the server finished starting up.
+pub(crate) fn record_ready() {
+    update(|metrics| metrics.ready = true);
+}
+
+/// Record that the server process exited.
+pub(crate) fn record_exit(status: ExitStatus) {
+    update(|metrics| {
+        metrics.process = None;
+        metrics.ready = false;
+        // Killed by a signal, like `128 + n` in a shell.
+        metrics.last_exit_code = status.code().or(status.signal().map(|signal| 128 + signal));
+    });
+}
+
+/// Count the events of interest in a line of server output.
+pub(crate) fn record_line(line: &str) {
+    if line.contains(TICK_LAG_PATTERN) {
+        update(|metrics| metrics.tick_lag_events += 1);
+    }
+}
+
+/// Record whether `server.jar` was already present or had to be downloaded.
+pub(crate) fn record_download(cached: bool) {
+    update(|metrics| {
+        if cached {
+            metrics.download_hits += 1;
+        } else {
+            metrics.download_misses += 1;
+        }
+    });
+}
+
+/// Record how long a backup took and how large it was.
+pub(crate) fn record_backup(duration: Duration, size: u64) {
+    update(|metrics| {
+        metrics.backups += 1;
+        metrics.last_backup = Some((duration, size));
+    });
+}
+
+/// Resource usage of a process, read from `/proc`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Usage {
+    resident_bytes: u64,
+    cpu_seconds: f64,
+}
+
+async fn read_usage(pid: u32) -> anyhow::Result<Usage> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).await?;
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).await?;
+    parse_usage(&status, &stat).context("Invalid /proc stats")
+}
+
+fn parse_usage(status: &str, stat: &str) -> Option<Usage> {
+    let resident_kib: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    // The command name may contain spaces, so count fields after it: utime
+    // and stime are the 14th and 15th fields overall.
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(Usage {
+        resident_bytes: resident_kib * 1024,
+        cpu_seconds: (utime + stime) as f64 / USER_HZ,
+    })
+}
+
+/// Render every metric in the Prometheus text format.
+async fn render(console: &Console) -> String {
+    // Copy everything out so the lock isn't held across reading `/proc`.
+    let metrics = METRICS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    let process = metrics.process;
+    let usage = match process {
+        Some((pid, _)) => read_usage(pid)
+            .await
+            .inspect_err(|err| tracing::debug!("Unable to read server process usage: {err:#}"))
+            .ok(),
+        None => None,
+    };
+
+    let mut out = Exposition::new();
+    out.gauge(
+        "mc_server_up",
+        "Whether the server process is running.",
+        u8::from(process.is_some()),
+    );
+    out.gauge(
+        "mc_server_ready",
+        "Whether the server has finished starting up.",
+        u8::from(metrics.ready),
+    );
+    out.counter(
+        "mc_server_restarts_total",
+        "Times the server process was started again.",
+        metrics.spawns.saturating_sub(1),
+    );
+    if let Some(code) = metrics.last_exit_code {
+        out.gauge(
+            "mc_server_last_exit_code",
+            "Exit code of the last server process, or 128 plus the signal that killed it.",
+            code,
+        );
+    }
+    if let Some((_, started)) = process {
+        out.gauge(
+            "mc_server_uptime_seconds",
+            "Seconds since the server process started.",
+            started.elapsed().as_secs_f64(),
+        );
+    }
+    out.gauge(
+        "mc_players_online",
+        "Players online, tracked from the server output.",
+        console.players().len(),
+    );
+    out.counter(
+        "mc_tick_lag_events_total",
+        "Times the server reported it can't keep up.",
+        metrics.tick_lag_events,
+    );
+    if let Some(usage) = usage {
+        out.gauge(
+            "mc_process_resident_memory_bytes",
+            "Resident memory of the server process.",
+            usage.resident_bytes,
+        );
+        out.counter(
+            "mc_process_cpu_seconds_total",
+            "CPU time used by the server process.",
+            usage.cpu_seconds,
+        );
+    }
+    out.header(
+        "mc_downloads_total",
+        "counter",
+        "Server jar fetches, by whether it was already downloaded.",
+    );
+    out.sample("mc_downloads_total{result=\"hit\"}", metrics.download_hits);
+    out.sample(
+        "mc_downloads_total{result=\"miss\"}",
+        metrics.download_misses,
+    );
+    out.counter("mc_backups_total", "Backups taken.", metrics.backups);
+    if let Some((duration, size)) = metrics.last_backup {
+        out.gauge(
+            "mc_last_backup_duration_seconds",
+            "How long the last backup took.",
+            duration.as_secs_f64(),
+        );
+        out.gauge(
+            "mc_last_backup_size_bytes",
+            "Size of the last backup.",
+            size,
+        );
+    }
+    out.0
+}
+
+/// A Prometheus text format exposition being written.
+struct Exposition(String);
+
+impl Exposition {
+    fn new() -> Self {
+        Exposition(String::new())
+    }
+
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, value: impl std::fmt::Display) {
+        let _ = writeln!(self.0, "{name} {value}");
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl std::fmt::Display) {
+        self.header(name, "gauge", help);
+        self.sample(name, value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: impl std::fmt::Display) {
+        self.header(name, "counter", help);
+        self.sample(name, value);
+    }
+}
+
+/// Bind the metrics endpoint.
+pub(crate) async fn bind(address: SocketAddr) -> anyhow::Result<TcpListener> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to listen for metrics on {address}"))?;
+    tracing::info!("Serving metrics on http://{address}/metrics");
+    Ok(listener)
+}
+
+/// Answer `GET /metrics` with the current metrics.
+pub(crate) async fn serve(listener: TcpListener, console: Console) {
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                tracing::warn!("Failed to accept metrics connection: {err}");
+                continue;
+            }
+        };
+        let console = console.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &console).await {
+                tracing::debug!("Metrics connection from {address} failed: {err:#}");
+            }
+        });
+    }
+}
+
+async fn respond(stream: TcpStream, console: &Console) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let request_line = tokio::time::timeout(REQUEST_TIMEOUT, read_request(reader))
+        .await
+        .context("Timed out reading request")??;
+
+    let (status, content_type, body) = match request_line.split_whitespace().collect::<Vec<_>>()[..]
+    {
+        ["GET", "/metrics", _] => ("200 OK", CONTENT_TYPE, render(console).await),
+        ["GET", _, _] => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+/// Read a request head, returning its request line.
+async fn read_request(reader: impl AsyncRead + Unpin) -> anyhow::Result<String> {
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LENGTH as u64));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Headers are ignored, but have to be read before responding.
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+    Ok(request_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_usage() {
+        let status = "Name:\tjava\nVmPeak:\t 4000000 kB\nVmRSS:\t  204800 kB\nThreads:\t42\n";
+        let stat = "1234 (java (main)) S 1 1234 1234 0 -1 4194560 5000 0 0 0 250 50 0 0 20 0 42 0 100 4000000000 51200";
+        assert_eq!(
+            parse_usage(status, stat),
+            Some(Usage {
+                resident_bytes: 204800 * 1024,
+                cpu_seconds: 3.0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_usage_invalid() {
+        assert_eq!(parse_usage("Name:\tjava\n", "1234 (java) S"), None);
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let console = Console::new(tx);
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, console));
+        record_download(true);
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE mc_server_up gauge\nmc_server_up 0\n"));
+        assert!(response.contains("mc_downloads_total{result=\"hit\"} "));
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read, Write},
+};
+
+use anyhow::{Context, anyhow, bail};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use serde::{Deserialize, Serialize};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    loader::Loader,
+    lock::Lock,
+    manifest::HashAlgorithm,
+    modrinth::{Hashes, SideSupport},
+    mods::{LockedFile, ModsLock, Target},
+    provider::ServerType,
+};
+
+/// Location of the index in a `.mrpack`.
+pub const INDEX_PATH: &str = "modrinth.index.json";
+/// Files applied on both clients and servers.
+const OVERRIDES: &str = "overrides";
+/// Files applied on servers only, after [`OVERRIDES`].
+const SERVER_OVERRIDES: &str = "server-overrides";
+/// Hosts the format allows files to be downloaded from.
+const ALLOWED_HOSTS: &[&str] = &[
+    "cdn.modrinth.com",
+    "github.com",
+    "raw.githubusercontent.com",
+    "gitlab.com",
+];
+/// Directories besides the mods exported as overrides.
+const EXPORTED_DIRECTORIES: &[&str] = &["config"];
+
+/// Keys of [`Index::dependencies`].
+const MINECRAFT: &str = "minecraft";
+const FABRIC_LOADER: &str = "fabric-loader";
+const QUILT_LOADER: &str = "quilt-loader";
+const FORGE: &str = "forge";
+const NEOFORGE: &str = "neoforge";
+
+/// Contents of `modrinth.index.json`.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub format_version: u32,
+    pub game: String,
+    /// The pack's own version, e.g. `1.0.0`.
+    pub version_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<IndexFile>,
+    /// Minecraft and loader versions, e.g. `minecraft = "1.21.4"`.
+    pub dependencies: BTreeMap<String, String>,
+}
+
+/// A file to download into the instance.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexFile {
+    /// Path relative to the instance, e.g. `mods/lithium-fabric-0.14.3.jar`.
+    pub path: Utf8PathBuf,
+    pub hashes: Hashes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+    /// Where to download the file from. The first from an allowed host is used.
+    pub downloads: Vec<String>,
+    pub file_size: u64,
+}
+
+/// Where a file is needed. Files without one are needed everywhere.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Env {
+    pub client: SideSupport,
+    pub server: SideSupport,
+}
+
+impl IndexFile {
+    pub fn is_for_server(&self) -> bool {
+        self.env
+            .is_none_or(|env| env.server != SideSupport::Unsupported)
+    }
+}
+
+impl Env {
+    /// The env to export, needed everywhere unless known otherwise.
+    fn exported(env: Option<Env>) -> Self {
+        // The format has no way to say a side is unknown.
+        let known = |side| match side {
+            SideSupport::Unknown => SideSupport::Required,
+            side => side,
+        };
+        match env {
+            Some(env) => Env {
+                client: known(env.client),
+                server: known(env.server),
+            },
+            None => Env {
+                client: SideSupport::Required,
+                server: SideSupport::Required,
+            },
+        }
+    }
+}
+
+/// Whether the format allows downloading from `url`.
+fn is_allowed_download(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| {
+        url.scheme() == "https"
+            && url
+                .host_str()
+                .is_some_and(|host| ALLOWED_HOSTS.contains(&host))
+    })
+}
+
+impl Index {
+    /// The server type, Minecraft version and loader version the pack pins.
+    pub fn server(&self) -> anyhow::Result<(ServerType, String, Option<String>)> {
+        let minecraft_version = self
+            .dependencies
+            .get(MINECRAFT)
+            .with_context(|| format!("{INDEX_PATH} doesn't pin a Minecraft version"))?;
+        let mut loaders = self
+            .dependencies
+            .iter()
+            .filter(|(key, _)| *key != MINECRAFT)
+            .map(|(key, version)| {
+                let server_type = match key.as_str() {
+                    FABRIC_LOADER => ServerType::Fabric,
+                    QUILT_LOADER => ServerType::Quilt,
+                    FORGE => ServerType::Forge,
+                    NEOFORGE => ServerType::NeoForge,
+                    _ => bail!("Unsupported modpack dependency: {key}"),
+                };
+                Ok((server_type, version))
+            });
+        let (server_type, loader_version) = match (loaders.next(), loaders.next()) {
+            (None, _) => (ServerType::Vanilla, None),
+            (Some(loader), None) => {
+                let (server_type, version) = loader?;
+                (server_type, Some(version.clone()))
+            }
+            (Some(_), Some(_)) => bail!("Modpack depends on more than one mod loader"),
+        };
+        Ok((server_type, minecraft_version.clone(), loader_version))
+    }
+}
+
+/// A modpack read from a `.mrpack` file.
+#[derive(Debug, Clone)]
+pub struct Pack {
+    pub index: Index,
+    /// Files to write into the workspace, with server overrides last.
+    overrides: Vec<(Utf8PathBuf, Vec<u8>)>,
+}
+
+/// Check that a path from a modpack stays inside the workspace.
+pub(crate) fn check_path(path: &Utf8Path) -> anyhow::Result<()> {
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Utf8Component::Normal(_)));
+    if !normal || path.as_str().is_empty() {
+        bail!("Refusing to write outside the workspace: {path}");
+    }
+    Ok(())
+}
+
+/// Read a `.mrpack` file.
+pub async fn read(path: &Utf8Path) -> anyhow::Result<Pack> {
+    let data = fs::read(path).await?;
+    tokio::task::spawn_blocking(move || parse(&data))
+        .await?
+        .with_context(|| format!("Invalid modpack {path}"))
+}
+
+fn parse(data: &[u8]) -> anyhow::Result<Pack> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let mut index = String::new();
+    archive
+        .by_name(INDEX_PATH)
+        .with_context(|| format!("Missing {INDEX_PATH}"))?
+        .read_to_string(&mut index)?;
+    let index: Index = serde_json::from_str(&index)?;
+    if index.format_version != 1 || index.game != MINECRAFT {
+        bail!(
+            "Unsupported modpack format {} for {}",
+            index.format_version,
+            index.game
+        );
+    }
+    for file in &index.files {
+        check_path(&file.path)?;
+    }
+
+    let mut overrides = Vec::new();
+    for prefix in [OVERRIDES, SERVER_OVERRIDES] {
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = Utf8PathBuf::from(entry.name());
+            let Ok(path) = name.strip_prefix(prefix) else {
+                continue;
+            };
+            check_path(path)?;
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            overrides.push((path.to_path_buf(), content));
+        }
+    }
+    Ok(Pack { index, overrides })
+}
+
+impl Pack {
+    /// The mods lock pinning the pack's server files.
+    pub fn mods_lock(&self, target: &Target) -> anyhow::Result<ModsLock> {
+        let mut lock = ModsLock::new(target);
+        for file in self.index.files.iter().filter(|file| file.is_for_server()) {
+            let url = file
+                .downloads
+                .iter()
+                .find(|url| is_allowed_download(url))
+                .with_context(|| format!("No download from an allowed host for {}", file.path))?;
+            lock.files.push(LockedFile {
+                path: file.path.clone(),
+                url: url.clone(),
+                sha1: file.hashes.sha1.clone(),
+                sha512: file.hashes.sha512.clone(),
+                env: file.env,
+            });
+        }
+        Ok(lock)
+    }
+
+    /// Write the pack's overrides into the workspace, returning their paths.
+    pub async fn apply_overrides(&self) -> anyhow::Result<Vec<&Utf8Path>> {
+        let mut applied = Vec::new();
+        for (path, content) in &self.overrides {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            tracing::debug!("Writing {path}");
+            fs::write(path, content).await?;
+            applied.push(path.as_path());
+        }
+        Ok(applied)
+    }
+}
+
+/// The dependency key and version a modpack pins the locked loader with.
+fn loader_dependency(lock: &Lock) -> anyhow::Result<Option<(&'static str, String)>> {
+    let server = &lock.server;
+    let Some(loader) = Loader::for_server(server.server_type) else {
+        return match server.server_type {
+            ServerType::Vanilla => Ok(None),
+            server_type => bail!("Modpacks can't pin {server_type} servers"),
+        };
+    };
+    let locked = lock
+        .loader
+        .as_ref()
+        .context("No loader in mc.lock, run `mc update` first")?;
+    let key = match loader {
+        Loader::Fabric => FABRIC_LOADER,
+        Loader::Quilt => QUILT_LOADER,
+        Loader::Forge => FORGE,
+        Loader::NeoForge => NEOFORGE,
+    };
+    // Forge is listed without the Minecraft version prefix it has on Maven.
+    let prefix = format!("{}-", server.version);
+    let version = match loader {
+        Loader::Forge => locked
+            .version
+            .strip_prefix(&prefix)
+            .unwrap_or(&locked.version),
+        _ => &locked.version,
+    };
+    Ok(Some((key, version.to_string())))
+}
+
+/// Export the workspace as a `.mrpack`, returning its contents.
+///
+/// Mods from `mods.lock` are listed for download, while other files in the
+/// mods directory and the config directory are bundled as overrides.
+pub async fn export(
+    lock: &Lock,
+    mods: Option<&ModsLock>,
+    name: &str,
+    version_id: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let target = Target::new(&lock.server);
+    let mut dependencies = BTreeMap::from([(MINECRAFT.to_string(), lock.server.version.clone())]);
+    if let Some((key, version)) = loader_dependency(lock)? {
+        dependencies.insert(key.to_string(), version);
+    }
+
+    let mut files = Vec::new();
+    let mods_directory = Utf8PathBuf::from(target.directory());
+    let locked = mods.iter().flat_map(|mods| {
+        let from_mods = mods.mods.iter().map(|locked| {
+            (
+                mods_directory.join(&locked.filename),
+                locked.url.clone(),
+                locked.sha512.clone(),
+                locked.env,
+            )
+        });
+        let from_files = mods.files.iter().map(|file| {
+            (
+                file.path.clone(),
+                file.url.clone(),
+                file.sha512.clone(),
+                file.env,
+            )
+        });
+        from_mods.chain(from_files)
+    });
+    for (path, url, sha512, env) in locked {
+        let data = fs::read(&path)
+            .await
+            .with_context(|| format!("{path} is missing, run `mc mods update` first"))?;
+        files.push(IndexFile {
+            hashes: Hashes {
+                sha512,
+                sha1: HashAlgorithm::Sha1.hex(&data),
+            },
+            env: Some(Env::exported(env)),
+            downloads: vec![url],
+            file_size: data.len() as u64,
+            path,
+        });
+    }
+
+    let index = Index {
+        format_version: 1,
+        game: MINECRAFT.to_string(),
+        version_id: version_id.to_string(),
+        name: name.to_string(),
+        summary: None,
+        files,
+        dependencies,
+    };
+    tokio::task::spawn_blocking(move || write_pack(&index, &mods_directory)).await?
+}
+
+fn write_pack(index: &Index, mods_directory: &Utf8Path) -> anyhow::Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    writer.start_file(INDEX_PATH, options)?;
+    writer.write_all(serde_json::to_string_pretty(index)?.as_bytes())?;
+
+    let mut directories = vec![mods_directory];
+    directories.extend(EXPORTED_DIRECTORIES.iter().map(Utf8Path::new));
+    for directory in directories {
+        let mut paths = Vec::new();
+        collect_files(directory, &mut paths)?;
+        for path in paths {
+            if index.files.iter().any(|file| file.path == path) {
+                continue;
+            }
+            tracing::debug!("Adding {path} to overrides");
+            writer.start_file(format!("{OVERRIDES}/{path}"), options)?;
+            writer.write_all(&fs_err::read(&path)?)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Recursively list the regular files under `directory`, if it exists.
+fn collect_files(directory: &Utf8Path, paths: &mut Vec<Utf8PathBuf>) -> anyhow::Result<()> {
+    let entries = match fs_err::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries = entries.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("Non UTF-8 file name: {name:?}"))?;
+        let path = directory.join(name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&path, paths)?;
+        } else if file_type.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const PACK: &[u8] = include_bytes!("../tests/fixtures/example.mrpack");
+
+    #[test]
+    fn test_parse() {
+        let pack = parse(PACK).unwrap();
+        assert_eq!(pack.index.name, "Example Pack");
+        assert_eq!(
+            pack.index.server().unwrap(),
+            (
+                ServerType::Fabric,
+                "1.21.4".to_string(),
+                Some("0.16.9".to_string())
+            )
+        );
+        let paths: Vec<_> = pack
+            .overrides
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            ["config/lithium.properties", "config/lithium.properties"]
+        );
+        // Server overrides are applied last, so they win.
+        assert_eq!(pack.overrides[1].1, b"mixin.ai=false\n");
+    }
+
+    #[test]
+    fn test_mods_lock_server_only() {
+        let pack = parse(PACK).unwrap();
+        let (server_type, minecraft_version, _) = pack.index.server().unwrap();
+        let target = Target {
+            server_type,
+            minecraft_version,
+        };
+        let lock = pack.mods_lock(&target).unwrap();
+        let paths: Vec<_> = lock.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["mods/lithium-fabric-0.14.3.jar"]);
+        assert_eq!(
+            lock.files[0].env.map(|env| env.server),
+            Some(SideSupport::Required)
+        );
+    }
+
+    #[test_case("mods/lithium.jar", true ; "relative")]
+    #[test_case("../server.jar", false ; "parent")]
+    #[test_case("/etc/passwd", false ; "absolute")]
+    #[test_case("mods/../../server.jar", false ; "nested parent")]
+    fn test_check_path(path: &str, ok: bool) {
+        assert_eq!(check_path(Utf8Path::new(path)).is_ok(), ok);
+    }
+
+    #[test_case("https://cdn.modrinth.com/data/gvQqBUqZ/lithium.jar", true ; "modrinth")]
+    #[test_case("https://github.com/owner/repo/releases/download/v1/a.jar", true ; "github")]
+    #[test_case("http://cdn.modrinth.com/data/gvQqBUqZ/lithium.jar", false ; "plain http")]
+    #[test_case("https://example.com/lithium.jar", false ; "other host")]
+    #[test_case("https://cdn.modrinth.com.example.com/a.jar", false ; "lookalike host")]
+    fn test_is_allowed_download(url: &str, allowed: bool) {
+        assert_eq!(is_allowed_download(url), allowed);
+    }
+
+    #[test]
+    fn test_env_exported() {
+        let server_only = Env {
+            client: SideSupport::Unsupported,
+            server: SideSupport::Required,
+        };
+        assert_eq!(Env::exported(Some(server_only)), server_only);
+        let unknown = Env {
+            client: SideSupport::Unknown,
+            server: SideSupport::Optional,
+        };
+        assert_eq!(Env::exported(Some(unknown)).client, SideSupport::Required);
+        assert_eq!(Env::exported(None).server, SideSupport::Required);
+    }
+
+    #[test]
+    fn test_server_multiple_loaders() {
+        let mut index = parse(PACK).unwrap().index;
+        index
+            .dependencies
+            .insert(FORGE.to_string(), "54.0.26".to_string());
+        assert!(index.server().is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::bail;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::fetch;
+
+/// Location of the Modrinth API.
+pub const MODRINTH_API_URL: &str = "https://api.modrinth.com/v2";
+
+/// A client for the Modrinth API.
+#[derive(Debug, Clone)]
+pub struct Modrinth {
+    client: Client,
+    url: String,
+}
+
+/// Whether a project works on one side, client or server.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SideSupport {
+    Required,
+    Optional,
+    Unsupported,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+/// A mod, plugin or other project.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    #[serde(default)]
+    pub client_side: SideSupport,
+    #[serde(default)]
+    pub server_side: SideSupport,
+}
+
+/// How stable a project version is.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionType {
+    Release,
+    Beta,
+    Alpha,
+}
+
+/// A published version of a project.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    /// The author's version number, e.g. `mc1.21.4-0.14.3`.
+    pub version_number: String,
+    pub version_type: VersionType,
+    pub files: Vec<File>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+impl Version {
+    /// The file to install, which is the primary one if any is marked.
+    pub fn primary_file(&self) -> Option<&File> {
+        self.files
+            .iter()
+            .find(|file| file.primary)
+            .or(self.files.first())
+    }
+}
+
+/// A file of a project version.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct File {
+    pub hashes: Hashes,
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub primary: bool,
+    pub size: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct Hashes {
+    pub sha512: String,
+    pub sha1: String,
+}
+
+/// How a version relates to another project.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyType {
+    Required,
+    Optional,
+    Incompatible,
+    /// Bundled in the version's own file.
+    Embedded,
+}
+
+/// Another project a version depends on, by version or by project.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct Dependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    pub dependency_type: DependencyType,
+}
+
+/// The version to install from a list ordered newest first, preferring releases.
+pub fn select_version(versions: &[Version]) -> Option<&Version> {
+    versions
+        .iter()
+        .find(|version| version.version_type == VersionType::Release)
+        .or(versions.first())
+}
+
+impl Modrinth {
+    /// A client for the Modrinth API at `url`, usually [`MODRINTH_API_URL`].
+    pub fn new(url: impl Into<String>) -> anyhow::Result<Self> {
+        Ok(Modrinth {
+            client: fetch::client()?,
+            url: url.into(),
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<T> {
+        let url = format!("{}{path}", self.url);
+        let response = self.client.get(&url).query(query).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            bail!("Not found on Modrinth: {path}");
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// Look up a project by id or slug.
+    pub async fn project(&self, id: &str) -> anyhow::Result<Project> {
+        tracing::debug!("Fetching Modrinth project {id}");
+        self.get(&format!("/project/{id}"), &[]).await
+    }
+
+    /// Versions of a project for any of `loaders` and the given Minecraft
+    /// version, newest first.
+    pub async fn versions(
+        &self,
+        project: &str,
+        loaders: &[&str],
+        minecraft_version: &str,
+    ) -> anyhow::Result<Vec<Version>> {
+        tracing::debug!("Fetching Modrinth versions of {project} for {minecraft_version}");
+        self.get(
+            &format!("/project/{project}/version"),
+            &[
+                ("loaders", serde_json::to_string(loaders)?),
+                (
+                    "game_versions",
+                    serde_json::to_string(&[minecraft_version])?,
+                ),
+            ],
+        )
+        .await
+    }
+
+    /// Look up a version by id.
+    pub async fn version(&self, id: &str) -> anyhow::Result<Version> {
+        tracing::debug!("Fetching Modrinth version {id}");
+        self.get(&format!("/version/{id}"), &[]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSIONS: &str = include_str!("../tests/fixtures/modrinth_versions.json");
+
+    #[test]
+    fn test_select_version() {
+        let versions: Vec<Version> = serde_json::from_str(VERSIONS).unwrap();
+        let version = select_version(&versions).unwrap();
+        assert_eq!(version.version_number, "mc1.21.4-0.14.3");
+        let file = version.primary_file().unwrap();
+        assert_eq!(file.filename, "lithium-fabric-0.14.3.jar");
+        assert_eq!(
+            version.dependencies[0].dependency_type,
+            DependencyType::Required
+        );
+    }
+
+    #[test]
+    fn test_select_version_prerelease_only() {
+        let versions: Vec<Version> = serde_json::from_str(VERSIONS).unwrap();
+        let betas = &versions[..1];
+        assert_eq!(
+            select_version(betas).unwrap().version_type,
+            VersionType::Beta
+        );
+        assert_eq!(select_version(&[]), None);
+    }
+}
@@ -0,0 +1,736 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io::ErrorKind,
+};
+
+use anyhow::{Context, anyhow, bail};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use fs_err::tokio as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fetch,
+    lock::{Lock, LockedServer},
+    manifest::HashAlgorithm,
+    modpack::{self, Env},
+    modrinth::{self, DependencyType, Modrinth, SideSupport},
+    provider::ServerType,
+    workspace,
+};
+
+/// Location of the mods lock file, relative to the workspace.
+pub const MODS_LOCK_PATH: &str = "mods.lock";
+
+const MODS_LOCK_HEADER: &str =
+    "# This file is generated by mc. Run `mc mods` to change the installed mods.";
+
+/// The server mods and plugins have to be compatible with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Target {
+    pub server_type: ServerType,
+    /// Minecraft version id, e.g. `1.21.4`.
+    pub minecraft_version: String,
+}
+
+impl Target {
+    pub fn new(server: &LockedServer) -> Self {
+        Target {
+            server_type: server.server_type,
+            minecraft_version: server.version.clone(),
+        }
+    }
+
+    /// Modrinth loaders whose mods or plugins the server can load.
+    pub fn loaders(&self) -> anyhow::Result<&'static [&'static str]> {
+        Ok(match self.server_type {
+            ServerType::Vanilla => bail!("Vanilla servers can't load mods or plugins"),
+            ServerType::Paper => &["paper", "spigot", "bukkit"],
+            ServerType::Folia => &["folia"],
+            ServerType::Purpur => &["purpur", "paper", "spigot", "bukkit"],
+            ServerType::Fabric => &["fabric"],
+            // Quilt loads Fabric mods too.
+            ServerType::Quilt => &["quilt", "fabric"],
+            ServerType::Forge => &["forge"],
+            ServerType::NeoForge => &["neoforge"],
+        })
+    }
+
+    /// Where the server loads mods or plugins from, relative to the workspace.
+    pub fn directory(&self) -> &'static str {
+        match self.server_type {
+            ServerType::Paper | ServerType::Folia | ServerType::Purpur => "plugins",
+            _ => "mods",
+        }
+    }
+}
+
+/// Contents of `mods.lock`, pinning the exact set of installed mods.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct ModsLock {
+    /// The server type the mods were resolved for.
+    #[serde(rename = "type")]
+    pub server_type: ServerType,
+    /// The Minecraft version the mods were resolved for.
+    pub minecraft_version: String,
+    #[serde(default, rename = "mod", skip_serializing_if = "Vec::is_empty")]
+    pub mods: Vec<LockedMod>,
+    /// Files installed from a modpack rather than resolved from Modrinth.
+    #[serde(default, rename = "file", skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<LockedFile>,
+}
+
+/// A pinned version of a Modrinth project and the file installed for it.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct LockedMod {
+    pub slug: String,
+    pub project_id: String,
+    pub version_id: String,
+    /// The author's version number, e.g. `mc1.21.4-0.14.3`.
+    pub version: String,
+    pub filename: String,
+    pub url: String,
+    pub sha512: String,
+    /// Whether this was only installed as a dependency of other mods.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dependency: bool,
+    /// Project ids of the mods this one requires.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Where the mod is needed, as the project declares it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+}
+
+/// A file pinned by a modpack, installed at an exact path.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct LockedFile {
+    /// Path relative to the workspace, e.g. `mods/lithium-fabric-0.14.3.jar`.
+    pub path: Utf8PathBuf,
+    pub url: String,
+    pub sha1: String,
+    pub sha512: String,
+    /// Where the file is needed, as the modpack declares it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+}
+
+impl LockedMod {
+    /// Whether `id` names this mod, by slug or project id.
+    pub fn is(&self, id: &str) -> bool {
+        self.slug == id || self.project_id == id
+    }
+}
+
+/// A mod added, updated or removed between two versions of `mods.lock`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Changed<'a> {
+    Added(&'a LockedMod),
+    Updated {
+        from: &'a LockedMod,
+        to: &'a LockedMod,
+    },
+    Removed(&'a LockedMod),
+}
+
+/// A change to the installed mods, naming projects by slug or id.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Change {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    /// Update the given mods, or every explicitly added mod if none are given.
+    Update(Vec<String>),
+}
+
+impl ModsLock {
+    /// An empty lock for the given server.
+    pub fn new(target: &Target) -> Self {
+        ModsLock {
+            server_type: target.server_type,
+            minecraft_version: target.minecraft_version.clone(),
+            mods: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Read the mods lock file from the current directory.
+    ///
+    /// Returns `None` if the file doesn't exist.
+    pub async fn load() -> anyhow::Result<Option<Self>> {
+        let content = match fs::read_to_string(MODS_LOCK_PATH).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let lock: Self =
+            toml::from_str(&content).with_context(|| format!("Invalid {MODS_LOCK_PATH}"))?;
+        lock.check()
+            .with_context(|| format!("Invalid {MODS_LOCK_PATH}"))?;
+        Ok(Some(lock))
+    }
+
+    /// Check that every locked file stays inside the workspace, since the
+    /// lock may have been edited by hand.
+    fn check(&self) -> anyhow::Result<()> {
+        for locked in &self.mods {
+            check_filename(&locked.filename)?;
+        }
+        for file in &self.files {
+            modpack::check_path(&file.path)?;
+        }
+        Ok(())
+    }
+
+    /// Write the mods lock file to the current directory.
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let content = format!("{MODS_LOCK_HEADER}\n{}", toml::to_string(self)?);
+        tracing::debug!("Writing {MODS_LOCK_PATH}");
+        fs::write(MODS_LOCK_PATH, content).await?;
+        Ok(())
+    }
+
+    /// Whether the mods were resolved for a different server.
+    pub fn is_stale(&self, target: &Target) -> bool {
+        self.server_type != target.server_type || self.minecraft_version != target.minecraft_version
+    }
+
+    /// The mods added, updated or removed in `updated`, compared to this lock.
+    pub fn changes<'a>(&'a self, updated: &'a ModsLock) -> Vec<Changed<'a>> {
+        let mut changes = Vec::new();
+        for locked in &updated.mods {
+            match self
+                .mods
+                .iter()
+                .find(|old| old.project_id == locked.project_id)
+            {
+                Some(old) if old.version_id == locked.version_id => {}
+                Some(old) => changes.push(Changed::Updated {
+                    from: old,
+                    to: locked,
+                }),
+                None => changes.push(Changed::Added(locked)),
+            }
+        }
+        for old in &self.mods {
+            if !updated
+                .mods
+                .iter()
+                .any(|locked| locked.project_id == old.project_id)
+            {
+                changes.push(Changed::Removed(old));
+            }
+        }
+        changes
+    }
+
+    fn find(&self, id: &str) -> Option<&LockedMod> {
+        self.mods.iter().find(|locked| locked.is(id))
+    }
+
+    /// Drop dependencies no longer required by any explicitly added mod.
+    fn remove_orphans(&mut self) {
+        let mut needed = BTreeSet::new();
+        let mut pending: Vec<&LockedMod> = self.mods.iter().filter(|m| !m.dependency).collect();
+        while let Some(locked) = pending.pop() {
+            if needed.insert(locked.project_id.clone()) {
+                pending.extend(
+                    locked
+                        .requires
+                        .iter()
+                        .filter_map(|id| self.mods.iter().find(|m| m.project_id == *id)),
+                );
+            }
+        }
+        self.mods
+            .retain(|locked| needed.contains(&locked.project_id));
+    }
+
+    /// Every locked file's path in the workspace, with where to download it
+    /// from and its SHA-512.
+    fn paths<'a>(&'a self, target: &Target) -> Vec<(Utf8PathBuf, &'a str, &'a str)> {
+        let directory = Utf8PathBuf::from(target.directory());
+        let mods = self.mods.iter().map(|locked| {
+            (
+                directory.join(&locked.filename),
+                locked.url.as_str(),
+                locked.sha512.as_str(),
+            )
+        });
+        let files = self
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file.url.as_str(), file.sha512.as_str()));
+        mods.chain(files).collect()
+    }
+}
+
+/// Check that a file name is a single plain path component, so that it
+/// stays inside the mods directory.
+fn check_filename(name: &str) -> anyhow::Result<()> {
+    let mut components = Utf8Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Utf8Component::Normal(normal)), None) if normal == name => Ok(()),
+        _ => bail!("Refusing to write outside the mods directory: {name}"),
+    }
+}
+
+/// What to resolve next: a project at its newest compatible version, or an
+/// exact version required by a dependency.
+#[derive(Debug)]
+enum Wanted {
+    Project(String),
+    Version(String),
+}
+
+/// Resolves mods and their required dependencies into a [`ModsLock`].
+struct Resolver<'a> {
+    modrinth: &'a Modrinth,
+    target: &'a Target,
+    loaders: &'static [&'static str],
+    lock: ModsLock,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(modrinth: &'a Modrinth, target: &'a Target, lock: ModsLock) -> anyhow::Result<Self> {
+        Ok(Resolver {
+            modrinth,
+            target,
+            loaders: target.loaders()?,
+            lock,
+        })
+    }
+
+    /// Record that `parent` requires `project_id`.
+    fn link(&mut self, parent: Option<&str>, project_id: &str) {
+        let Some(parent) = parent else {
+            return;
+        };
+        if let Some(parent) = self.lock.mods.iter_mut().find(|m| m.project_id == parent)
+            && !parent.requires.iter().any(|id| id == project_id)
+        {
+            parent.requires.push(project_id.to_string());
+        }
+    }
+
+    /// Mark an already resolved mod as explicitly added, or link it as a
+    /// dependency. Returns whether `id` was already resolved.
+    fn resolved(&mut self, id: &str, parent: Option<&str>) -> bool {
+        let Some(locked) = self.lock.mods.iter_mut().find(|m| m.is(id)) else {
+            return false;
+        };
+        if parent.is_none() {
+            locked.dependency = false;
+        }
+        let project_id = locked.project_id.clone();
+        self.link(parent, &project_id);
+        true
+    }
+
+    /// Resolve a project and everything it requires.
+    async fn add(&mut self, id: &str) -> anyhow::Result<()> {
+        let Target {
+            server_type,
+            minecraft_version,
+        } = self.target;
+        let mut queue = VecDeque::from([(Wanted::Project(id.to_string()), None::<String>)]);
+        while let Some((wanted, parent)) = queue.pop_front() {
+            let parent = parent.as_deref();
+            let (project, version) = match wanted {
+                Wanted::Project(id) => {
+                    if self.resolved(&id, parent) {
+                        continue;
+                    }
+                    let project = self.modrinth.project(&id).await?;
+                    if self.resolved(&project.id, parent) {
+                        continue;
+                    }
+                    let versions = self
+                        .modrinth
+                        .versions(&project.id, self.loaders, minecraft_version)
+                        .await?;
+                    let version = modrinth::select_version(&versions)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "{} has no version for {server_type} {minecraft_version}",
+                                project.title
+                            )
+                        })?
+                        .clone();
+                    (project, version)
+                }
+                Wanted::Version(id) => {
+                    let version = self.modrinth.version(&id).await?;
+                    if self.resolved(&version.project_id, parent) {
+                        continue;
+                    }
+                    let project = self.modrinth.project(&version.project_id).await?;
+                    (project, version)
+                }
+            };
+
+            if project.server_side == SideSupport::Unsupported {
+                match parent {
+                    None => bail!("{} doesn't run on servers", project.title),
+                    Some(_) => {
+                        tracing::warn!("Skipping client-side dependency {}", project.title);
+                        continue;
+                    }
+                }
+            }
+            for dependency in &version.dependencies {
+                if dependency.dependency_type == DependencyType::Incompatible
+                    && let Some(other) = dependency
+                        .project_id
+                        .as_deref()
+                        .and_then(|id| self.lock.find(id))
+                {
+                    bail!("{} is incompatible with {}", project.title, other.slug);
+                }
+            }
+            let file = version.primary_file().ok_or_else(|| {
+                anyhow!("{} {} has no files", project.title, version.version_number)
+            })?;
+            check_filename(&file.filename)?;
+
+            tracing::debug!("Resolved {} {}", project.slug, version.version_number);
+            self.lock.mods.push(LockedMod {
+                slug: project.slug.clone(),
+                project_id: project.id.clone(),
+                version_id: version.id.clone(),
+                version: version.version_number.clone(),
+                filename: file.filename.clone(),
+                url: file.url.clone(),
+                sha512: file.hashes.sha512.clone(),
+                dependency: parent.is_some(),
+                requires: Vec::new(),
+                env: Some(Env {
+                    client: project.client_side,
+                    server: project.server_side,
+                }),
+            });
+            self.link(parent, &project.id);
+
+            for dependency in &version.dependencies {
+                if dependency.dependency_type != DependencyType::Required {
+                    continue;
+                }
+                let wanted = match (&dependency.version_id, &dependency.project_id) {
+                    (Some(version_id), _) => Wanted::Version(version_id.clone()),
+                    (None, Some(project_id)) => Wanted::Project(project_id.clone()),
+                    (None, None) => {
+                        tracing::warn!(
+                            "Skipping dependency of {} without a Modrinth project",
+                            project.title
+                        );
+                        continue;
+                    }
+                };
+                queue.push_back((wanted, Some(project.id.clone())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolve projects by slug or id, with their required dependencies, and add
+/// them to `lock`. Mods already in the lock keep their versions.
+pub(crate) async fn add(
+    modrinth: &Modrinth,
+    target: &Target,
+    lock: &ModsLock,
+    ids: &[String],
+) -> anyhow::Result<ModsLock> {
+    let mut resolver = Resolver::new(modrinth, target, lock.clone())?;
+    for id in ids {
+        resolver.add(id).await?;
+    }
+    Ok(resolver.lock)
+}
+
+/// Resolve the newest compatible versions of the given mods, or of every
+/// explicitly added mod if none are given.
+///
+/// Their dependencies are resolved again too, unless other mods still
+/// require the locked versions.
+pub(crate) async fn update(
+    modrinth: &Modrinth,
+    target: &Target,
+    lock: &ModsLock,
+    ids: &[String],
+) -> anyhow::Result<ModsLock> {
+    for id in ids {
+        if lock.find(id).is_none() {
+            bail!("{id} isn't installed");
+        }
+    }
+    let updating = |locked: &LockedMod| {
+        !locked.dependency && (ids.is_empty() || ids.iter().any(|id| locked.is(id)))
+    };
+    let explicit: Vec<String> = lock
+        .mods
+        .iter()
+        .filter(|locked| updating(locked))
+        .map(|locked| locked.project_id.clone())
+        .collect();
+
+    let mut kept = ModsLock::new(target);
+    kept.files = lock.files.clone();
+    kept.mods = lock
+        .mods
+        .iter()
+        .filter(|locked| !updating(locked))
+        .cloned()
+        .collect();
+    kept.remove_orphans();
+
+    let mut resolver = Resolver::new(modrinth, target, kept)?;
+    for id in &explicit {
+        resolver.add(id).await?;
+    }
+    Ok(resolver.lock)
+}
+
+/// Remove mods by slug or id, along with dependencies nothing else requires.
+pub(crate) fn remove(lock: &ModsLock, ids: &[String]) -> anyhow::Result<ModsLock> {
+    let mut updated = lock.clone();
+    for id in ids {
+        let locked = updated
+            .mods
+            .iter_mut()
+            .find(|locked| locked.is(id))
+            .ok_or_else(|| anyhow!("{id} isn't installed"))?;
+        // Still installed if something else requires it.
+        locked.dependency = true;
+    }
+    updated.remove_orphans();
+    for id in ids {
+        if let Some(locked) = updated.find(id) {
+            tracing::warn!("Keeping {} as a dependency of other mods", locked.slug);
+        }
+    }
+    Ok(updated)
+}
+
+/// Make the installed mods match `lock`.
+///
+/// Files with the locked SHA-512 are left alone, and the rest are downloaded
+/// to a temporary file, verified and renamed into place. Files in `previous`
+/// but not in `lock` are removed.
+pub async fn sync(
+    modrinth: &Modrinth,
+    target: &Target,
+    previous: Option<&ModsLock>,
+    lock: &ModsLock,
+) -> anyhow::Result<()> {
+    let paths = lock.paths(target);
+    for (path, url, sha512) in &paths {
+        match fs::read(path).await {
+            Ok(data) if HashAlgorithm::Sha512.hex(&data) == *sha512 => {
+                tracing::debug!("Found locked {path}, skipping download");
+                continue;
+            }
+            Ok(_) => tracing::debug!("Existing {path} doesn't match lock"),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        let (directory, name) = match (path.parent(), path.file_name()) {
+            (Some(directory), Some(name)) => (directory, name),
+            _ => bail!("Invalid path in {MODS_LOCK_PATH}: {path}"),
+        };
+        fs::create_dir_all(directory).await?;
+        let temp_path = directory.join(fetch::temp_path(name));
+        tracing::info!("Downloading {path}");
+        fetch::download(
+            modrinth.client(),
+            url,
+            temp_path.as_str(),
+            Some((HashAlgorithm::Sha512, sha512)),
+        )
+        .await?;
+        fs::rename(&temp_path, path).await?;
+    }
+
+    for (old, _, _) in previous.iter().flat_map(|previous| previous.paths(target)) {
+        if paths.iter().any(|(path, _, _)| *path == old) {
+            continue;
+        }
+        match fs::remove_file(&old).await {
+            Ok(()) => tracing::info!("Removed {old}"),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Apply a change to the installed mods of the stopped server in the
+/// current workspace, returning `mods.lock` from before and after.
+///
+/// Mods resolved for a different server have to be updated before anything
+/// else is changed.
+pub async fn apply(modrinth: &Modrinth, change: &Change) -> anyhow::Result<(ModsLock, ModsLock)> {
+    let _lock = workspace::lock().context("Stop the server before changing mods")?;
+    let lock = Lock::load()
+        .await?
+        .context("No mc.lock, run `mc update` to pick a server first")?;
+    let target = Target::new(&lock.server);
+    let installed = ModsLock::load().await?;
+    let current = installed.clone().unwrap_or_else(|| ModsLock::new(&target));
+    if current.is_stale(&target) && !matches!(change, Change::Update(_)) {
+        bail!(
+            "Mods were resolved for {} {}, run `mc mods update` first",
+            current.server_type,
+            current.minecraft_version
+        );
+    }
+
+    let updated = match change {
+        Change::Add(ids) => add(modrinth, &target, &current, ids).await?,
+        Change::Remove(ids) => remove(&current, ids)?,
+        Change::Update(ids) => update(modrinth, &target, &current, ids).await?,
+    };
+    sync(modrinth, &target, installed.as_ref(), &updated).await?;
+    updated.save().await?;
+    Ok((current, updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn locked(slug: &str, dependency: bool, requires: &[&str]) -> LockedMod {
+        LockedMod {
+            slug: slug.to_string(),
+            project_id: format!("id-{slug}"),
+            version_id: format!("version-{slug}"),
+            version: "1.0.0".to_string(),
+            filename: format!("{slug}-1.0.0.jar"),
+            url: format!("https://cdn.modrinth.com/data/id-{slug}/{slug}-1.0.0.jar"),
+            sha512: "00".repeat(64),
+            dependency,
+            requires: requires.iter().map(|id| format!("id-{id}")).collect(),
+            env: None,
+        }
+    }
+
+    fn lock(mods: Vec<LockedMod>) -> ModsLock {
+        ModsLock {
+            server_type: ServerType::Fabric,
+            minecraft_version: "1.21.4".to_string(),
+            mods,
+            files: Vec::new(),
+        }
+    }
+
+    fn slugs(lock: &ModsLock) -> Vec<&str> {
+        lock.mods
+            .iter()
+            .map(|locked| locked.slug.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_mods_lock_round_trip() {
+        let lock = lock(vec![
+            locked("lithium", false, &["fabric-api"]),
+            locked("fabric-api", true, &[]),
+        ]);
+        let content = toml::to_string(&lock).unwrap();
+        assert!(content.contains("[[mod]]"));
+        assert!(content.contains("dependency = true"));
+        assert_eq!(toml::from_str::<ModsLock>(&content).unwrap(), lock);
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = lock(vec![
+            locked("lithium", false, &[]),
+            locked("sodium", false, &[]),
+        ]);
+        let mut new = lock(vec![
+            locked("lithium", false, &[]),
+            locked("iris", false, &[]),
+        ]);
+        assert_eq!(
+            old.changes(&new),
+            [Changed::Added(&new.mods[1]), Changed::Removed(&old.mods[1]),]
+        );
+
+        new.mods[0].version_id = "newer".to_string();
+        assert_eq!(
+            old.changes(&new)[0],
+            Changed::Updated {
+                from: &old.mods[0],
+                to: &new.mods[0],
+            }
+        );
+    }
+
+    #[test]
+    fn test_remove_orphans() {
+        let lock = lock(vec![
+            locked("lithium", false, &["fabric-api"]),
+            locked("ferrite-core", false, &["fabric-api", "cloth-config"]),
+            locked("fabric-api", true, &[]),
+            locked("cloth-config", true, &[]),
+        ]);
+        let removed = remove(&lock, &["ferrite-core".to_string()]).unwrap();
+        assert_eq!(slugs(&removed), ["lithium", "fabric-api"]);
+    }
+
+    #[test]
+    fn test_remove_still_required() {
+        let lock = lock(vec![
+            locked("lithium", false, &["fabric-api"]),
+            locked("fabric-api", false, &[]),
+        ]);
+        let removed = remove(&lock, &["id-fabric-api".to_string()]).unwrap();
+        assert_eq!(slugs(&removed), ["lithium", "fabric-api"]);
+        assert!(removed.mods[1].dependency);
+    }
+
+    #[test]
+    fn test_remove_missing() {
+        assert!(remove(&lock(Vec::new()), &["sodium".to_string()]).is_err());
+    }
+
+    #[test_case("lithium-fabric-0.14.3.jar", true ; "plain")]
+    #[test_case("../server.jar", false ; "parent")]
+    #[test_case("sub/lithium.jar", false ; "nested")]
+    #[test_case("/etc/passwd", false ; "absolute")]
+    #[test_case("", false ; "empty")]
+    fn test_check_filename(name: &str, ok: bool) {
+        assert_eq!(check_filename(name).is_ok(), ok);
+    }
+
+    #[test]
+    fn test_check_lock() {
+        let mut lock = lock(vec![locked("lithium", false, &[])]);
+        assert!(lock.check().is_ok());
+        lock.mods[0].filename = "../server.jar".to_string();
+        assert!(lock.check().is_err());
+    }
+
+    #[test]
+    fn test_target() {
+        let paper = Target {
+            server_type: ServerType::Paper,
+            minecraft_version: "1.21.4".to_string(),
+        };
+        assert_eq!(paper.directory(), "plugins");
+        assert!(paper.loaders().unwrap().contains(&"bukkit"));
+        let vanilla = Target {
+            server_type: ServerType::Vanilla,
+            ..paper
+        };
+        assert!(vanilla.loaders().is_err());
+    }
+}
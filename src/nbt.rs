@@ -0,0 +1,286 @@
+use std::{collections::BTreeMap, io::Read};
+
+use anyhow::{anyhow, bail};
+use flate2::read::GzDecoder;
+
+/// Guard against malicious or corrupt files nesting tags without bound.
+const MAX_DEPTH: usize = 512;
+
+/// A compound tag's named children.
+pub type Compound = BTreeMap<String, Tag>;
+
+/// A single NBT tag, as used by Java Edition.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Look up a child of a compound tag.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(compound) => compound.get(name),
+            _ => None,
+        }
+    }
+
+    /// Any integral tag as an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value.into()),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// A string tag as a `&str`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// An int array tag as a slice.
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Read a gzip compressed NBT file, like `level.dat`.
+///
+/// Returns the name of the root tag (usually empty) and the tag itself.
+pub fn from_gzip(data: &[u8]) -> anyhow::Result<(String, Tag)> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut decompressed)?;
+    from_bytes(&decompressed)
+}
+
+/// Read an uncompressed NBT document.
+///
+/// Returns the name of the root tag (usually empty) and the tag itself.
+pub fn from_bytes(data: &[u8]) -> anyhow::Result<(String, Tag)> {
+    let mut reader = Reader { data };
+    let id = reader.u8()?;
+    if id == 0 {
+        bail!("Empty NBT document");
+    }
+    let name = reader.string()?;
+    let tag = reader.tag(id, 0)?;
+    Ok((name, tag))
+}
+
+/// A cursor over big-endian NBT data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk()
+            .ok_or(anyhow!("Unexpected end of NBT data"))?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(u8::from_be_bytes(self.take()?))
+    }
+
+    fn len(&mut self) -> anyhow::Result<usize> {
+        let len = i32::from_be_bytes(self.take()?);
+        // Each element takes at least a byte, so a longer length is corrupt.
+        match usize::try_from(len) {
+            Ok(len) if len <= self.data.len() => Ok(len),
+            _ => bail!("Invalid NBT length: {len}"),
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+        if len > self.data.len() {
+            bail!("Unexpected end of NBT data");
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        // Java uses modified UTF-8, which only differs from UTF-8 for NUL and
+        // supplementary characters. Those are rare enough to accept lossily.
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn tag(&mut self, id: u8, depth: usize) -> anyhow::Result<Tag> {
+        if depth > MAX_DEPTH {
+            bail!("NBT nested too deeply");
+        }
+        let tag = match id {
+            1 => Tag::Byte(i8::from_be_bytes(self.take()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.take()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.take()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.take()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.take()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.take()?)),
+            7 => {
+                let len = self.len()?;
+                let values = (0..len)
+                    .map(|_| Ok(i8::from_be_bytes(self.take()?)))
+                    .collect::<anyhow::Result<_>>()?;
+                Tag::ByteArray(values)
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let id = self.u8()?;
+                let len = self.len()?;
+                let values = (0..len)
+                    .map(|_| self.tag(id, depth + 1))
+                    .collect::<anyhow::Result<_>>()?;
+                Tag::List(values)
+            }
+            10 => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    compound.insert(name, self.tag(id, depth + 1)?);
+                }
+                Tag::Compound(compound)
+            }
+            11 => {
+                let len = self.len()?;
+                let values = (0..len)
+                    .map(|_| Ok(i32::from_be_bytes(self.take()?)))
+                    .collect::<anyhow::Result<_>>()?;
+                Tag::IntArray(values)
+            }
+            12 => {
+                let len = self.len()?;
+                let values = (0..len)
+                    .map(|_| Ok(i64::from_be_bytes(self.take()?)))
+                    .collect::<anyhow::Result<_>>()?;
+                Tag::LongArray(values)
+            }
+            id => bail!("Unknown NBT tag type: {id}"),
+        };
+        Ok(tag)
+    }
+}
+
+/// Builders for NBT data in tests.
+#[cfg(test)]
+pub(crate) mod build {
+    /// A named tag: its type id, name and payload.
+    pub(crate) fn named(id: u8, name: &str, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![id];
+        data.extend(string(name));
+        data.extend(payload);
+        data
+    }
+
+    /// A string payload.
+    pub(crate) fn string(value: &str) -> Vec<u8> {
+        let mut data = (value.len() as u16).to_be_bytes().to_vec();
+        data.extend(value.as_bytes());
+        data
+    }
+
+    /// A compound payload of named tags.
+    pub(crate) fn compound(children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = children.concat();
+        data.push(0);
+        data
+    }
+
+    /// A list payload of strings.
+    pub(crate) fn strings(values: &[&str]) -> Vec<u8> {
+        let mut data = vec![8];
+        data.extend((values.len() as i32).to_be_bytes());
+        for value in values {
+            data.extend(string(value));
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build::named, *};
+
+    #[test]
+    fn test_from_bytes_compound() {
+        let mut payload = Vec::new();
+        payload.extend(named(3, "DataVersion", &4082i32.to_be_bytes()));
+        payload.extend(named(8, "LevelName", b"\x00\x05world"));
+        payload.extend(named(
+            11,
+            "pos",
+            &[
+                2i32.to_be_bytes(),
+                1i32.to_be_bytes(),
+                (-1i32).to_be_bytes(),
+            ]
+            .concat(),
+        ));
+        payload.extend(named(
+            9,
+            "list",
+            &[&[1][..], &2i32.to_be_bytes(), &[7, 8]].concat(),
+        ));
+        payload.push(0);
+        let data = named(10, "", &payload);
+
+        let (name, tag) = from_bytes(&data).unwrap();
+        assert_eq!(name, "");
+        assert_eq!(tag.get("DataVersion").and_then(Tag::as_i64), Some(4082));
+        assert_eq!(tag.get("LevelName").and_then(Tag::as_str), Some("world"));
+        assert_eq!(
+            tag.get("pos").and_then(Tag::as_int_array),
+            Some(&[1, -1][..])
+        );
+        assert_eq!(
+            tag.get("list"),
+            Some(&Tag::List(vec![Tag::Byte(7), Tag::Byte(8)]))
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_truncated() {
+        let data = named(10, "", &named(3, "DataVersion", &[0, 0]));
+        assert!(from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_length() {
+        let data = named(7, "", &i32::MAX.to_be_bytes());
+        assert!(from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_too_deep() {
+        let mut data = Vec::new();
+        for _ in 0..=MAX_DEPTH + 1 {
+            data.extend([9, 0, 0, 0, 1]);
+        }
+        let err = from_bytes(&named(9, "", &data)).unwrap_err();
+        assert_eq!(err.to_string(), "NBT nested too deeply");
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail};
+use tokio::{net::TcpStream, time::Instant};
+
+use crate::protocol::{self, Handshake, NextState, Reader, Status};
+
+/// Protocol version sent in the handshake. Servers answer status requests
+/// whatever the version, so `-1` is conventional for pings.
+const PING_PROTOCOL_VERSION: i32 = -1;
+
+/// A server's answer to a Server List Ping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong {
+    pub status: Status,
+    /// Round trip time of the ping packet.
+    pub latency: Duration,
+}
+
+/// Split a `host[:port]` address, defaulting the port to `default_port`.
+///
+/// IPv6 addresses with a port must be bracketed, e.g. `[::1]:25565`.
+pub fn parse_address(address: &str, default_port: u16) -> anyhow::Result<(String, u16)> {
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .with_context(|| format!("Invalid address: {address}"))?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port.parse()?,
+            None if rest.is_empty() => default_port,
+            None => bail!("Invalid address: {address}"),
+        };
+        return Ok((host.to_string(), port));
+    }
+    match address.split_once(':') {
+        // More than one colon is a bare IPv6 address.
+        Some((_, port)) if port.contains(':') => Ok((address.to_string(), default_port)),
+        Some((host, port)) => Ok((host.to_string(), port.parse()?)),
+        None => Ok((address.to_string(), default_port)),
+    }
+}
+
+/// Ping a server with the Server List Ping protocol.
+///
+/// Sends a handshake and status request, then measures the round trip of a
+/// ping packet. Fails if the server doesn't answer within `timeout`.
+pub async fn ping(host: &str, port: u16, timeout: Duration) -> anyhow::Result<Pong> {
+    tokio::time::timeout(timeout, exchange(host, port))
+        .await
+        .map_err(|_| anyhow!("Timed out pinging {host}:{port}"))?
+}
+
+async fn exchange(host: &str, port: u16) -> anyhow::Result<Pong> {
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("Failed to connect to {host}:{port}"))?;
+
+    let handshake = Handshake {
+        protocol_version: PING_PROTOCOL_VERSION,
+        server_address: host.to_string(),
+        server_port: port,
+        next_state: NextState::Status,
+    };
+    protocol::write_packet(&mut stream, Handshake::ID, &handshake.encode()).await?;
+    protocol::write_packet(&mut stream, 0x00, &[]).await?;
+
+    let (id, body) = protocol::read_packet(&mut stream).await?;
+    if id != 0x00 {
+        bail!("Expected a status response, got packet {id:#04x}");
+    }
+    let json = Reader::new(&body).string()?;
+    let status = serde_json::from_str(&json).context("Invalid status response")?;
+
+    let payload = rand::random::<i64>().to_be_bytes();
+    let sent = Instant::now();
+    protocol::write_packet(&mut stream, 0x01, &payload).await?;
+    let (id, body) = protocol::read_packet(&mut stream).await?;
+    let latency = sent.elapsed();
+    if id != 0x01 || body != payload {
+        bail!("Invalid pong from {host}:{port}");
+    }
+
+    Ok(Pong { status, latency })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_case::test_case;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::protocol::DEFAULT_PORT;
+
+    #[test_case("localhost", ("localhost", 25565) ; "host")]
+    #[test_case("mc.example.com:25566", ("mc.example.com", 25566) ; "host and port")]
+    #[test_case("[::1]:25566", ("::1", 25566) ; "bracketed ipv6")]
+    #[test_case("[::1]", ("::1", 25565) ; "bracketed ipv6 without port")]
+    #[test_case("::1", ("::1", 25565) ; "bare ipv6")]
+    fn test_parse_address(address: &str, expected: (&str, u16)) {
+        let (host, port) = parse_address(address, DEFAULT_PORT).unwrap();
+        assert_eq!((host.as_str(), port), expected);
+    }
+
+    #[test_case("localhost:port" ; "invalid port")]
+    #[test_case("[::1]25565" ; "missing colon")]
+    fn test_parse_address_invalid(address: &str) {
+        assert!(parse_address(address, DEFAULT_PORT).is_err());
+    }
+
+    /// Answer one Server List Ping like a real server would.
+    async fn fake_server(listener: TcpListener, status: serde_json::Value) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let (id, body) = protocol::read_packet(&mut stream).await.unwrap();
+        assert_eq!(id, Handshake::ID);
+        assert_eq!(
+            Handshake::parse(&body).unwrap().next_state,
+            NextState::Status
+        );
+        let (id, _) = protocol::read_packet(&mut stream).await.unwrap();
+        assert_eq!(id, 0x00);
+
+        let mut body = Vec::new();
+        protocol::put_string(&mut body, &status.to_string());
+        protocol::write_packet(&mut stream, 0x00, &body)
+            .await
+            .unwrap();
+
+        let (id, body) = protocol::read_packet(&mut stream).await.unwrap();
+        assert_eq!(id, 0x01);
+        protocol::write_packet(&mut stream, 0x01, &body)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let status = json!({
+            "version": {"name": "1.21.3", "protocol": 768},
+            "players": {"max": 20, "online": 1, "sample": [{"name": "Steve", "id": "8667ba71-b85a-4004-af54-457a9734eed7"}]},
+            "description": {"text": "A Minecraft Server"},
+        });
+        let server = tokio::spawn(fake_server(listener, status));
+
+        let pong = ping("127.0.0.1", port, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(pong.status.version.name, "1.21.3");
+        assert_eq!(pong.status.motd(), "A Minecraft Server");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        assert!(
+            ping("127.0.0.1", port, Duration::from_secs(5))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::io::ErrorKind;
+
+use anyhow::{Context, bail};
+use fs_err::tokio as fs;
+use jiff::{Timestamp, ToSpan};
+use md5::{Digest, Md5};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::fetch;
+
+/// Location of Mojang's profile API.
+pub const MOJANG_API_URL: &str = "https://api.mojang.com";
+/// Where `mc` caches profiles it looked up, relative to the workspace.
+pub const PROFILES_PATH: &str = "mc.profiles.json";
+/// Where the server caches the profiles of players who have joined.
+const USERCACHE_PATH: &str = "usercache.json";
+/// How long a looked up profile is trusted, the same as the server's cache.
+const CACHE_HOURS: i64 = 30 * 24;
+
+/// A player's name and UUID, as the server records them.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Profile {
+    pub uuid: String,
+    pub name: String,
+}
+
+/// A profile in `usercache.json` or `mc.profiles.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Cached {
+    name: String,
+    uuid: String,
+    /// When `mc` should look the profile up again, absent from the server's
+    /// cache since its entries are refreshed as players join.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<Timestamp>,
+}
+
+/// A profile as returned by the Mojang API, with an unhyphenated UUID.
+#[derive(Debug, Deserialize)]
+struct MojangProfile {
+    id: String,
+    name: String,
+}
+
+/// Resolves player names to the UUIDs the server identifies them by.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    client: Client,
+    url: String,
+    online_mode: bool,
+}
+
+/// The UUID an `online-mode=false` server gives a player, derived from
+/// their name alone.
+pub fn offline_uuid(name: &str) -> String {
+    let mut hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{name}")).into();
+    // Mark it as a version 3, name based, UUID.
+    hash[6] = (hash[6] & 0x0f) | 0x30;
+    hash[8] = (hash[8] & 0x3f) | 0x80;
+    let hex: String = hash.iter().map(|byte| format!("{byte:02x}")).collect();
+    hyphenate(&hex).expect("MD5 is 32 hex digits")
+}
+
+/// Format a UUID given as 32 hex digits with hyphens.
+fn hyphenate(id: &str) -> Option<String> {
+    if id.len() != 32 || !id.chars().all(|char| char.is_ascii_hexdigit()) {
+        return None;
+    }
+    let id = id.to_ascii_lowercase();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &id[..8],
+        &id[8..12],
+        &id[12..16],
+        &id[16..20],
+        &id[20..]
+    ))
+}
+
+async fn load_cache(path: &str) -> anyhow::Result<Vec<Cached>> {
+    match fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content).with_context(|| format!("Invalid {path}")),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn find<'a>(cache: &'a [Cached], name: &str) -> Option<&'a Cached> {
+    cache
+        .iter()
+        .find(|cached| cached.name.eq_ignore_ascii_case(name))
+}
+
+impl Resolver {
+    /// A resolver using the profile API at `url`, usually [`MOJANG_API_URL`].
+    ///
+    /// Servers with `online-mode=false` don't use Mojang accounts, so their
+    /// UUIDs are derived from player names instead.
+    pub fn new(url: impl Into<String>, online_mode: bool) -> anyhow::Result<Self> {
+        Ok(Resolver {
+            client: fetch::client()?,
+            url: url.into(),
+            online_mode,
+        })
+    }
+
+    /// Resolve a player name in the current workspace.
+    ///
+    /// Players who joined are found in the server's own cache, others are
+    /// looked up and cached in [`PROFILES_PATH`].
+    pub async fn resolve(&self, name: &str) -> anyhow::Result<Profile> {
+        if !self.online_mode {
+            return Ok(Profile {
+                uuid: offline_uuid(name),
+                name: name.to_string(),
+            });
+        }
+
+        let now = Timestamp::now();
+        let usercache = load_cache(USERCACHE_PATH).await?;
+        let mut cache = load_cache(PROFILES_PATH).await?;
+        let cached = find(&usercache, name).or_else(|| {
+            find(&cache, name).filter(|cached| cached.expires.is_some_and(|expires| expires > now))
+        });
+        if let Some(cached) = cached {
+            tracing::debug!("Found cached profile for {}", cached.name);
+            return Ok(Profile {
+                uuid: cached.uuid.clone(),
+                name: cached.name.clone(),
+            });
+        }
+
+        let profile = self.lookup(name).await?;
+        cache.retain(|cached| {
+            !cached.name.eq_ignore_ascii_case(name)
+                && cached.expires.is_some_and(|expires| expires > now)
+        });
+        cache.push(Cached {
+            name: profile.name.clone(),
+            uuid: profile.uuid.clone(),
+            expires: Some(now.checked_add(CACHE_HOURS.hours())?),
+        });
+        fs::write(PROFILES_PATH, serde_json::to_string_pretty(&cache)?).await?;
+        Ok(profile)
+    }
+
+    /// Look up a player's profile with the profile API.
+    pub async fn lookup(&self, name: &str) -> anyhow::Result<Profile> {
+        tracing::debug!("Looking up profile for {name}");
+        let url = format!("{}/users/profiles/minecraft/{name}", self.url);
+        let response = self.client.get(&url).send().await?;
+        // The API has answered unknown names with both of these.
+        if matches!(
+            response.status(),
+            StatusCode::NOT_FOUND | StatusCode::NO_CONTENT
+        ) {
+            bail!("No Minecraft account is named {name}");
+        }
+        let profile: MojangProfile = response.error_for_status()?.json().await?;
+        Ok(Profile {
+            uuid: hyphenate(&profile.id)
+                .with_context(|| format!("Invalid UUID for {name}: {}", profile.id))?,
+            name: profile.name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[test_case("Notch", "b50ad385-829d-3141-a216-7e7d7539ba7f")]
+    #[test_case("notch", "42653081-a90e-3475-b3d6-3550cdb43f8e")]
+    fn test_offline_uuid(name: &str, expected: &str) {
+        assert_eq!(offline_uuid(name), expected);
+    }
+
+    #[test_case(
+        "069a79f444e94726a5befca90e38aaf5",
+        Some("069a79f4-44e9-4726-a5be-fca90e38aaf5") ;
+        "lowercase"
+    )]
+    #[test_case(
+        "069A79F444E94726A5BEFCA90E38AAF5",
+        Some("069a79f4-44e9-4726-a5be-fca90e38aaf5") ;
+        "uppercase"
+    )]
+    #[test_case("069a79f4", None ; "too short")]
+    fn test_hyphenate(id: &str, expected: Option<&str>) {
+        assert_eq!(hyphenate(id).as_deref(), expected);
+    }
+
+    /// Answer one HTTP request like the profile API would.
+    async fn fake_api(listener: TcpListener, status: &str, body: &str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        let request = String::from_utf8(request).unwrap();
+        request.lines().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let api = tokio::spawn(fake_api(
+            listener,
+            "200 OK",
+            r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch"}"#,
+        ));
+
+        let resolver = Resolver::new(url, true).unwrap();
+        let profile = resolver.lookup("notch").await.unwrap();
+        assert_eq!(
+            profile,
+            Profile {
+                uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+                name: "Notch".to_string(),
+            }
+        );
+        assert_eq!(
+            api.await.unwrap(),
+            "GET /users/profiles/minecraft/notch HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_unknown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let api = tokio::spawn(fake_api(listener, "404 Not Found", "{}"));
+
+        let resolver = Resolver::new(url, true).unwrap();
+        let err = resolver.lookup("nobody").await.unwrap_err();
+        assert_eq!(err.to_string(), "No Minecraft account is named nobody");
+        api.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_offline_mode() {
+        // Offline servers need no lookup, so an unreachable API is fine.
+        let resolver = Resolver::new("http://127.0.0.1:1", false).unwrap();
+        let profile = resolver.resolve("Notch").await.unwrap();
+        assert_eq!(profile.uuid, "b50ad385-829d-3141-a216-7e7d7539ba7f");
+    }
+}
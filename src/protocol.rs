@@ -0,0 +1,355 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The port servers listen on unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// The largest length a server accepts, the most a 3-byte VarInt holds. A
+/// status response alone can be 32767 UTF-16 units, or about 96 KiB of UTF-8.
+const MAX_PACKET_LENGTH: usize = (1 << 21) - 1;
+
+/// The state a client asks to switch to after the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NextState {
+    /// Server List Ping.
+    Status,
+    /// Joining the game.
+    Login,
+    /// Joining the game after a transfer from another server.
+    Transfer,
+}
+
+/// The first packet of every connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Handshake {
+    pub protocol_version: i32,
+    pub server_address: String,
+    pub server_port: u16,
+    pub next_state: NextState,
+}
+
+impl Handshake {
+    /// Packet id of the handshake.
+    pub const ID: i32 = 0x00;
+
+    /// Parse a handshake packet body.
+    pub fn parse(body: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader { data: body };
+        let protocol_version = reader.varint()?;
+        let server_address = reader.string()?;
+        let server_port = u16::from_be_bytes(reader.take()?);
+        let next_state = match reader.varint()? {
+            1 => NextState::Status,
+            2 => NextState::Login,
+            3 => NextState::Transfer,
+            state => bail!("Unknown next state: {state}"),
+        };
+        Ok(Handshake {
+            protocol_version,
+            server_address,
+            server_port,
+            next_state,
+        })
+    }
+
+    /// Encode a handshake packet body.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_varint(&mut body, self.protocol_version);
+        put_string(&mut body, &self.server_address);
+        body.extend(self.server_port.to_be_bytes());
+        let next_state = match self.next_state {
+            NextState::Status => 1,
+            NextState::Login => 2,
+            NextState::Transfer => 3,
+        };
+        put_varint(&mut body, next_state);
+        body
+    }
+}
+
+/// The JSON status a server answers a Server List Ping with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub version: StatusVersion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub players: Option<StatusPlayers>,
+    /// The MOTD, as a text component or plain string.
+    #[serde(default)]
+    pub description: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforces_secure_chat: Option<bool>,
+}
+
+impl Status {
+    /// The MOTD as plain text, without formatting codes.
+    pub fn motd(&self) -> String {
+        let mut text = String::new();
+        flatten_text(&self.description, &mut text);
+        // `§` starts a two character formatting code, e.g. `§a` for green.
+        let mut plain = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '§' {
+                chars.next();
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+}
+
+/// Append the text of a text component and its children.
+fn flatten_text(component: &serde_json::Value, out: &mut String) {
+    match component {
+        serde_json::Value::String(text) => out.push_str(text),
+        serde_json::Value::Array(components) => {
+            for component in components {
+                flatten_text(component, out);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            if let Some(text) = fields.get("text") {
+                flatten_text(text, out);
+            }
+            if let Some(extra) = fields.get("extra") {
+                flatten_text(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusPlayers {
+    pub max: i64,
+    pub online: i64,
+    /// A few of the players online, if the server shares them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<StatusPlayer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusPlayer {
+    pub name: String,
+    pub id: String,
+}
+
+/// Read a length-prefixed packet, returning its id and body.
+pub(crate) async fn read_packet<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> anyhow::Result<(i32, Vec<u8>)> {
+    let length = read_varint(reader).await?;
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|length| (1..=MAX_PACKET_LENGTH).contains(length))
+        .ok_or(anyhow!("Invalid packet length: {length}"))?;
+    let mut packet = vec![0; length];
+    reader.read_exact(&mut packet).await?;
+
+    let mut reader = Reader { data: &packet };
+    let id = reader.varint()?;
+    Ok((id, reader.data.to_vec()))
+}
+
+/// Write a packet with the given id and body.
+pub(crate) async fn write_packet<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    id: i32,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    let mut packet = Vec::new();
+    put_varint(&mut packet, id);
+    packet.extend(body);
+
+    let mut framed = Vec::new();
+    put_varint(&mut framed, packet.len().try_into()?);
+    framed.extend(packet);
+    writer.write_all(&framed).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read a VarInt directly from a stream.
+async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<i32> {
+    let mut value = 0u32;
+    for position in 0..5 {
+        let byte = reader.read_u8().await?;
+        value |= u32::from(byte & 0x7f) << (7 * position);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    bail!("VarInt is too long")
+}
+
+/// Append a VarInt: 7 bits at a time, least significant first.
+pub(crate) fn put_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+/// Append a VarInt length-prefixed UTF-8 string.
+pub(crate) fn put_string(buf: &mut Vec<u8>, value: &str) {
+    put_varint(buf, value.len() as i32);
+    buf.extend(value.as_bytes());
+}
+
+/// A cursor over a packet body.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk()
+            .ok_or(anyhow!("Unexpected end of packet"))?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    pub fn varint(&mut self) -> anyhow::Result<i32> {
+        let mut value = 0u32;
+        for position in 0..5 {
+            let [byte] = self.take()?;
+            value |= u32::from(byte & 0x7f) << (7 * position);
+            if byte & 0x80 == 0 {
+                return Ok(value as i32);
+            }
+        }
+        bail!("VarInt is too long")
+    }
+
+    pub fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.varint()?;
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= self.data.len())
+            .ok_or(anyhow!("Invalid string length: {len}"))?;
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_case::test_case;
+
+    #[test_case(0, &[0x00] ; "zero")]
+    #[test_case(1, &[0x01] ; "one")]
+    #[test_case(128, &[0x80, 0x01] ; "two bytes")]
+    #[test_case(25565, &[0xdd, 0xc7, 0x01] ; "port")]
+    #[test_case(2147483647, &[0xff, 0xff, 0xff, 0xff, 0x07] ; "max")]
+    #[test_case(-1, &[0xff, 0xff, 0xff, 0xff, 0x0f] ; "negative")]
+    fn test_varint(value: i32, encoded: &[u8]) {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, value);
+        assert_eq!(buf, encoded);
+        assert_eq!(Reader::new(encoded).varint().unwrap(), value);
+    }
+
+    #[test]
+    fn test_varint_too_long() {
+        assert!(Reader::new(&[0xff; 6]).varint().is_err());
+    }
+
+    #[test]
+    fn test_handshake_round_trip() {
+        let handshake = Handshake {
+            protocol_version: 768,
+            server_address: "mc.example.com".to_string(),
+            server_port: 25565,
+            next_state: NextState::Login,
+        };
+        assert_eq!(Handshake::parse(&handshake.encode()).unwrap(), handshake);
+    }
+
+    #[tokio::test]
+    async fn test_packet_round_trip() {
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 0x01, &42i64.to_be_bytes())
+            .await
+            .unwrap();
+        assert_eq!(buf[..2], [9, 0x01]);
+        let (id, body) = read_packet(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(id, 0x01);
+        assert_eq!(body, 42i64.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_packet_largest_status() {
+        let mut body = Vec::new();
+        put_string(&mut body, &"€".repeat(32767));
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 0x00, &body).await.unwrap();
+        let (_, read) = read_packet(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(read, body);
+    }
+
+    #[tokio::test]
+    async fn test_packet_too_long() {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, 1 << 21);
+        assert!(read_packet(&mut buf.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn test_status_deserialize() {
+        let status: Status = serde_json::from_str(
+            r#"{
+                "version": {"name": "1.21.3", "protocol": 768},
+                "players": {"max": 20, "online": 1, "sample": [{"name": "Steve", "id": "8667ba71-b85a-4004-af54-457a9734eed7"}]},
+                "description": {"text": "A Minecraft Server"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(status.version.protocol, 768);
+        let players = status.players.unwrap();
+        assert_eq!(players.online, 1);
+        assert_eq!(players.sample[0].name, "Steve");
+    }
+
+    #[test_case(json!("§aA §lMinecraft§r Server"), "A Minecraft Server" ; "legacy string")]
+    #[test_case(json!({"text": "A ", "extra": [{"text": "Minecraft", "bold": true}, " Server"]}), "A Minecraft Server" ; "component")]
+    #[test_case(json!([{"text": "A"}, " Minecraft Server"]), "A Minecraft Server" ; "array")]
+    fn test_status_motd(description: serde_json::Value, expected: &str) {
+        let status = Status {
+            version: StatusVersion {
+                name: "1.21.3".to_string(),
+                protocol: 768,
+            },
+            players: None,
+            description,
+            favicon: None,
+            enforces_secure_chat: None,
+        };
+        assert_eq!(status.motd(), expected);
+    }
+}
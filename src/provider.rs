@@ -0,0 +1,182 @@
+mod paper;
+mod purpur;
+mod vanilla;
+
+use std::{cmp::Ordering, fmt};
+
+use anyhow::bail;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{fetch::Fetch, lock::Lock, manifest::Download};
+
+use paper::Paper;
+use purpur::Purpur;
+use vanilla::Vanilla;
+
+/// Where a server jar comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ServerType {
+    /// Mojang's server.
+    #[default]
+    Vanilla,
+    /// Paper, from the PaperMC API.
+    Paper,
+    /// Folia, Paper's regionized multithreading fork.
+    Folia,
+    /// Purpur, a Paper fork.
+    Purpur,
+    /// Mojang's server run through the Fabric mod loader.
+    Fabric,
+    /// Mojang's server run through the Quilt mod loader.
+    Quilt,
+    /// Mojang's server run through the Forge mod loader.
+    Forge,
+    /// Mojang's server run through the NeoForge mod loader.
+    #[serde(rename = "neoforge")]
+    NeoForge,
+}
+
+impl ServerType {
+    /// Every server type, in the order they're listed in `--help`.
+    pub const ALL: &[ServerType] = &[
+        ServerType::Vanilla,
+        ServerType::Paper,
+        ServerType::Folia,
+        ServerType::Purpur,
+        ServerType::Fabric,
+        ServerType::Quilt,
+        ServerType::Forge,
+        ServerType::NeoForge,
+    ];
+
+    /// The name used in `mc.toml` and on the command line, e.g. `neoforge`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ServerType::Vanilla => "vanilla",
+            ServerType::Paper => "paper",
+            ServerType::Folia => "folia",
+            ServerType::Purpur => "purpur",
+            ServerType::Fabric => "fabric",
+            ServerType::Quilt => "quilt",
+            ServerType::Forge => "forge",
+            ServerType::NeoForge => "neoforge",
+        }
+    }
+
+    pub fn is_vanilla(&self) -> bool {
+        *self == ServerType::Vanilla
+    }
+
+    /// Whether `server.jar` is Mojang's, possibly launched through a mod loader.
+    pub fn runs_vanilla_jar(self) -> bool {
+        matches!(
+            self,
+            ServerType::Vanilla
+                | ServerType::Fabric
+                | ServerType::Quilt
+                | ServerType::Forge
+                | ServerType::NeoForge
+        )
+    }
+
+    /// Resolve a server build with the provider for this type.
+    pub async fn resolve(
+        self,
+        client: &Client,
+        fetch: &Fetch,
+        lock: Option<&Lock>,
+    ) -> anyhow::Result<Build> {
+        match self {
+            ServerType::Vanilla
+            | ServerType::Fabric
+            | ServerType::Quilt
+            | ServerType::Forge
+            | ServerType::NeoForge => Vanilla.resolve(client, fetch, lock).await,
+            ServerType::Paper => Paper::new("paper").resolve(client, fetch, lock).await,
+            ServerType::Folia => Paper::new("folia").resolve(client, fetch, lock).await,
+            ServerType::Purpur => Purpur.resolve(client, fetch, lock).await,
+        }
+    }
+}
+
+impl fmt::Display for ServerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A server jar resolved by a [`Provider`], ready to download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Build {
+    /// Minecraft version id, e.g. `1.21.3`.
+    pub version: String,
+    /// Build of the version, for server types with several builds per version.
+    pub build: Option<String>,
+    /// SHA-1 of Mojang's version metadata, for vanilla servers.
+    pub metadata_sha1: Option<String>,
+    pub download: Download,
+}
+
+/// A source of server jars.
+pub(crate) trait Provider {
+    /// Resolve what to download for `fetch`.
+    ///
+    /// The current lock, of any server type, is used to refuse downgrading
+    /// the world past its last run version.
+    fn resolve(
+        &self,
+        client: &Client,
+        fetch: &Fetch,
+        lock: Option<&Lock>,
+    ) -> impl Future<Output = anyhow::Result<Build>> + Send;
+}
+
+/// Parse a release version id like `1.21.4` into its numeric parts.
+///
+/// Returns `None` for snapshots, pre-releases and release candidates.
+pub(crate) fn parse_release(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Order release version ids numerically, so that `1.21.10` follows `1.21.9`.
+pub(crate) fn compare_releases(a: &str, b: &str) -> Option<Ordering> {
+    Some(parse_release(a)?.cmp(&parse_release(b)?))
+}
+
+/// Refuse to move a world to a release older than the one that last ran it.
+///
+/// For providers without release dates, unlike Mojang's manifest.
+fn check_downgrade(target: &str, lock: Option<&Lock>) -> anyhow::Result<()> {
+    let Some(last_run) = lock.and_then(|lock| lock.server.last_run.as_ref()) else {
+        return Ok(());
+    };
+    match compare_releases(target, last_run) {
+        Some(Ordering::Less) => bail!("Refusing to downgrade world from {last_run} to {target}"),
+        Some(_) => Ok(()),
+        None => {
+            tracing::warn!(
+                "Unable to compare {target} with last run version {last_run}, skipping downgrade check"
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("1.21.10", "1.21.9", Some(Ordering::Greater) ; "numeric")]
+    #[test_case("1.21", "1.21.1", Some(Ordering::Less) ; "shorter")]
+    #[test_case("1.21.4", "1.21.4", Some(Ordering::Equal) ; "equal")]
+    #[test_case("1.21.4-rc3", "1.21.4", None ; "release candidate")]
+    #[test_case("24w45a", "1.21.4", None ; "snapshot")]
+    fn test_compare_releases(a: &str, b: &str, expected: Option<Ordering>) {
+        assert_eq!(compare_releases(a, b), expected);
+    }
+}
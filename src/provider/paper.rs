@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail};
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::{Build, Provider, check_downgrade, compare_releases};
+use crate::{
+    fetch::Fetch,
+    lock::Lock,
+    manifest::{Download, HashAlgorithm, Type},
+};
+
+/// Location of the PaperMC downloads API.
+pub const PAPER_API_URL: &str = "https://fill.papermc.io/v3";
+
+/// The download of a build that is the server itself.
+const SERVER_DOWNLOAD: &str = "server:default";
+
+/// A project published through the PaperMC API, e.g. Paper or Folia.
+#[derive(Debug, Clone, Copy)]
+pub struct Paper {
+    project: &'static str,
+}
+
+impl Paper {
+    pub fn new(project: &'static str) -> Self {
+        Paper { project }
+    }
+}
+
+/// A project's versions, grouped by family, e.g. `1.21` has `1.21.4`.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Project {
+    versions: BTreeMap<String, Vec<String>>,
+}
+
+impl Project {
+    /// Release versions, newest first.
+    fn releases(&self) -> Vec<&str> {
+        let mut releases: Vec<_> = self
+            .versions
+            .values()
+            .flatten()
+            .map(String::as_str)
+            .filter(|version| super::parse_release(version).is_some())
+            .collect();
+        releases.sort_by(|a, b| compare_releases(b, a).unwrap_or(std::cmp::Ordering::Equal));
+        releases
+    }
+}
+
+/// How far along a build is. Only stable builds are picked automatically.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum Channel {
+    Alpha,
+    Beta,
+    Stable,
+    Recommended,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct PaperBuild {
+    id: u64,
+    channel: Channel,
+    downloads: BTreeMap<String, PaperDownload>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct PaperDownload {
+    checksums: Checksums,
+    size: u64,
+    url: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Checksums {
+    sha256: String,
+}
+
+/// The newest stable build, if any.
+fn latest_stable(builds: &[PaperBuild]) -> Option<&PaperBuild> {
+    builds
+        .iter()
+        .filter(|build| matches!(build.channel, Channel::Stable | Channel::Recommended))
+        .max_by_key(|build| build.id)
+}
+
+impl Paper {
+    async fn builds(&self, client: &Client, version: &str) -> anyhow::Result<Vec<PaperBuild>> {
+        tracing::debug!("Fetching {} {version} builds", self.project);
+        let response = client
+            .get(format!(
+                "{PAPER_API_URL}/projects/{}/versions/{version}/builds",
+                self.project
+            ))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            bail!("No such {} version: {version}", self.project);
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// The newest release with a stable build.
+    async fn latest_release(&self, client: &Client) -> anyhow::Result<(String, PaperBuild)> {
+        tracing::debug!("Fetching {} versions", self.project);
+        let project: Project = client
+            .get(format!("{PAPER_API_URL}/projects/{}", self.project))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        for version in project.releases() {
+            let builds = self.builds(client, version).await?;
+            if let Some(build) = latest_stable(&builds) {
+                return Ok((version.to_string(), build.clone()));
+            }
+            tracing::debug!("{} {version} has no stable builds yet", self.project);
+        }
+        bail!("{} has no stable builds", self.project)
+    }
+}
+
+impl Provider for Paper {
+    async fn resolve(
+        &self,
+        client: &Client,
+        fetch: &Fetch,
+        lock: Option<&Lock>,
+    ) -> anyhow::Result<Build> {
+        let (version, build) = match fetch {
+            Fetch::Version(version) => {
+                let builds = self.builds(client, version).await?;
+                let build = latest_stable(&builds)
+                    .ok_or(anyhow!("{} {version} has no stable builds", self.project))?;
+                (version.clone(), build.clone())
+            }
+            Fetch::Build { version, build } => {
+                let builds = self.builds(client, version).await?;
+                let build = builds
+                    .into_iter()
+                    .find(|candidate| candidate.id.to_string() == *build)
+                    .ok_or(anyhow!("No such {} {version} build: {build}", self.project))?;
+                (version.clone(), build)
+            }
+            Fetch::Latest(Type::Release) => self.latest_release(client).await?,
+            Fetch::Latest(_) => bail!("{} only publishes builds for releases", self.project),
+        };
+        check_downgrade(&version, lock)?;
+
+        let download = build.downloads.get(SERVER_DOWNLOAD).ok_or(anyhow!(
+            "{} {version} build {} has no server download",
+            self.project,
+            build.id
+        ))?;
+        Ok(Build {
+            version,
+            build: Some(build.id.to_string()),
+            metadata_sha1: None,
+            download: Download {
+                algorithm: HashAlgorithm::Sha256,
+                hash: download.checksums.sha256.clone(),
+                size: Some(download.size),
+                url: download.url.clone(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = include_str!("../../tests/fixtures/paper_project.json");
+    const BUILDS: &str = include_str!("../../tests/fixtures/paper_builds.json");
+
+    #[test]
+    fn test_project_releases() {
+        let project: Project = serde_json::from_str(PROJECT).unwrap();
+        assert_eq!(
+            project.releases()[..4],
+            ["1.21.10", "1.21.9", "1.21.8", "1.21.4"]
+        );
+        assert!(!project.releases().contains(&"1.21.9-rc1"));
+    }
+
+    #[test]
+    fn test_latest_stable() {
+        let builds: Vec<PaperBuild> = serde_json::from_str(BUILDS).unwrap();
+        let build = latest_stable(&builds).unwrap();
+        assert_eq!(build.id, 231);
+        let download = &build.downloads[SERVER_DOWNLOAD];
+        assert_eq!(
+            download.checksums.sha256,
+            "a0c2bfa4d4b7ea9a2e0b8d3e4c8e5e1c9a6f6d3e2b1c0a9f8e7d6c5b4a3f2e1d"
+        );
+        assert_eq!(download.size, 51234567);
+    }
+
+    #[test]
+    fn test_latest_stable_none() {
+        let builds: Vec<PaperBuild> = serde_json::from_str(BUILDS).unwrap();
+        let experimental: Vec<_> = builds
+            .into_iter()
+            .filter(|build| build.channel == Channel::Alpha)
+            .collect();
+        assert_eq!(latest_stable(&experimental), None);
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, bail};
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::{Build, Provider, check_downgrade, compare_releases};
+use crate::{
+    fetch::Fetch,
+    lock::Lock,
+    manifest::{Download, HashAlgorithm, Type},
+};
+
+/// Location of the Purpur downloads API.
+pub const PURPUR_API_URL: &str = "https://api.purpurmc.org/v2/purpur";
+
+/// Purpur, which publishes builds with MD5 checksums only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Purpur;
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Project {
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Version {
+    builds: Builds,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct Builds {
+    latest: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+struct PurpurBuild {
+    build: String,
+    result: String,
+    md5: String,
+}
+
+async fn get<T: serde::de::DeserializeOwned>(client: &Client, url: String) -> anyhow::Result<T> {
+    let response = client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        bail!("Not found: {url}");
+    }
+    Ok(response.error_for_status()?.json().await?)
+}
+
+/// The newest release in a list of versions.
+fn latest_release(versions: &[String]) -> Option<&str> {
+    versions
+        .iter()
+        .filter(|version| super::parse_release(version).is_some())
+        .max_by(|a, b| compare_releases(a, b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(String::as_str)
+}
+
+impl Provider for Purpur {
+    async fn resolve(
+        &self,
+        client: &Client,
+        fetch: &Fetch,
+        lock: Option<&Lock>,
+    ) -> anyhow::Result<Build> {
+        let (version, build) = match fetch {
+            Fetch::Version(version) => {
+                tracing::debug!("Fetching purpur {version} builds");
+                let builds: Version = get(client, format!("{PURPUR_API_URL}/{version}")).await?;
+                (version.clone(), builds.builds.latest)
+            }
+            Fetch::Build { version, build } => (version.clone(), build.clone()),
+            Fetch::Latest(Type::Release) => {
+                tracing::debug!("Fetching purpur versions");
+                let project: Project = get(client, PURPUR_API_URL.to_string()).await?;
+                let version = latest_release(&project.versions)
+                    .ok_or(anyhow!("Purpur has no releases"))?
+                    .to_string();
+                let builds: Version = get(client, format!("{PURPUR_API_URL}/{version}")).await?;
+                (version, builds.builds.latest)
+            }
+            Fetch::Latest(_) => bail!("Purpur only publishes builds for releases"),
+        };
+        check_downgrade(&version, lock)?;
+
+        let url = format!("{PURPUR_API_URL}/{version}/{build}");
+        let build: PurpurBuild = get(client, url.clone()).await?;
+        if build.result != "SUCCESS" {
+            bail!("Purpur {version} build {} failed", build.build);
+        }
+        Ok(Build {
+            version,
+            build: Some(build.build),
+            metadata_sha1: None,
+            download: Download {
+                algorithm: HashAlgorithm::Md5,
+                hash: build.md5,
+                size: None,
+                url: format!("{url}/download"),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_release() {
+        let project: Project = serde_json::from_str(
+            r#"{"project": "purpur", "metadata": {"current": "1.21.10"}, "versions": ["1.20.6", "1.21.9", "1.21.10", "1.21.10-rc1"]}"#,
+        )
+        .unwrap();
+        assert_eq!(latest_release(&project.versions), Some("1.21.10"));
+    }
+
+    #[test]
+    fn test_build() {
+        let build: PurpurBuild = serde_json::from_str(
+            r#"{
+                "project": "purpur",
+                "version": "1.21.4",
+                "build": "2416",
+                "result": "SUCCESS",
+                "timestamp": 1740263498000,
+                "duration": 94120,
+                "commits": [],
+                "md5": "b1946ac92492d2347c6235b4d2611184"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(build.build, "2416");
+        assert_eq!(build.md5, "b1946ac92492d2347c6235b4d2611184");
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::anyhow;
+use reqwest::Client;
+
+use super::{Build, Provider};
+use crate::{
+    fetch::Fetch,
+    lock::Lock,
+    manifest::{Downloads, VERSION_MANIFEST_URL, Version, VersionManifest, VersionMetadata},
+};
+
+/// Mojang's server, from the version manifest.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vanilla;
+
+impl Provider for Vanilla {
+    async fn resolve(
+        &self,
+        client: &Client,
+        fetch: &Fetch,
+        lock: Option<&Lock>,
+    ) -> anyhow::Result<Build> {
+        tracing::debug!("Fetching version manifest");
+        let manifest: VersionManifest = client
+            .get(VERSION_MANIFEST_URL)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        // TODO: Consider logging whether a version is requested or is latest.
+        let version = match fetch {
+            Fetch::Version(version) => manifest
+                .version(version)
+                .ok_or(anyhow!("No such version: {version}"))?,
+            Fetch::Build { .. } => return Err(anyhow!("Vanilla servers don't have builds")),
+            Fetch::Latest(r#type) => {
+                let id = manifest
+                    .latest(r#type)
+                    .ok_or_else(|| anyhow!("Mojang no longer publishes {type:?} versions"))?;
+                manifest.version(id).ok_or(anyhow!(
+                    "Latest version {id} is inexplicably missing from the manifest"
+                ))?
+            }
+        };
+
+        if let Some(lock) = lock
+            && let Some(last_run) = &lock.server.last_run
+        {
+            check_downgrade(&manifest, &version, last_run)?;
+        }
+        let locked_metadata = lock
+            .filter(|lock| {
+                lock.server.server_type.runs_vanilla_jar() && lock.server.version == version.id
+            })
+            .and_then(|lock| lock.server.metadata_sha1.as_ref());
+        if let Some(locked_metadata) = locked_metadata
+            && version.sha1 != *locked_metadata
+        {
+            return Err(anyhow!(
+                "Metadata for {} changed since it was locked (expected: {}, actual: {})",
+                version.id,
+                locked_metadata,
+                version.sha1
+            ));
+        }
+
+        tracing::debug!("Fetching version {} metadata", version.id);
+        let version_metadata: VersionMetadata =
+            client.get(&version.url).send().await?.json().await?;
+
+        let VersionMetadata {
+            downloads: Downloads { server },
+        } = version_metadata;
+
+        Ok(Build {
+            version: version.id,
+            build: None,
+            metadata_sha1: Some(version.sha1),
+            download: server,
+        })
+    }
+}
+
+/// Refuse to move a world to a version released before the one that last ran it.
+fn check_downgrade(
+    manifest: &VersionManifest,
+    target: &Version,
+    last_run: &str,
+) -> anyhow::Result<()> {
+    let Some(last_run) = manifest.version(last_run) else {
+        tracing::warn!(
+            "Last run version {last_run} is missing from the manifest, skipping downgrade check"
+        );
+        return Ok(());
+    };
+    if target.release_time < last_run.release_time {
+        return Err(anyhow!(
+            "Refusing to downgrade world from {} to {}",
+            last_run.id,
+            target.id
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const VERSION_MANIFEST: &str = include_str!("../../tests/fixtures/version_manifest_v2.json");
+
+    #[test_case("1.21.3", "1.21.3" ; "same version")]
+    #[test_case("1.21.1", "1.21.3" ; "upgrade")]
+    #[test_case("1.21.3", "1.21.4-rc3" ; "upgrade to snapshot")]
+    #[test_case("0.0.0", "1.20" ; "unknown last run")]
+    fn test_check_downgrade_allowed(last_run: &str, target: &str) {
+        let manifest: VersionManifest = serde_json::from_str(VERSION_MANIFEST).unwrap();
+        let target = manifest.version(target).unwrap();
+        assert!(check_downgrade(&manifest, &target, last_run).is_ok());
+    }
+
+    #[test_case("1.21.3", "1.21.1" ; "older release")]
+    #[test_case("1.21.4-rc3", "1.21.3" ; "snapshot to release")]
+    fn test_check_downgrade_refused(last_run: &str, target: &str) {
+        let manifest: VersionManifest = serde_json::from_str(VERSION_MANIFEST).unwrap();
+        let target = manifest.version(target).unwrap();
+        assert!(check_downgrade(&manifest, &target, last_run).is_err());
+    }
+}
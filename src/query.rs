@@ -0,0 +1,403 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow, bail};
+use tokio::net::UdpSocket;
+
+/// Every request starts with these bytes.
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+/// Only the low 4 bits of each byte of the session id are used.
+const SESSION_MASK: i32 = 0x0f0f_0f0f;
+/// Padding before the key/value section of a full stat response.
+const FULL_STAT_PADDING: usize = 11;
+/// Padding between the key/value and player sections of a full stat response.
+const PLAYERS_PADDING: usize = 10;
+/// Query responses fit in a single datagram.
+const MAX_RESPONSE_LENGTH: usize = 64 * 1024;
+
+/// The short summary returned by a basic stat request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicStat {
+    pub motd: String,
+    pub game_type: String,
+    pub map: String,
+    pub online_players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+/// Everything returned by a full stat request, including every player online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullStat {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    pub plugins: Plugins,
+    pub map: String,
+    pub online_players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+    /// Names of the players online.
+    pub players: Vec<String>,
+}
+
+/// The server software and plugins reported by a full stat.
+///
+/// Vanilla servers report neither, while e.g. Paper reports
+/// `Paper on 1.21.3: LuckPerms 5.4.145; WorldEdit 7.3.8`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plugins {
+    pub server: Option<String>,
+    pub plugins: Vec<String>,
+}
+
+impl Plugins {
+    fn parse(value: &str) -> Self {
+        let (server, plugins) = match value.split_once(": ") {
+            Some((server, plugins)) => (Some(server), plugins),
+            None if value.is_empty() => (None, ""),
+            None => (Some(value), ""),
+        };
+        Plugins {
+            server: server.map(str::to_string),
+            plugins: plugins
+                .split("; ")
+                .filter(|plugin| !plugin.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// Request a basic stat from a server with `enable-query=true`.
+pub async fn basic_stat(host: &str, port: u16, timeout: Duration) -> anyhow::Result<BasicStat> {
+    with_timeout(host, port, timeout, async {
+        let session = Session::start(host, port).await?;
+        let response = session.request(&session.token.to_be_bytes()).await?;
+        parse_basic_stat(&response)
+    })
+    .await
+}
+
+/// Request a full stat from a server with `enable-query=true`.
+pub async fn full_stat(host: &str, port: u16, timeout: Duration) -> anyhow::Result<FullStat> {
+    with_timeout(host, port, timeout, async {
+        let session = Session::start(host, port).await?;
+        // Padding after the token asks for a full stat instead of a basic one.
+        let mut payload = session.token.to_be_bytes().to_vec();
+        payload.extend([0; 4]);
+        let response = session.request(&payload).await?;
+        parse_full_stat(&response)
+    })
+    .await
+}
+
+async fn with_timeout<T>(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    future: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| anyhow!("Timed out querying {host}:{port}, is enable-query=true set?"))?
+}
+
+/// A socket which has completed the handshake and holds a challenge token.
+struct Session {
+    socket: UdpSocket,
+    id: i32,
+    token: i32,
+}
+
+impl Session {
+    async fn start(host: &str, port: u16) -> anyhow::Result<Self> {
+        let address = tokio::net::lookup_host((host, port))
+            .await?
+            .next()
+            .with_context(|| format!("Failed to resolve {host}"))?;
+        let local = match address {
+            SocketAddr::V4(_) => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((local, 0)).await?;
+        socket.connect(address).await?;
+        let id = rand::random::<i32>() & SESSION_MASK;
+
+        socket.send(&encode_request(HANDSHAKE, id, &[])).await?;
+        let response = receive(&socket, HANDSHAKE, id).await?;
+        let token = Parser::new(&response)
+            .string()?
+            .parse()
+            .context("Invalid challenge token")?;
+        Ok(Session { socket, id, token })
+    }
+
+    async fn request(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.socket
+            .send(&encode_request(STAT, self.id, payload))
+            .await?;
+        receive(&self.socket, STAT, self.id).await
+    }
+}
+
+fn encode_request(kind: u8, session: i32, payload: &[u8]) -> Vec<u8> {
+    let mut request = MAGIC.to_vec();
+    request.push(kind);
+    request.extend(session.to_be_bytes());
+    request.extend(payload);
+    request
+}
+
+/// Receive a response of the given kind, returning the payload.
+async fn receive(socket: &UdpSocket, kind: u8, session: i32) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0; MAX_RESPONSE_LENGTH];
+    let len = socket.recv(&mut buf).await?;
+    buf.truncate(len);
+    let mut parser = Parser::new(&buf);
+    let [received_kind] = parser.take()?;
+    let received_session = i32::from_be_bytes(parser.take()?);
+    if received_kind != kind || received_session != session {
+        bail!("Unexpected query response");
+    }
+    Ok(parser.data.to_vec())
+}
+
+fn parse_basic_stat(payload: &[u8]) -> anyhow::Result<BasicStat> {
+    let mut parser = Parser::new(payload);
+    Ok(BasicStat {
+        motd: parser.string()?,
+        game_type: parser.string()?,
+        map: parser.string()?,
+        online_players: parser.string()?.parse()?,
+        max_players: parser.string()?.parse()?,
+        // The only little-endian field in the protocol.
+        host_port: u16::from_le_bytes(parser.take()?),
+        host_ip: parser.string()?,
+    })
+}
+
+fn parse_full_stat(payload: &[u8]) -> anyhow::Result<FullStat> {
+    let mut parser = Parser::new(payload);
+    parser.skip(FULL_STAT_PADDING)?;
+    let mut info = BTreeMap::new();
+    loop {
+        let key = parser.string()?;
+        if key.is_empty() {
+            break;
+        }
+        info.insert(key, parser.string()?);
+    }
+    parser.skip(PLAYERS_PADDING)?;
+    let mut players = Vec::new();
+    loop {
+        let player = parser.string()?;
+        if player.is_empty() {
+            break;
+        }
+        players.push(player);
+    }
+
+    let mut field = |key: &str| {
+        info.remove(key)
+            .ok_or_else(|| anyhow!("Full stat is missing {key}"))
+    };
+    Ok(FullStat {
+        motd: field("hostname")?,
+        game_type: field("gametype")?,
+        game_id: field("game_id")?,
+        version: field("version")?,
+        plugins: Plugins::parse(&field("plugins")?),
+        map: field("map")?,
+        online_players: field("numplayers")?.parse()?,
+        max_players: field("maxplayers")?.parse()?,
+        host_port: field("hostport")?.parse()?,
+        host_ip: field("hostip")?,
+        players,
+    })
+}
+
+/// A cursor over a response payload of null-terminated strings.
+struct Parser<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Parser { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk()
+            .ok_or(anyhow!("Unexpected end of query response"))?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        self.data = self
+            .data
+            .get(len..)
+            .ok_or(anyhow!("Unexpected end of query response"))?;
+        Ok(())
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let end = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(anyhow!("Unterminated string in query response"))?;
+        let value = String::from_utf8_lossy(&self.data[..end]).into_owned();
+        self.data = &self.data[end + 1..];
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn full_stat_payload(plugins: &str, players: &[&str]) -> Vec<u8> {
+        let mut payload = b"splitnum\0\x80\0".to_vec();
+        for (key, value) in [
+            ("hostname", "A Minecraft Server"),
+            ("gametype", "SMP"),
+            ("game_id", "MINECRAFT"),
+            ("version", "1.21.3"),
+            ("plugins", plugins),
+            ("map", "world"),
+            ("numplayers", "2"),
+            ("maxplayers", "20"),
+            ("hostport", "25565"),
+            ("hostip", "127.0.0.1"),
+        ] {
+            payload.extend(format!("{key}\0{value}\0").bytes());
+        }
+        payload.extend(b"\0\x01player_\0\0");
+        for player in players {
+            payload.extend(format!("{player}\0").bytes());
+        }
+        payload.push(0);
+        payload
+    }
+
+    #[test]
+    fn test_parse_basic_stat() {
+        let payload = b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0";
+        assert_eq!(
+            parse_basic_stat(payload).unwrap(),
+            BasicStat {
+                motd: "A Minecraft Server".to_string(),
+                game_type: "SMP".to_string(),
+                map: "world".to_string(),
+                online_players: 2,
+                max_players: 20,
+                host_port: 25565,
+                host_ip: "127.0.0.1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_full_stat() {
+        let payload = full_stat_payload("", &["Steve", "Alex"]);
+        let stat = parse_full_stat(&payload).unwrap();
+        assert_eq!(stat.version, "1.21.3");
+        assert_eq!(stat.online_players, 2);
+        assert_eq!(stat.plugins, Plugins::default());
+        assert_eq!(stat.players, ["Steve", "Alex"]);
+    }
+
+    #[test]
+    fn test_parse_full_stat_truncated() {
+        let payload = full_stat_payload("", &["Steve"]);
+        assert!(parse_full_stat(&payload[..payload.len() - 1]).is_err());
+    }
+
+    #[test_case("", None, &[] ; "vanilla")]
+    #[test_case("Paper on 1.21.3", Some("Paper on 1.21.3"), &[] ; "no plugins")]
+    #[test_case("Paper on 1.21.3: LuckPerms 5.4.145; WorldEdit 7.3.8", Some("Paper on 1.21.3"), &["LuckPerms 5.4.145", "WorldEdit 7.3.8"] ; "plugins")]
+    fn test_parse_plugins(value: &str, server: Option<&str>, plugins: &[&str]) {
+        let parsed = Plugins::parse(value);
+        assert_eq!(parsed.server.as_deref(), server);
+        assert_eq!(parsed.plugins, plugins);
+    }
+
+    /// Answer a handshake and a stat request like a real server would.
+    async fn fake_server(socket: UdpSocket) {
+        const TOKEN: i32 = 9513307;
+        let mut buf = [0; 1024];
+
+        let (len, client) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(buf[..3], [0xfe, 0xfd, HANDSHAKE]);
+        let session = &buf[3..7];
+        assert_eq!(len, 7);
+        let mut response = vec![HANDSHAKE];
+        response.extend(session);
+        response.extend(format!("{TOKEN}\0").bytes());
+        socket.send_to(&response, client).await.unwrap();
+
+        let (len, client) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(buf[..3], [0xfe, 0xfd, STAT]);
+        assert_eq!(buf[7..11], TOKEN.to_be_bytes());
+        let mut response = vec![STAT];
+        response.extend(&buf[3..7]);
+        // A full stat request is padded to 15 bytes.
+        if len == 15 {
+            response.extend(full_stat_payload("", &["Steve", "Alex"]));
+        } else {
+            response.extend(b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0");
+        }
+        socket.send_to(&response, client).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_basic_stat() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(socket));
+
+        let stat = basic_stat("127.0.0.1", port, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(stat.online_players, 2);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_full_stat() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(socket));
+
+        let stat = full_stat("127.0.0.1", port, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(stat.players, ["Steve", "Alex"]);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_disabled() {
+        // Nothing answers, like a server without enable-query=true.
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        assert!(
+            full_stat("127.0.0.1", port, Duration::from_millis(100))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// The port servers listen for RCON on unless `rcon.port` is set.
+pub const DEFAULT_PORT: u16 = 25575;
+
+const RESPONSE: i32 = 0;
+const COMMAND: i32 = 2;
+const LOGIN: i32 = 3;
+/// The request id servers answer a rejected login with.
+const REJECTED: i32 = -1;
+/// Servers split responses into packets of at most 4096 bytes of text, so
+/// anything much larger is corrupt or hostile.
+const MAX_PACKET_LENGTH: usize = 8 * 1024;
+
+/// A packet of the RCON protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        // The id, kind and two trailing nulls follow the length.
+        let length = 4 + 4 + self.body.len() + 2;
+        let mut data = Vec::with_capacity(4 + length);
+        data.extend((length as i32).to_le_bytes());
+        data.extend(self.id.to_le_bytes());
+        data.extend(self.kind.to_le_bytes());
+        data.extend(self.body.as_bytes());
+        data.extend([0, 0]);
+        data
+    }
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<Packet> {
+    let length = reader.read_i32_le().await?;
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|length| (10..=MAX_PACKET_LENGTH).contains(length))
+        .with_context(|| format!("Invalid RCON packet length: {length}"))?;
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data).await?;
+    let id = i32::from_le_bytes(data[..4].try_into()?);
+    let kind = i32::from_le_bytes(data[4..8].try_into()?);
+    let body = &data[8..length - 2];
+    Ok(Packet {
+        id,
+        kind,
+        body: String::from_utf8_lossy(body).into_owned(),
+    })
+}
+
+async fn write_packet<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    packet: &Packet,
+) -> anyhow::Result<()> {
+    writer.write_all(&packet.encode()).await?;
+    Ok(())
+}
+
+/// An authenticated RCON connection to a server with `enable-rcon=true`.
+#[derive(Debug)]
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+    timeout: Duration,
+}
+
+impl Rcon {
+    /// Connect and log in with the server's `rcon.password`.
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        password: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+            .await
+            .with_context(|| format!("Timed out connecting to {host}:{port}"))?
+            .with_context(|| format!("Failed to connect to RCON on {host}:{port}"))?;
+        let mut rcon = Rcon {
+            stream,
+            next_id: 1,
+            timeout,
+        };
+        let id = rcon.send(LOGIN, password).await?;
+        let response = rcon.receive().await?;
+        if response.id == REJECTED {
+            bail!("RCON password was rejected");
+        }
+        if response.id != id {
+            bail!("Unexpected RCON login response id {}", response.id);
+        }
+        Ok(rcon)
+    }
+
+    async fn send(&mut self, kind: i32, body: &str) -> anyhow::Result<i32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let packet = Packet {
+            id,
+            kind,
+            body: body.to_string(),
+        };
+        write_packet(&mut self.stream, &packet).await?;
+        Ok(id)
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<Packet> {
+        tokio::time::timeout(self.timeout, read_packet(&mut self.stream))
+            .await
+            .context("Timed out waiting for RCON response")?
+    }
+
+    /// Run a command, returning the server's response.
+    pub async fn command(&mut self, command: &str) -> anyhow::Result<String> {
+        let id = self.send(COMMAND, command).await?;
+        // Long responses are split over several packets, so follow the
+        // command with a request the server answers only after it.
+        let marker = self.send(RESPONSE, "").await?;
+        let mut response = String::new();
+        loop {
+            let packet = self.receive().await?;
+            if packet.id == marker {
+                return Ok(response);
+            }
+            if packet.id == id {
+                response.push_str(&packet.body);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let packet = Packet {
+            id: 1,
+            kind: LOGIN,
+            body: "pw".to_string(),
+        };
+        assert_eq!(
+            packet.encode(),
+            [12, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, b'p', b'w', 0, 0]
+        );
+    }
+
+    /// Answer a login and one command like a vanilla server would.
+    async fn fake_server(listener: TcpListener, password: &str, reply: &[&str]) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let login = read_packet(&mut stream).await.unwrap();
+        assert_eq!(login.kind, LOGIN);
+        let id = if login.body == password { login.id } else { -1 };
+        write_packet(
+            &mut stream,
+            &Packet {
+                id,
+                kind: COMMAND,
+                body: String::new(),
+            },
+        )
+        .await
+        .unwrap();
+        if id == -1 {
+            return;
+        }
+
+        let command = read_packet(&mut stream).await.unwrap();
+        assert_eq!(command.body, "list");
+        let marker = read_packet(&mut stream).await.unwrap();
+        for part in reply {
+            write_packet(
+                &mut stream,
+                &Packet {
+                    id: command.id,
+                    kind: RESPONSE,
+                    body: part.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        write_packet(
+            &mut stream,
+            &Packet {
+                id: marker.id,
+                kind: RESPONSE,
+                body: format!("Unknown request {:x}", marker.kind),
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_command() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(
+            listener,
+            "hunter2",
+            &["There are 1 of a max of 20 players online:", " Steve"],
+        ));
+
+        let mut rcon = Rcon::connect("127.0.0.1", port, "hunter2", Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            rcon.command("list").await.unwrap(),
+            "There are 1 of a max of 20 players online: Steve"
+        );
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(listener, "hunter2", &[]));
+
+        let err = Rcon::connect("127.0.0.1", port, "guess", Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "RCON password was rejected");
+        server.await.unwrap();
+    }
+}
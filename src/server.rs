@@ -1,45 +1,225 @@
-use std::{io::BufRead, process::Stdio, time::Duration};
+mod console;
+mod drain;
+mod idle;
+mod players;
+mod schedule;
+
+use std::{
+    io::BufRead,
+    net::SocketAddr,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use camino::Utf8PathBuf;
+use futures_util::FutureExt;
+use jiff::{
+    SignedDuration,
+    fmt::friendly::{Designator, Spacing, SpanPrinter},
+};
+use serde::Deserialize;
 use tokio::{
-    io::AsyncWriteExt,
-    process::{Child, ChildStdin, Command},
-    signal::unix::{SignalKind, signal},
-    sync::mpsc,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
+    signal::unix::{Signal, SignalKind, signal},
+    sync::{Mutex, Notify, mpsc},
+    task::JoinHandle,
 };
 
+use crate::{
+    backup, control,
+    fetch::SERVER_PATH,
+    logs::{self, Log},
+    metrics,
+};
+
+pub use console::is_unknown_command;
+pub(crate) use console::{Console, wait_for};
+pub use drain::Drain;
+pub use idle::Idle;
+pub use schedule::{Action, Task, Trigger};
+
+/// How long to wait for graceful shutdown unless configured otherwise.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// The JVM heap size unless configured otherwise.
+pub const DEFAULT_MEMORY: &str = "1G";
+
+/// How long to wait before restarting a server that exited on its own.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// How long to wait for the rest of the output of a server that exited.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Output the server prints once it has started, e.g. `Done (4.2s)!`.
+const DONE_PATTERN: &str = "Done (";
+
+/// Placeholder in broadcast messages for the time left, e.g. until a restart.
+const REMAINING: &str = "{remaining}";
+
+/// The server exited before it finished starting up.
+#[derive(Debug, thiserror::Error)]
+#[error("Server exited during startup ({status})")]
+pub struct StartupFailed {
+    pub status: ExitStatus,
+}
+
+/// When to restart the server after it exits on its own.
+///
+/// A server stopped by `mc` (e.g. on SIGTERM) is never restarted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum RestartPolicy {
+    /// Never restart the server.
+    #[default]
+    Never,
+    /// Restart the server if it exits with a non-zero code or a signal.
+    OnFailure,
+    /// Always restart the server.
+    Always,
+}
+
+impl RestartPolicy {
+    /// Every policy, in the order they're listed in `--help`.
+    pub const ALL: &[RestartPolicy] = &[
+        RestartPolicy::Never,
+        RestartPolicy::OnFailure,
+        RestartPolicy::Always,
+    ];
+
+    /// The name used in `mc.toml` and on the command line, e.g. `on-failure`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RestartPolicy::Never => "never",
+            RestartPolicy::OnFailure => "on-failure",
+            RestartPolicy::Always => "always",
+        }
+    }
+
+    fn should_restart(self, status: ExitStatus) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+/// What `java` runs to start the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Launch {
+    /// An executable jar, relative to the server directory.
+    Jar(Utf8PathBuf),
+    /// A Java argument file naming the main class and classpath, relative to
+    /// the server directory, like the `unix_args.txt` Forge installs.
+    ArgFile(Utf8PathBuf),
+}
+
+impl Default for Launch {
+    fn default() -> Self {
+        Launch::Jar(SERVER_PATH.into())
+    }
+}
+
 /// Configuration for running a Minecraft server.
+///
+/// Start from [`Config::new`] and set the fields that differ.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Config {
     /// Path to the directory containing `server.jar`.
     pub directory: Utf8PathBuf,
+    /// What to run, `server.jar` or a mod loader's launcher.
+    pub launch: Launch,
+    /// The server that runs, recorded with each run, e.g. `vanilla 1.21.4`.
+    pub version: Option<String>,
     /// How long to wait for graceful shutdown before killing the server.
     pub shutdown_timeout: Duration,
     /// Minimum heap size for the JVM (`-Xms`), e.g. `1G`, `512M`.
     pub min_memory: String,
     /// Maximum heap size for the JVM (`-Xmx`), e.g. `1G`, `512M`.
     pub max_memory: String,
+    /// Extra arguments passed to the JVM before the server jar or argument file.
+    pub jvm_args: Vec<String>,
+    /// When to restart the server after it exits on its own.
+    pub restart: RestartPolicy,
+    /// Where and how often to back up the world.
+    pub backup: backup::Config,
+    /// Tasks to run on a schedule while the server is up.
+    pub schedule: Vec<Task>,
+    /// Whether to warn players and let them leave before stopping on SIGTERM.
+    pub drain: Option<Drain>,
+    /// Whether to stop the server while nobody is online.
+    pub idle: Option<Idle>,
+    /// Where to serve Prometheus metrics, if anywhere.
+    pub metrics_listen: Option<SocketAddr>,
+    /// Where to log server output and each run.
+    pub logs: logs::Config,
+}
+
+impl Config {
+    /// Run `server.jar` in `directory` with the default settings.
+    pub fn new(directory: Utf8PathBuf) -> Self {
+        Config {
+            directory,
+            launch: Launch::default(),
+            version: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            min_memory: DEFAULT_MEMORY.to_string(),
+            max_memory: DEFAULT_MEMORY.to_string(),
+            jvm_args: Vec::new(),
+            restart: RestartPolicy::default(),
+            backup: backup::Config::default(),
+            schedule: Vec::new(),
+            drain: None,
+            idle: None,
+            metrics_listen: None,
+            logs: logs::Config::default(),
+        }
+    }
+}
+
+/// Fill in the time left in a broadcast message, e.g. `Restarting in 5 minutes`.
+fn fill_remaining(message: &str, remaining: Duration) -> String {
+    let remaining = SignedDuration::try_from(remaining).unwrap_or(SignedDuration::MAX);
+    let remaining = SpanPrinter::new()
+        .designator(Designator::Verbose)
+        .spacing(Spacing::BetweenUnitsAndDesignators)
+        .duration_to_string(&remaining);
+    message.replace(REMAINING, &remaining)
 }
 
 /// Spawn a Minecraft server as a child process.
 ///
 /// Returns the child process handle for lifecycle management.
 fn spawn(config: &Config) -> Result<Child> {
-    let jar_path = config.directory.join("server.jar");
+    let launch = match &config.launch {
+        Launch::Jar(jar) => vec!["-jar".to_string(), config.directory.join(jar).into_string()],
+        Launch::ArgFile(path) => vec![format!("@{}", config.directory.join(path))],
+    };
 
     let xms = format!("-Xms{}", config.min_memory);
     let xmx = format!("-Xmx{}", config.max_memory);
 
     let mut cmd = Command::new("java");
 
-    cmd.args([&xms, &xmx, "-jar", jar_path.as_str(), "nogui"])
+    cmd.args([&xms, &xmx])
+        .args(&config.jvm_args)
+        .args(&launch)
+        .arg("nogui")
         .current_dir(&config.directory)
         .stdin(Stdio::piped())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit());
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
 
-    tracing::debug!("Executing: java {xms} {xmx} -jar {jar_path} nogui");
+    let jvm_args = config
+        .jvm_args
+        .iter()
+        .map(|arg| format!(" {arg}"))
+        .collect::<String>();
+    let launch = launch.join(" ");
+    tracing::debug!("Executing: java {xms} {xmx}{jvm_args} {launch} nogui");
 
     cmd.spawn().context("Failed to spawn server process")
 }
@@ -65,23 +245,89 @@ fn spawn_stdin_reader(tx: mpsc::Sender<String>) {
     });
 }
 
-/// Write lines from the channel to the child's stdin.
+/// Copy the child's stdout to our own, publishing each line to the console
+/// and logging it.
+///
+/// Lines are read as bytes so that invalid UTF-8 can't stall the server by
+/// leaving its output pipe full.
+async fn forward_output(child_stdout: ChildStdout, console: Console, log: Arc<Mutex<Log>>) {
+    let mut reader = BufReader::new(child_stdout);
+    let mut stdout = tokio::io::stdout();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        // Keep forwarding even if our own stdout is gone.
+        let _ = stdout.write_all(&line).await;
+        let _ = stdout.flush().await;
+        log.lock().await.write(&line).await;
+        let text = String::from_utf8_lossy(&line);
+        metrics::record_line(&text);
+        console.publish(text.trim_end_matches(['\r', '\n']).to_string());
+    }
+}
+
+/// Copy the child's stderr to our own, logging each line.
+async fn forward_errors(child_stderr: ChildStderr, log: Arc<Mutex<Log>>) {
+    let mut reader = BufReader::new(child_stderr);
+    let mut stderr = tokio::io::stderr();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let _ = stderr.write_all(&line).await;
+        let _ = stderr.flush().await;
+        log.lock().await.write(&line).await;
+    }
+}
+
+/// Wait for the rest of a run's output, then record how it ended.
+async fn finish_run(config: &Config, id: u64, status: ExitStatus, output: Vec<JoinHandle<()>>) {
+    for task in output {
+        if tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, task)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Server output is still open after it exited");
+        }
+    }
+    if let Err(err) = logs::stop_run(&config.logs, id, status).await {
+        tracing::warn!("Failed to record the end of the run: {err:#}");
+    }
+}
+
+/// Write lines from the channel to the current child's stdin.
 ///
-/// Runs until the channel is closed or writing fails.
-async fn write_to_child(mut child_stdin: ChildStdin, mut rx: mpsc::Receiver<String>) {
+/// Runs until the channel is closed. The child's stdin is swapped out on each
+/// restart; lines sent while no server is running are dropped.
+async fn write_to_child(
+    child_stdin: Arc<Mutex<Option<ChildStdin>>>,
+    mut rx: mpsc::Receiver<String>,
+) {
     while let Some(line) = rx.recv().await {
-        if child_stdin
+        let mut child_stdin = child_stdin.lock().await;
+        let Some(stdin) = child_stdin.as_mut() else {
+            tracing::warn!("Server is not running, dropping command: {line}");
+            continue;
+        };
+        if stdin
             .write_all(format!("{line}\n").as_bytes())
             .await
             .is_err()
         {
-            break;
+            *child_stdin = None;
         }
     }
 }
 
 /// Wait for the child process to exit and log the exit code.
-async fn wait_for_child(child: &mut Child) -> Result<()> {
+async fn wait_for_child(child: &mut Child) -> Result<ExitStatus> {
     let status = child
         .wait()
         .await
@@ -91,61 +337,237 @@ async fn wait_for_child(child: &mut Child) -> Result<()> {
     } else {
         tracing::warn!("Server terminated by signal");
     }
-    Ok(())
+    Ok(status)
 }
 
 /// Gracefully shut down the server by sending "stop" and waiting for exit.
 ///
 /// If the server doesn't exit within the timeout, it is forcefully killed.
-async fn shutdown(child: &mut Child, tx: &mpsc::Sender<String>, timeout: Duration) -> Result<()> {
-    if tx.send("stop".to_string()).await.is_err() {
+async fn shutdown(child: &mut Child, console: &Console, timeout: Duration) -> Result<ExitStatus> {
+    if console.send("stop").await.is_err() {
         tracing::warn!("Failed to send stop command, channel closed");
     }
 
-    tokio::select! {
-        result = wait_for_child(child) => result,
+    let status = tokio::select! {
+        result = wait_for_child(child) => result?,
         () = tokio::time::sleep(timeout) => {
             tracing::warn!(
                 "Server did not exit within {} seconds, sending SIGKILL",
                 timeout.as_secs()
             );
-            child.kill().await.context("Failed to kill server process")
+            child.kill().await.context("Failed to kill server process")?;
+            wait_for_child(child).await?
         }
-    }
+    };
+    metrics::record_exit(status);
+    Ok(status)
 }
 
 /// Run the Minecraft server, handling SIGTERM for graceful shutdown.
 ///
 /// Forwards stdin to the server, allowing interactive commands. On SIGTERM,
-/// sends the "stop" command for graceful shutdown.
+/// sends the "stop" command for graceful shutdown. If the server exits on its
+/// own it is restarted according to the [`RestartPolicy`].
+///
+/// Other `mc` processes can reach the server through a control socket in the
+/// workspace. Callers should hold the [workspace lock](crate::workspace::lock).
 pub async fn run(config: &Config) -> Result<()> {
     let mut sigterm =
         signal(SignalKind::terminate()).context("Failed to register SIGTERM handler")?;
     tracing::debug!("SIGTERM handler registered");
 
-    let mut child = spawn(config)?;
-    let child_stdin = child
-        .stdin
-        .take()
-        .context("Failed to capture child stdin")?;
-
     // Channel for sending commands to the child's stdin.
-    // Both the stdin reader and the main task (for SIGTERM) can send to this.
+    // The stdin reader, the main task (for SIGTERM) and background tasks can
+    // all send to this through the console.
     let (tx, rx) = mpsc::channel::<String>(32);
+    let console = Console::new(tx.clone());
 
     // Spawn a reader to forward stdin lines to the child process.
-    spawn_stdin_reader(tx.clone());
+    spawn_stdin_reader(tx);
 
     // Spawn a task to write commands from the channel to the child's stdin.
-    tokio::spawn(async move {
-        write_to_child(child_stdin, rx).await;
+    let child_stdin = Arc::new(Mutex::new(None));
+    tokio::spawn(write_to_child(child_stdin.clone(), rx));
+
+    let listener = control::bind().await?;
+    let control = tokio::spawn(control::serve(listener, console.clone(), config.clone()));
+
+    let metrics = match config.metrics_listen {
+        Some(address) => {
+            let listener = metrics::bind(address).await?;
+            Some(tokio::spawn(metrics::serve(listener, console.clone())))
+        }
+        None => None,
+    };
+
+    let scheduled_backups = config.backup.interval.map(|interval| {
+        tokio::spawn(backup::schedule(
+            console.clone(),
+            config.backup.clone(),
+            interval,
+        ))
     });
 
-    tokio::select! {
-        result = wait_for_child(&mut child) => result,
-        _ = sigterm.recv() => {
-            tracing::debug!("Received SIGTERM signal, initiating graceful shutdown");
-            shutdown(&mut child, &tx, config.shutdown_timeout).await
+    let restart = Arc::new(Notify::new());
+    let tasks: Vec<_> = config
+        .schedule
+        .iter()
+        .map(|task| {
+            tokio::spawn(schedule::run(
+                task.clone(),
+                console.clone(),
+                restart.clone(),
+            ))
+        })
+        .collect();
+
+    let log = Arc::new(Mutex::new(Log::open(&config.logs).await?));
+    let result = supervise(config, &console, &child_stdin, &log, &restart, &mut sigterm).await;
+
+    control.abort();
+    if let Some(metrics) = metrics {
+        metrics.abort();
+    }
+    if let Some(scheduled_backups) = scheduled_backups {
+        scheduled_backups.abort();
+    }
+    for task in tasks {
+        task.abort();
+    }
+    control::unbind().await;
+
+    result
+}
+
+/// Why the supervised server process stopped.
+enum Stopped {
+    /// It exited on its own.
+    Exited,
+    /// It was stopped for a scheduled restart.
+    Restart,
+    /// It was stopped for having nobody online.
+    Idle,
+}
+
+/// Spawn the server and restart it according to the [`RestartPolicy`] until
+/// it stops for good. Scheduled restarts happen regardless of the policy.
+///
+/// A server that exits before it finishes starting isn't restarted, since it
+/// would most likely fail again. This fails with [`StartupFailed`] instead.
+///
+/// In idle mode, a server nobody is on is stopped and started again once
+/// someone tries to join.
+async fn supervise(
+    config: &Config,
+    console: &Console,
+    child_stdin: &Mutex<Option<ChildStdin>>,
+    log: &Arc<Mutex<Log>>,
+    restart: &Notify,
+    sigterm: &mut Signal,
+) -> Result<()> {
+    loop {
+        // Subscribe first so the startup line can't be missed.
+        let mut output = console.subscribe();
+        let run = logs::start_run(&config.logs, config.version.clone()).await?;
+        let mut child = spawn(config)?;
+        console.set_running(true);
+        // Drop a restart requested while the previous server was stopping.
+        restart.notified().now_or_never();
+        metrics::record_spawn(child.id());
+        *child_stdin.lock().await = Some(
+            child
+                .stdin
+                .take()
+                .context("Failed to capture child stdin")?,
+        );
+        let child_stdout = child
+            .stdout
+            .take()
+            .context("Failed to capture child stdout")?;
+        let child_stderr = child
+            .stderr
+            .take()
+            .context("Failed to capture child stderr")?;
+        let forwarding = vec![
+            tokio::spawn(forward_output(child_stdout, console.clone(), log.clone())),
+            tokio::spawn(forward_errors(child_stderr, log.clone())),
+        ];
+
+        let mut started = false;
+        let (stopped, status) = loop {
+            tokio::select! {
+                result = wait_for_child(&mut child) => {
+                    let status = result?;
+                    metrics::record_exit(status);
+                    break (Stopped::Exited, status);
+                }
+                () = restart.notified() => {
+                    tracing::info!("Restarting server on schedule");
+                    let status = shutdown(&mut child, console, config.shutdown_timeout).await?;
+                    break (Stopped::Restart, status);
+                }
+                () = idle::wait(console, config.idle.as_ref()), if started => {
+                    tracing::info!("Nobody is online, stopping server until someone joins");
+                    let status = shutdown(&mut child, console, config.shutdown_timeout).await?;
+                    break (Stopped::Idle, status);
+                }
+                _ = sigterm.recv() => {
+                    tracing::debug!("Received SIGTERM signal, initiating graceful shutdown");
+                    if let Some(drain) = &config.drain {
+                        drain::drain(drain, console, sigterm).await;
+                    }
+                    let status = shutdown(&mut child, console, config.shutdown_timeout).await?;
+                    finish_run(config, run, status, forwarding).await;
+                    return Ok(());
+                }
+                // Output only closes with the console, so treat that as started.
+                _ = wait_for(&mut output, DONE_PATTERN), if !started => {
+                    tracing::debug!("Server started");
+                    metrics::record_ready();
+                    started = true;
+                }
+            }
+        };
+
+        console.set_running(false);
+        console.clear_players();
+        finish_run(config, run, status, forwarding).await;
+        match stopped {
+            Stopped::Exited => {}
+            Stopped::Restart => continue,
+            Stopped::Idle => {
+                let Some(idle) = &config.idle else {
+                    continue;
+                };
+                tokio::select! {
+                    result = idle::sleep(idle, config) => result?,
+                    _ = sigterm.recv() => {
+                        tracing::debug!("Received SIGTERM signal while sleeping");
+                        return Ok(());
+                    }
+                }
+                // The server was down anyway, so skip a restart scheduled meanwhile.
+                restart.notified().now_or_never();
+                continue;
+            }
+        };
+        if !started {
+            return Err(StartupFailed { status }.into());
+        }
+        if !config.restart.should_restart(status) {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Restarting server in {} seconds ({status})",
+            RESTART_DELAY.as_secs()
+        );
+        tokio::select! {
+            () = tokio::time::sleep(RESTART_DELAY) => {}
+            _ = sigterm.recv() => {
+                tracing::debug!("Received SIGTERM signal, not restarting");
+                return Ok(());
+            }
         }
     }
 }
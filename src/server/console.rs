@@ -0,0 +1,222 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use tokio::sync::{
+    Mutex, MutexGuard,
+    broadcast::{self, error::RecvError},
+    mpsc, watch,
+};
+
+use super::players::Players;
+
+/// How many lines of server output are buffered for slow subscribers.
+const OUTPUT_CAPACITY: usize = 1024;
+/// What servers reply to a command they don't know, since 1.13 and before.
+const UNKNOWN_COMMAND: [&str; 2] = ["Unknown or incomplete command", "Unknown command"];
+
+/// A handle for sending commands to the server and watching its output.
+///
+/// Handles are cheap to clone and outlive individual server processes, so they
+/// keep working across restarts.
+#[derive(Debug, Clone)]
+pub struct Console {
+    commands: mpsc::Sender<String>,
+    output: broadcast::Sender<String>,
+    players: Players,
+    /// Whether a server process is running to receive commands.
+    running: Arc<watch::Sender<bool>>,
+    /// Held while automatic saving is turned off, e.g. for a backup.
+    saving: Arc<Mutex<()>>,
+}
+
+impl Console {
+    pub(crate) fn new(commands: mpsc::Sender<String>) -> Self {
+        let (output, _) = broadcast::channel(OUTPUT_CAPACITY);
+        Console {
+            commands,
+            output,
+            players: Players::default(),
+            running: Arc::new(watch::Sender::new(false)),
+            saving: Arc::default(),
+        }
+    }
+
+    /// Wait for exclusive use of `save-off`, so that one caller can't turn
+    /// saving back on while another still needs it off.
+    pub(crate) async fn lock_saving(&self) -> MutexGuard<'_, ()> {
+        self.saving.lock().await
+    }
+
+    /// Publish a line of server output to subscribers.
+    pub(super) fn publish(&self, line: String) {
+        self.players.update(&line);
+        // Having no subscribers is fine.
+        let _ = self.output.send(line);
+    }
+
+    /// Record whether a server process is running.
+    pub(super) fn set_running(&self, running: bool) {
+        self.running.send_replace(running);
+    }
+
+    /// Whether a server process is running, as opposed to restarting or
+    /// stopped for being idle.
+    pub fn is_running(&self) -> bool {
+        *self.running.borrow()
+    }
+
+    /// Watch for server processes starting and stopping.
+    pub(super) fn watch_running(&self) -> watch::Receiver<bool> {
+        self.running.subscribe()
+    }
+
+    /// Forget the players online once the server has stopped.
+    pub(super) fn clear_players(&self) {
+        self.players.clear();
+    }
+
+    /// Names of the players online, tracked from the server output.
+    pub fn players(&self) -> Vec<String> {
+        self.players.online()
+    }
+
+    /// Send a command to the server's stdin.
+    pub async fn send(&self, command: impl Into<String>) -> Result<()> {
+        self.commands
+            .send(command.into())
+            .await
+            .map_err(|_| anyhow!("Server console is closed"))
+    }
+
+    /// Subscribe to lines of server output produced from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.output.subscribe()
+    }
+
+    /// Send a command and wait for an output line containing `pattern`.
+    pub async fn send_and_wait(
+        &self,
+        command: impl Into<String>,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<String> {
+        // Subscribe first so the response can't be missed.
+        let mut output = self.subscribe();
+        self.send(command).await?;
+        tokio::time::timeout(timeout, wait_for(&mut output, pattern))
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for server output \"{pattern}\""))?
+    }
+
+    /// Send a command and collect its response from the output that follows.
+    ///
+    /// The console interleaves responses with chat and other players, so
+    /// without `until` the response is taken to be the first line, if one
+    /// appears within `timeout`. Otherwise lines are collected until one
+    /// containing `until`, which must then appear in time.
+    pub async fn exec(
+        &self,
+        command: impl Into<String>,
+        until: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Vec<String>> {
+        let mut output = self.subscribe();
+        self.send(command).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            match tokio::time::timeout_at(deadline, output.recv()).await {
+                Ok(Ok(line)) => {
+                    let done = until.is_none_or(|pattern| line.contains(pattern));
+                    lines.push(line);
+                    if done {
+                        return Ok(lines);
+                    }
+                }
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    tracing::warn!("Skipped {skipped} lines of server output");
+                }
+                Ok(Err(RecvError::Closed)) => bail!("Server output is closed"),
+                Err(_) => match until {
+                    Some(pattern) => {
+                        bail!("Timed out waiting for server output \"{pattern}\"")
+                    }
+                    None => return Ok(lines),
+                },
+            }
+        }
+    }
+}
+
+/// Whether server output says the server didn't understand a command.
+pub fn is_unknown_command(line: &str) -> bool {
+    UNKNOWN_COMMAND.iter().any(|reply| line.contains(reply))
+}
+
+/// Wait for an output line containing `pattern`.
+pub async fn wait_for(output: &mut broadcast::Receiver<String>, pattern: &str) -> Result<String> {
+    loop {
+        match output.recv().await {
+            Ok(line) if line.contains(pattern) => return Ok(line),
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Skipped {skipped} lines of server output");
+            }
+            Err(RecvError::Closed) => bail!("Server output is closed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    /// A console whose server answers every command with `reply`.
+    fn console(reply: &'static [&'static str]) -> Console {
+        let (commands, mut received) = mpsc::channel(1);
+        let console = Console::new(commands);
+        let server = console.clone();
+        tokio::spawn(async move {
+            while received.recv().await.is_some() {
+                for line in reply {
+                    server.publish(line.to_string());
+                }
+            }
+        });
+        console
+    }
+
+    #[tokio::test]
+    async fn test_exec() {
+        let console = console(&["Made Steve a server operator", "Steve joined the game"]);
+        let lines = console
+            .exec("op Steve", None, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(lines, ["Made Steve a server operator"]);
+
+        let lines = console
+            .exec("op Steve", Some("joined"), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+
+        let err = console
+            .exec("op Steve", Some("nothing"), Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Timed out waiting for server output \"nothing\""
+        );
+    }
+
+    #[test_case("[12:00:00] [Server thread/INFO]: Unknown or incomplete command, see below for error", true ; "modern")]
+    #[test_case("[12:00:00 INFO]: Unknown command. Type \"/help\" for help.", true ; "legacy")]
+    #[test_case("[12:00:00] [Server thread/INFO]: Made Steve a server operator", false ; "known")]
+    fn test_is_unknown_command(line: &str, expected: bool) {
+        assert_eq!(is_unknown_command(line), expected);
+    }
+}
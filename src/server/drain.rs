@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use tokio::{signal::unix::Signal, time::Instant};
+
+use super::{Console, REMAINING, fill_remaining};
+
+/// Remaining times at which players are reminded of the shutdown.
+const COUNTDOWN: [Duration; 5] = [
+    Duration::from_secs(10 * 60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(60),
+    Duration::from_secs(30),
+    Duration::from_secs(10),
+];
+
+/// How to let players leave before stopping the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Drain {
+    /// The longest to wait before stopping.
+    pub timeout: Duration,
+    /// Broadcast with `say`, where `{remaining}` is replaced by the time left.
+    pub message: String,
+    /// Whether to stop as soon as the last player leaves.
+    pub until_empty: bool,
+}
+
+impl Drain {
+    /// Wait up to `timeout`, broadcasting the default message.
+    pub fn new(timeout: Duration) -> Self {
+        Drain {
+            timeout,
+            message: Drain::default_message(),
+            until_empty: false,
+        }
+    }
+
+    /// The default broadcast message.
+    pub fn default_message() -> String {
+        format!("Server stopping in {REMAINING}")
+    }
+}
+
+/// Warn players of a shutdown with a countdown, returning once the drain
+/// timeout passes or, if configured, the server is empty.
+///
+/// Returns immediately if nobody is online, and early on another SIGTERM.
+pub(super) async fn drain(drain: &Drain, console: &Console, sigterm: &mut Signal) {
+    let players = console.players();
+    if players.is_empty() {
+        return;
+    }
+    tracing::info!(
+        "Waiting up to {}s for {} to leave",
+        drain.timeout.as_secs(),
+        players.join(", ")
+    );
+
+    let deadline = Instant::now() + drain.timeout;
+    let mut countdown = std::iter::once(drain.timeout)
+        .chain(COUNTDOWN.into_iter().filter(|mark| *mark < drain.timeout))
+        .peekable();
+    let mut output = console.subscribe();
+    loop {
+        let next_mark = countdown.peek().map(|mark| deadline - *mark);
+        tokio::select! {
+            () = tokio::time::sleep_until(deadline) => return,
+            () = tokio::time::sleep_until(next_mark.unwrap_or(deadline)), if next_mark.is_some() => {
+                let Some(remaining) = countdown.next() else {
+                    continue;
+                };
+                let message = fill_remaining(&drain.message, remaining);
+                if let Err(err) = console.send(format!("say {message}")).await {
+                    tracing::warn!("Failed to warn players: {err}");
+                }
+            }
+            _ = output.recv(), if drain.until_empty => {
+                if console.players().is_empty() {
+                    tracing::info!("All players left");
+                    return;
+                }
+            }
+            _ = sigterm.recv() => {
+                tracing::info!("Received SIGTERM again, stopping now");
+                return;
+            }
+        }
+    }
+}
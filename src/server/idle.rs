@@ -0,0 +1,195 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, bail};
+use serde_json::json;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::Instant,
+};
+
+use super::{Config, Console};
+use crate::{
+    fetch::SERVER_PATH,
+    jar,
+    protocol::{self, Handshake, NextState, Reader, Status, StatusPlayers, StatusVersion},
+    workspace,
+};
+
+/// How long a client has to finish talking to the sleeping server.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Players trying to join a sleeping server are disconnected with this.
+const WAKING_MESSAGE: &str = "The server is starting, reconnect in a minute";
+const DEFAULT_MAX_PLAYERS: i64 = 20;
+
+/// How to stop the server while nobody is online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Idle {
+    /// How long the server has to be empty before it's stopped.
+    pub timeout: Duration,
+    /// The MOTD shown in the server list while the server is stopped.
+    pub motd: String,
+}
+
+impl Idle {
+    /// Stop after `timeout` with nobody online, showing the default MOTD.
+    pub fn new(timeout: Duration) -> Self {
+        Idle {
+            timeout,
+            motd: Idle::default_motd(),
+        }
+    }
+
+    /// The default MOTD while sleeping.
+    pub fn default_motd() -> String {
+        "Sleeping, join to wake the server up".to_string()
+    }
+}
+
+/// Wait until nobody has been online for the idle timeout.
+///
+/// Never returns if idle mode is off.
+pub(super) async fn wait(console: &Console, idle: Option<&Idle>) {
+    let Some(idle) = idle else {
+        return std::future::pending().await;
+    };
+    let mut output = console.subscribe();
+    loop {
+        while !console.players().is_empty() {
+            // Output only closes with the console, which outlives this.
+            let _ = output.recv().await;
+        }
+        let deadline = Instant::now() + idle.timeout;
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep_until(deadline) => return,
+                _ = output.recv() => {
+                    if !console.players().is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Stand in for the stopped server until someone tries to join.
+///
+/// Listens on the server port, answering Server List Pings with the sleeping
+/// MOTD. A login attempt is turned away with a message to reconnect, and the
+/// port is released for the server to start on.
+pub(super) async fn sleep(idle: &Idle, config: &Config) -> anyhow::Result<()> {
+    let properties = workspace::properties().await?;
+    let ip = properties
+        .get("server-ip")
+        .filter(|ip| !ip.is_empty())
+        .map_or("0.0.0.0", String::as_str);
+    let port = workspace::server_port().await?;
+    let max_players = properties
+        .get("max-players")
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PLAYERS);
+
+    // Show the real version so clients don't think they're incompatible.
+    let version = match jar::read_version(&config.directory.join(SERVER_PATH)).await {
+        Ok(version) => StatusVersion {
+            name: version.name,
+            protocol: version.protocol_version.try_into()?,
+        },
+        Err(err) => {
+            tracing::warn!("Unable to read server version: {err:#}");
+            StatusVersion {
+                name: "unknown".to_string(),
+                protocol: -1,
+            }
+        }
+    };
+    let status = Status {
+        version,
+        players: Some(StatusPlayers {
+            max: max_players,
+            online: 0,
+            sample: Vec::new(),
+        }),
+        description: json!({ "text": idle.motd }),
+        favicon: None,
+        enforces_secure_chat: None,
+    };
+    let status = Arc::new(serde_json::to_string(&status)?);
+
+    let listener = TcpListener::bind((ip, port))
+        .await
+        .with_context(|| format!("Failed to listen on {ip}:{port}"))?;
+    tracing::info!("Sleeping until someone joins on port {port}");
+
+    let (wake, mut woken) = mpsc::channel(1);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, address) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        tracing::warn!("Failed to accept connection: {err}");
+                        continue;
+                    }
+                };
+                let status = status.clone();
+                let wake = wake.clone();
+                tokio::spawn(async move {
+                    let result =
+                        tokio::time::timeout(CONNECTION_TIMEOUT, respond(stream, &status, &wake))
+                            .await;
+                    match result {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => tracing::debug!("Connection from {address} failed: {err:#}"),
+                        Err(_) => tracing::debug!("Connection from {address} timed out"),
+                    }
+                });
+            }
+            Some(name) = woken.recv() => {
+                tracing::info!("{name} is trying to join, waking the server up");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Handle one connection to the sleeping server.
+async fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    wake: &mpsc::Sender<String>,
+) -> anyhow::Result<()> {
+    let (id, body) = protocol::read_packet(&mut stream).await?;
+    if id != Handshake::ID {
+        bail!("Expected a handshake, got packet {id:#04x}");
+    }
+    match Handshake::parse(&body)?.next_state {
+        NextState::Status => {
+            // A status request, answered with the status...
+            protocol::read_packet(&mut stream).await?;
+            let mut body = Vec::new();
+            protocol::put_string(&mut body, status);
+            protocol::write_packet(&mut stream, 0x00, &body).await?;
+            // ...then a ping, answered with the same payload.
+            let (id, body) = protocol::read_packet(&mut stream).await?;
+            if id == 0x01 {
+                protocol::write_packet(&mut stream, 0x01, &body).await?;
+            }
+        }
+        NextState::Login | NextState::Transfer => {
+            // Login start carries the player's name.
+            let (_, body) = protocol::read_packet(&mut stream).await?;
+            let name = Reader::new(&body)
+                .string()
+                .unwrap_or_else(|_| "Someone".to_string());
+            let mut reason = Vec::new();
+            protocol::put_string(&mut reason, &json!({ "text": WAKING_MESSAGE }).to_string());
+            protocol::write_packet(&mut stream, 0x00, &reason).await?;
+            // Waking only needs to happen once.
+            let _ = wake.try_send(name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+/// Players online, tracked from join and leave messages in the server output.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Players {
+    online: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Players {
+    /// Update the players online from a line of server output.
+    pub(super) fn update(&self, line: &str) {
+        match parse_event(line) {
+            Some(Event::Joined(name)) => {
+                self.lock().insert(name.to_string());
+            }
+            Some(Event::Left(name)) => {
+                self.lock().remove(name);
+            }
+            None => {}
+        }
+    }
+
+    /// Forget all players, e.g. once the server has stopped.
+    pub(super) fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Names of the players online, sorted.
+    pub(super) fn online(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.online.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Event<'a> {
+    Joined(&'a str),
+    Left(&'a str),
+}
+
+/// Parse a join or leave message after the log prefix, which differs by
+/// server type, e.g.
+///
+/// - `[12:00:00] [Server thread/INFO]: Steve joined the game` (vanilla)
+/// - `[12:00:00 INFO]: Steve joined the game` (Paper, Purpur, Folia)
+/// - `[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: Steve
+///   joined the game` (Forge, NeoForge)
+fn parse_event(line: &str) -> Option<Event<'_>> {
+    if !line.starts_with('[') {
+        return None;
+    }
+    // The prefix ends at the first `]: `, so chat containing one can't pass
+    // off the rest of the message as an event.
+    let (_, message) = line.split_once("]: ")?;
+    let event = if let Some(name) = message.strip_suffix(" joined the game") {
+        Event::Joined(name)
+    } else if let Some(name) = message.strip_suffix(" left the game") {
+        Event::Left(name)
+    } else {
+        return None;
+    };
+    // Player names can't contain spaces, so chat like `<Steve> Alex left the
+    // game` can't be mistaken for an event.
+    let (Event::Joined(name) | Event::Left(name)) = event;
+    let valid = (1..=16).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("[12:00:00] [Server thread/INFO]: Steve joined the game", Some(Event::Joined("Steve")) ; "joined")]
+    #[test_case("[12:00:00] [Server thread/INFO]: Alex_2 left the game", Some(Event::Left("Alex_2")) ; "left")]
+    #[test_case("[12:00:00] [Server thread/INFO]: <Steve> Alex left the game", None ; "chat")]
+    #[test_case("[12:00:00] [Server thread/INFO]: Steve lost connection: Disconnected", None ; "lost connection")]
+    #[test_case("[12:00:00 INFO]: Steve joined the game", Some(Event::Joined("Steve")) ; "paper joined")]
+    #[test_case("[12:00:00 INFO]: Steve left the game", Some(Event::Left("Steve")) ; "paper left")]
+    #[test_case("[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: Steve joined the game", Some(Event::Joined("Steve")) ; "forge joined")]
+    #[test_case("[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: Steve left the game", Some(Event::Left("Steve")) ; "forge left")]
+    #[test_case("[12:00:00 INFO]: <Steve> x]: Alex joined the game", None ; "chat with prefix")]
+    #[test_case("Steve joined the game", None ; "no prefix")]
+    fn test_parse_event(line: &str, expected: Option<Event>) {
+        assert_eq!(parse_event(line), expected);
+    }
+
+    #[test]
+    fn test_players() {
+        let players = Players::default();
+        players.update("[12:00:00] [Server thread/INFO]: Steve joined the game");
+        players.update("[12:00:01] [Server thread/INFO]: Alex joined the game");
+        players.update("[12:00:02] [Server thread/INFO]: Steve left the game");
+        assert_eq!(players.online(), ["Alex"]);
+        players.clear();
+        assert!(players.online().is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, bail};
+use jiff::{Timestamp, civil::Time, tz::TimeZone};
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use super::{Console, REMAINING, fill_remaining};
+use crate::config::parse_duration;
+
+/// A task run while the server is up, like a daily restart or a periodic
+/// `save-all`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Entry")]
+#[non_exhaustive]
+pub struct Task {
+    pub trigger: Trigger,
+    pub action: Action,
+    /// How long before running the task to warn players, longest first.
+    pub warnings: Vec<Duration>,
+    /// The warning broadcast with `say`, where `{remaining}` is replaced by
+    /// the time left.
+    pub message: String,
+}
+
+/// When a [`Task`] runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Trigger {
+    /// Every day at a time of day in a time zone.
+    Daily { at: Time, time_zone: TimeZone },
+    /// On an interval, starting over each time the server starts.
+    Every(Duration),
+}
+
+/// What a [`Task`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Send a command to the server console.
+    Command(String),
+    /// Stop the server and start it again.
+    Restart,
+}
+
+/// A `[[schedule]]` entry in `mc.toml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Entry {
+    at: Option<Time>,
+    time_zone: Option<String>,
+    every: Option<String>,
+    command: Option<String>,
+    #[serde(default)]
+    restart: bool,
+    #[serde(default)]
+    warnings: Vec<String>,
+    message: Option<String>,
+}
+
+impl TryFrom<Entry> for Task {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: Entry) -> anyhow::Result<Self> {
+        let trigger = match (entry.at, entry.every) {
+            (Some(at), None) => {
+                let time_zone = match entry.time_zone {
+                    Some(name) => TimeZone::get(&name)
+                        .with_context(|| format!("Unknown time zone: {name}"))?,
+                    None => TimeZone::system(),
+                };
+                Trigger::Daily { at, time_zone }
+            }
+            (None, Some(every)) if entry.time_zone.is_none() => {
+                Trigger::Every(parse_duration(&every)?)
+            }
+            (None, Some(_)) => bail!("time-zone only applies to tasks run `at` a time of day"),
+            _ => bail!("A scheduled task needs exactly one of `at` or `every`"),
+        };
+
+        let action = match (entry.command, entry.restart) {
+            (Some(command), false) => Action::Command(command),
+            (None, true) => Action::Restart,
+            _ => bail!("A scheduled task needs exactly one of `command` or `restart`"),
+        };
+
+        let mut warnings = entry
+            .warnings
+            .iter()
+            .map(|warning| parse_duration(warning))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        warnings.sort_by_key(|warning| std::cmp::Reverse(*warning));
+
+        let message = entry.message.unwrap_or_else(|| match action {
+            Action::Command(_) => format!("Scheduled maintenance in {REMAINING}"),
+            Action::Restart => format!("Server restarting in {REMAINING}"),
+        });
+
+        Ok(Task {
+            trigger,
+            action,
+            warnings,
+            message,
+        })
+    }
+}
+
+impl Trigger {
+    /// The first time the task runs strictly after `after`.
+    pub fn next(&self, after: Timestamp) -> anyhow::Result<Timestamp> {
+        match self {
+            Trigger::Daily { at, time_zone } => {
+                let date = after.to_zoned(time_zone.clone()).date();
+                let today = date.to_datetime(*at).to_zoned(time_zone.clone())?;
+                if today.timestamp() > after {
+                    return Ok(today.timestamp());
+                }
+                let tomorrow = date.tomorrow()?.to_datetime(*at);
+                Ok(tomorrow.to_zoned(time_zone.clone())?.timestamp())
+            }
+            Trigger::Every(interval) => Ok(after.checked_add(*interval)?),
+        }
+    }
+}
+
+impl Task {
+    /// The warning message with the remaining time filled in.
+    fn warning(&self, remaining: Duration) -> String {
+        fill_remaining(&self.message, remaining)
+    }
+}
+
+/// Run a task on its schedule until aborted.
+///
+/// Commands go through the console. Restarts are requested through `restart`,
+/// which the supervisor acts on. Occurrences missed while running late, e.g.
+/// after the host was suspended, or while no server is running, e.g. when
+/// it's stopped for being idle, are skipped.
+pub(super) async fn run(task: Task, console: Console, restart: Arc<Notify>) {
+    if !matches!(task.trigger, Trigger::Every(_)) {
+        return run_from_now(&task, &console, &restart).await;
+    }
+
+    // Intervals count from when the server starts, so start over each time
+    // it does, e.g. after a restart or being stopped for being idle.
+    let mut running = console.watch_running();
+    loop {
+        if running.wait_for(|running| *running).await.is_err() {
+            return;
+        }
+        tokio::select! {
+            () = run_from_now(&task, &console, &restart) => return,
+            _ = running.changed() => {}
+        }
+    }
+}
+
+/// Run a task on its schedule, counting from now.
+async fn run_from_now(task: &Task, console: &Console, restart: &Notify) {
+    let mut next = match task.trigger.next(Timestamp::now()) {
+        Ok(next) => next,
+        Err(err) => {
+            tracing::error!("Unable to schedule task: {err:#}");
+            return;
+        }
+    };
+    loop {
+        tracing::debug!("Next scheduled task at {next}: {:?}", task.action);
+        for warning in &task.warnings {
+            let Ok(at) = next.checked_sub(*warning) else {
+                continue;
+            };
+            if at <= Timestamp::now() {
+                continue;
+            }
+            sleep_until(at).await;
+            if !console.is_running() {
+                continue;
+            }
+            if let Err(err) = console
+                .send(format!("say {}", task.warning(*warning)))
+                .await
+            {
+                tracing::warn!("Failed to send scheduled warning: {err}");
+            }
+        }
+
+        sleep_until(next).await;
+        match &task.action {
+            _ if !console.is_running() => {
+                tracing::info!("Skipping scheduled task, the server isn't running");
+            }
+            Action::Command(command) => {
+                tracing::info!("Running scheduled command: {command}");
+                if let Err(err) = console.send(command.clone()).await {
+                    tracing::warn!("Failed to send scheduled command: {err}");
+                }
+            }
+            // Only requested while running, since a permit stored meanwhile
+            // would restart the next server as soon as it starts.
+            Action::Restart => restart.notify_one(),
+        }
+
+        let now = Timestamp::now();
+        next = match task.trigger.next(next.max(now)) {
+            Ok(next) => next,
+            Err(err) => {
+                tracing::error!("Unable to schedule task: {err:#}");
+                return;
+            }
+        };
+    }
+}
+
+async fn sleep_until(at: Timestamp) {
+    if let Ok(duration) = Duration::try_from(Timestamp::now().duration_until(at)) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn task(toml: &str) -> anyhow::Result<Task> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    #[test]
+    fn test_task_daily_restart() {
+        let task = task(
+            r#"
+            at = "04:00"
+            time-zone = "America/New_York"
+            restart = true
+            warnings = ["1m", "10m", "5m"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(task.action, Action::Restart);
+        assert_eq!(
+            task.warnings,
+            [600, 300, 60].map(Duration::from_secs).to_vec()
+        );
+        assert_eq!(
+            task.warning(Duration::from_secs(600)),
+            "Server restarting in 10 minutes"
+        );
+    }
+
+    #[test_case(r#"at = "04:00""# ; "no action")]
+    #[test_case(r#"command = "save-all""# ; "no trigger")]
+    #[test_case("at = \"04:00\"\nevery = \"1h\"\nrestart = true" ; "both triggers")]
+    #[test_case("every = \"1h\"\ncommand = \"save-all\"\nrestart = true" ; "both actions")]
+    #[test_case("every = \"1h\"\ntime-zone = \"UTC\"\nrestart = true" ; "time zone on interval")]
+    #[test_case("at = \"04:00\"\ntime-zone = \"Mars/Olympus\"\nrestart = true" ; "unknown time zone")]
+    fn test_task_invalid(toml: &str) {
+        assert!(task(toml).is_err());
+    }
+
+    #[test_case("2024-11-29T03:00:00Z", "2024-11-29T04:00:00Z" ; "later today")]
+    #[test_case("2024-11-29T04:00:00Z", "2024-11-30T04:00:00Z" ; "exactly now")]
+    #[test_case("2024-11-29T05:00:00Z", "2024-11-30T04:00:00Z" ; "tomorrow")]
+    fn test_trigger_daily(after: &str, expected: &str) {
+        let trigger = Trigger::Daily {
+            at: Time::constant(4, 0, 0, 0),
+            time_zone: TimeZone::UTC,
+        };
+        assert_eq!(
+            trigger.next(after.parse().unwrap()).unwrap(),
+            expected.parse::<Timestamp>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trigger_daily_time_zone() {
+        // 04:00 in New York is 09:00 UTC during standard time.
+        let trigger = Trigger::Daily {
+            at: Time::constant(4, 0, 0, 0),
+            time_zone: TimeZone::get("America/New_York").unwrap(),
+        };
+        assert_eq!(
+            trigger
+                .next("2024-11-29T12:00:00Z".parse().unwrap())
+                .unwrap(),
+            "2024-11-30T09:00:00Z".parse::<Timestamp>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trigger_every() {
+        let trigger = Trigger::Every(Duration::from_secs(15 * 60));
+        assert_eq!(
+            trigger
+                .next("2024-11-29T12:00:00Z".parse().unwrap())
+                .unwrap(),
+            "2024-11-29T12:15:00Z".parse::<Timestamp>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_every_starts_over_with_server() {
+        let (commands, mut received) = tokio::sync::mpsc::channel(8);
+        let console = Console::new(commands);
+        let task = Task {
+            trigger: Trigger::Every(Duration::from_millis(300)),
+            action: Action::Command("save-all".to_string()),
+            warnings: Vec::new(),
+            message: String::new(),
+        };
+        let running = tokio::spawn(run(task, console.clone(), Arc::default()));
+
+        console.set_running(true);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        console.set_running(false);
+        console.set_running(true);
+        // Due 300ms after the first start, but the server started again.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(received.try_recv().is_err());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(received.try_recv().unwrap(), "save-all");
+
+        running.abort();
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{Context, bail};
+use fs_err::tokio as fs;
+
+use crate::{
+    backup::{self, Backup},
+    fetch::{SERVER_PATH, kept_path, sha1_hex},
+    install::Prepared,
+    lock::{Lock, LockedServer},
+    server, workspace,
+};
+
+/// Whether the locked server has yet to run the world last run by another version.
+pub fn is_upgrade(lock: &Lock) -> bool {
+    lock.server
+        .last_run
+        .as_ref()
+        .is_some_and(|last_run| *last_run != lock.server.version)
+}
+
+/// Back up the world before a new server version first runs it.
+///
+/// World upgrades are one-way, so this is the only way back. The backup is
+/// recorded in the lock for [`rollback`]. Returns `None` if this isn't an
+/// upgrade or there is no world yet.
+pub async fn backup(lock: &mut Lock, config: &backup::Config) -> anyhow::Result<Option<Backup>> {
+    if !is_upgrade(lock) || workspace::world_directories().await?.is_empty() {
+        return Ok(None);
+    }
+
+    let backup = backup::create_labeled(config, &format!("pre-{}", lock.server.version)).await?;
+    if let Some(previous) = &mut lock.previous
+        && lock.server.last_run.as_ref() == Some(&previous.version)
+    {
+        previous.backup = Some(backup.path.clone());
+    }
+    Ok(Some(backup))
+}
+
+/// Roll back to the server replaced by the last upgrade.
+///
+/// Restores the world from the backup taken before the upgrade and swaps the
+/// kept jar back into place, keeping the current one. Returns the lock for
+/// the previous server. The server must not be running.
+pub async fn rollback(lock: &Lock) -> anyhow::Result<Lock> {
+    let previous = lock
+        .previous
+        .as_ref()
+        .context("No previous server version to roll back to")?;
+
+    // Check everything is in place before touching anything.
+    let kept = kept_path(&previous.version);
+    let data = fs::read(&kept)
+        .await
+        .with_context(|| format!("Server {} is no longer kept", previous.version))?;
+    if sha1_hex(&data) != previous.sha1 {
+        bail!("{kept} doesn't match the lock");
+    }
+    if let Some(backup) = &previous.backup
+        && !fs::try_exists(backup).await?
+    {
+        bail!("Backup from before the upgrade is missing: {backup}");
+    }
+
+    // The restore is checked against the current jar, which is newer.
+    let last_run = match &previous.backup {
+        Some(backup) => {
+            backup::restore(backup).await?;
+            tracing::info!("Restored world from {backup}");
+            Some(previous.version.clone())
+        }
+        None => {
+            tracing::warn!("No backup from before the upgrade, leaving the world as is");
+            lock.server.last_run.clone()
+        }
+    };
+
+    if fs::try_exists(SERVER_PATH).await? {
+        fs::rename(SERVER_PATH, kept_path(&lock.server.version)).await?;
+    }
+    fs::rename(&kept, SERVER_PATH).await?;
+    tracing::info!("Rolled back {SERVER_PATH} to {}", previous.version);
+
+    Ok(Lock {
+        server: LockedServer {
+            server_type: previous.server_type,
+            version: previous.version.clone(),
+            build: previous.build.clone(),
+            sha1: previous.sha1.clone(),
+            metadata_sha1: previous.metadata_sha1.clone(),
+            last_run,
+        },
+        // The launcher is installed again for the loader the world last ran with.
+        loader: previous.loader.clone(),
+        previous: None,
+    })
+}
+
+/// Run a server installed by [`prepare`](crate::install::prepare).
+///
+/// If an upgraded server fails to start, the way back is logged, or taken
+/// right away with `auto_rollback`.
+pub async fn run(
+    config: &server::Config,
+    prepared: &Prepared,
+    auto_rollback: bool,
+) -> anyhow::Result<()> {
+    let lock = &prepared.lock;
+    match server::run(config).await {
+        Err(err) if prepared.upgrade && err.is::<server::StartupFailed>() => {
+            let Some(previous) = &lock.previous else {
+                return Err(err);
+            };
+            if !auto_rollback {
+                tracing::error!(
+                    "Run `mc rollback` to return to {} and the world from before the upgrade",
+                    previous.version
+                );
+                return Err(err);
+            }
+            tracing::error!("{err}, rolling back to {}", previous.version);
+            let lock = rollback(lock).await?;
+            lock.save().await?;
+            Err(err.context(format!("Rolled back to {}", lock.server.version)))
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{
+        fetch::{previous_server, replaced_version},
+        lock::LockedLoader,
+        provider::ServerType,
+    };
+    use test_case::test_case;
+
+    #[test_case(None, false ; "first run")]
+    #[test_case(Some("1.21.3"), false ; "same version")]
+    #[test_case(Some("1.21.1"), true ; "upgrade")]
+    fn test_is_upgrade(last_run: Option<&str>, expected: bool) {
+        let lock = Lock {
+            server: LockedServer {
+                server_type: ServerType::Vanilla,
+                version: "1.21.3".to_string(),
+                build: None,
+                sha1: "45810d238246d90e811d896f87b14695b7fb6839".to_string(),
+                metadata_sha1: Some("b7cbc0e4a3e8fd1d1bd3ed1e5cc8e1bde2bb1a56".to_string()),
+                last_run: last_run.map(Into::into),
+            },
+            loader: None,
+            previous: None,
+        };
+        assert_eq!(is_upgrade(&lock), expected);
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_and_roll_back() {
+        let directory: Utf8PathBuf = std::env::temp_dir()
+            .join(format!("mc-test-{:x}", rand::random::<u64>()))
+            .try_into()
+            .unwrap();
+        fs::create_dir_all(&directory).await.unwrap();
+        // Jars are kept relative to the workspace. No other test depends on
+        // the current directory.
+        let original = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&directory).unwrap();
+
+        // Without a version.json, only the lock can tell which version this is.
+        let old = b"1.21.3 server".to_vec();
+        fs::write(SERVER_PATH, &old).await.unwrap();
+        let lock = Lock {
+            server: LockedServer {
+                server_type: ServerType::Fabric,
+                version: "1.21.3".to_string(),
+                build: None,
+                sha1: sha1_hex(&old),
+                metadata_sha1: None,
+                last_run: Some("1.21.3".to_string()),
+            },
+            loader: Some(LockedLoader {
+                version: "0.16.9".to_string(),
+                installer: "1.0.1".to_string(),
+                minecraft_version: "1.21.3".to_string(),
+                sha1: "a1b7e5c3f1a0d2e4b6c8d0f2a4b6c8e0d2f4a6b8".to_string(),
+            }),
+            previous: None,
+        };
+
+        // Replace the jar as fetching 1.21.4 does.
+        let replaced = replaced_version(Some(&lock), &old).unwrap();
+        fs::rename(SERVER_PATH, kept_path(&replaced)).await.unwrap();
+        let new = b"1.21.4 server".to_vec();
+        fs::write(SERVER_PATH, &new).await.unwrap();
+        let upgraded = Lock {
+            server: LockedServer {
+                version: "1.21.4".to_string(),
+                sha1: sha1_hex(&new),
+                ..lock.server.clone()
+            },
+            loader: None,
+            previous: previous_server(Some(&lock), "1.21.4", &old),
+        };
+
+        let rolled_back = rollback(&upgraded).await;
+        let server = fs::read(SERVER_PATH).await;
+        let kept = fs::read(kept_path("1.21.4")).await;
+        std::env::set_current_dir(original).unwrap();
+        fs::remove_dir_all(&directory).await.unwrap();
+
+        let rolled_back = rolled_back.unwrap();
+        assert_eq!(rolled_back.server.version, "1.21.3");
+        assert_eq!(rolled_back.server.sha1, lock.server.sha1);
+        assert_eq!(rolled_back.loader, lock.loader);
+        assert_eq!(server.unwrap(), old);
+        assert_eq!(kept.unwrap(), new);
+    }
+}
@@ -1,11 +1,23 @@
-use std::env::set_current_dir;
+use std::{
+    collections::BTreeMap,
+    env::set_current_dir,
+    fs::TryLockError,
+    io::{ErrorKind, Read, Seek, Write},
+};
 
 use anyhow::Context;
 use camino::{Utf8Path, Utf8PathBuf};
 use fs_err::tokio as fs;
 use jiff::Zoned;
 
+use crate::protocol::DEFAULT_PORT;
+
 const EULA_PATH: &str = "eula.txt";
+const PROPERTIES_PATH: &str = "server.properties";
+const PID_PATH: &str = "mc.pid";
+const DEFAULT_LEVEL_NAME: &str = "world";
+/// Suffixes of the extra dimension directories some servers (e.g. Paper) use.
+const DIMENSION_SUFFIXES: [&str; 2] = ["_nether", "_the_end"];
 const EULA_HEADER: &str = "By changing the setting below to TRUE you are indicating your agreement to our EULA (https://aka.ms/MinecraftEULA).";
 
 /// Prepare a workspace directory for the Minecraft server.
@@ -25,16 +37,88 @@ pub async fn prepare(directory: &Utf8Path) -> anyhow::Result<()> {
         anyhow::bail!("Directory is not writable: {directory}");
     }
 
-    // Change working directory
-    tracing::debug!("Changing working directory to {directory}");
-    set_current_dir(&directory)
-        .with_context(|| format!("Failed to change to directory: {directory}"))?;
+    enter(&directory)?;
 
     accept_eula().await?;
 
     Ok(())
 }
 
+/// Change the process's working directory to an existing workspace.
+///
+/// Unlike [`prepare`], this doesn't create or modify anything, which makes it
+/// suitable for read-only commands.
+pub fn enter(directory: &Utf8Path) -> anyhow::Result<()> {
+    tracing::debug!("Changing working directory to {directory}");
+    set_current_dir(directory)
+        .with_context(|| format!("Failed to change to directory: {directory}"))
+}
+
+/// An exclusive lock on the current workspace.
+///
+/// Held by the `mc` process managing the server so that other `mc` processes
+/// don't modify the workspace underneath it. Released when dropped.
+#[derive(Debug)]
+pub struct WorkspaceLock {
+    _file: fs_err::File,
+}
+
+/// Lock the current workspace, recording our pid in `mc.pid`.
+pub fn lock() -> anyhow::Result<WorkspaceLock> {
+    let mut file = fs_err::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(PID_PATH)?;
+
+    match file.file().try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            anyhow::bail!(
+                "Workspace is locked by another mc process (pid {})",
+                pid.trim()
+            );
+        }
+        Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    tracing::debug!("Locked workspace");
+
+    Ok(WorkspaceLock { _file: file })
+}
+
+/// Check whether another process holds the workspace lock.
+pub fn is_locked() -> anyhow::Result<bool> {
+    let file = match fs_err::File::open(PID_PATH) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    match file.file().try_lock_shared() {
+        Ok(()) => Ok(false),
+        Err(TryLockError::WouldBlock) => Ok(true),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+/// Lock the current workspace unless a server is running in it.
+///
+/// Returns `None` while a server is running, in which case changes go
+/// through its console rather than to the files it has loaded.
+pub fn lock_unless_running() -> anyhow::Result<Option<WorkspaceLock>> {
+    if is_locked()? {
+        Ok(None)
+    } else {
+        Ok(Some(lock()?))
+    }
+}
+
 /// Ensure that eula.txt exists and contains `eula=true`.
 async fn accept_eula() -> anyhow::Result<()> {
     let eula_path = Utf8Path::new(EULA_PATH);
@@ -57,6 +141,215 @@ async fn accept_eula() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Apply overrides to `server.properties`, creating it if necessary.
+///
+/// Existing keys are updated in place, preserving comments and ordering. New
+/// keys are appended.
+pub async fn apply_properties(overrides: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    if overrides.is_empty() {
+        return Ok(());
+    }
+
+    let existing = match fs::read_to_string(PROPERTIES_PATH).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let updated = merge_properties(&existing, overrides);
+    if updated != existing {
+        tracing::debug!(
+            "Applying {} override(s) to {PROPERTIES_PATH}",
+            overrides.len()
+        );
+        fs::write(PROPERTIES_PATH, updated).await?;
+    }
+
+    Ok(())
+}
+
+/// Read `server.properties`, which is empty until the server first starts.
+pub async fn properties() -> anyhow::Result<BTreeMap<String, String>> {
+    match fs::read_to_string(PROPERTIES_PATH).await {
+        Ok(content) => Ok(parse_properties(&content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The port the server listens on, as configured by `server-port` in
+/// `server.properties`.
+pub async fn server_port() -> anyhow::Result<u16> {
+    Ok(properties()
+        .await?
+        .get("server-port")
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT))
+}
+
+/// The port the server answers Query requests on, or `None` if
+/// `enable-query` isn't set in `server.properties`.
+pub async fn query_port() -> anyhow::Result<Option<u16>> {
+    let properties = properties().await?;
+    if properties.get("enable-query").map(String::as_str) != Some("true") {
+        return Ok(None);
+    }
+    Ok(Some(
+        properties
+            .get("query.port")
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT),
+    ))
+}
+
+/// The port and password to reach the server with RCON, or `None` if
+/// `enable-rcon` isn't set in `server.properties`.
+pub async fn rcon() -> anyhow::Result<Option<(u16, String)>> {
+    let mut properties = properties().await?;
+    if properties.get("enable-rcon").map(String::as_str) != Some("true") {
+        return Ok(None);
+    }
+    let port = properties
+        .get("rcon.port")
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(crate::rcon::DEFAULT_PORT);
+    let password = properties
+        .remove("rcon.password")
+        .filter(|password| !password.is_empty())
+        .context("Set rcon.password in server.properties to use RCON")?;
+    Ok(Some((port, password)))
+}
+
+/// Whether players are authenticated with Mojang accounts, as configured by
+/// `online-mode` in `server.properties`.
+pub async fn online_mode() -> anyhow::Result<bool> {
+    Ok(properties().await?.get("online-mode").map(String::as_str) != Some("false"))
+}
+
+/// The world directory, as configured by `level-name` in `server.properties`.
+pub async fn world_directory() -> anyhow::Result<Utf8PathBuf> {
+    let level_name = properties()
+        .await?
+        .remove("level-name")
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string());
+    Ok(level_name.into())
+}
+
+/// The world directories that exist, including separate dimension directories.
+pub async fn world_directories() -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let world = world_directory().await?;
+    let candidates = std::iter::once(world.clone()).chain(
+        DIMENSION_SUFFIXES
+            .iter()
+            .map(|suffix| format!("{world}{suffix}").into()),
+    );
+
+    let mut worlds = Vec::new();
+    for candidate in candidates {
+        if fs::try_exists(&candidate).await? {
+            worlds.push(candidate);
+        }
+    }
+    Ok(worlds)
+}
+
+/// Parse the `key=value` lines of a properties file, skipping comments.
+fn parse_properties(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), unescape(value.trim())))
+        .collect()
+}
+
+/// Escape a key or value as `java.util.Properties` writes it, so that it
+/// reads back unchanged and can't start another line.
+fn escape(text: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, char) in text.chars().enumerate() {
+        match char {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            ' ' if is_key || index == 0 => escaped.push_str("\\ "),
+            char if char.is_control() => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// Undo [`escape`] for a value read from a properties file.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    Some(char) => unescaped.push(char),
+                    None => unescaped.push_str(&code),
+                }
+            }
+            Some(char) => unescaped.push(char),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Merge overrides into the contents of a properties file.
+fn merge_properties(existing: &str, overrides: &BTreeMap<String, String>) -> String {
+    let mut remaining = overrides.clone();
+    let mut merged = String::with_capacity(existing.len());
+
+    for line in existing.lines() {
+        let entry = match line.split_once('=') {
+            Some((key, _)) if !line.trim_start().starts_with('#') => {
+                remaining.remove_entry(key.trim())
+            }
+            _ => None,
+        };
+        match entry {
+            Some((key, value)) => {
+                merged.push_str(&format!(
+                    "{}={}\n",
+                    escape(&key, true),
+                    escape(&value, false)
+                ));
+            }
+            None => merged.push_str(&format!("{line}\n")),
+        }
+    }
+
+    for (key, value) in remaining {
+        merged.push_str(&format!(
+            "{}={}\n",
+            escape(&key, true),
+            escape(&value, false)
+        ));
+    }
+
+    merged
+}
+
 /// Check if a line contains `eula=true` (case insensitive for the boolean value).
 fn is_eula_accepted(line: &str) -> bool {
     let trimmed = line.trim();
@@ -116,4 +409,38 @@ mod tests {
     fn test_is_eula_accepted_empty_or_invalid(given: &str) {
         assert!(!is_eula_accepted(given));
     }
+
+    fn overrides(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test_case("", &[("motd", "Hi")], "motd=Hi\n" ; "empty file")]
+    #[test_case("motd=A Minecraft Server\n", &[("motd", "Hi")], "motd=Hi\n" ; "replace existing")]
+    #[test_case("#Comment\npvp=true\n", &[("motd", "Hi")], "#Comment\npvp=true\nmotd=Hi\n" ; "append missing")]
+    #[test_case("a=1\nb=2\nc=3\n", &[("b", "5")], "a=1\nb=5\nc=3\n" ; "preserve order")]
+    #[test_case("", &[("motd", "Hi\nop=me")], "motd=Hi\\nop\\=me\n" ; "newline")]
+    #[test_case("", &[("motd", " a:b\\c")], "motd=\\ a\\:b\\\\c\n" ; "special characters")]
+    fn test_merge_properties(existing: &str, given: &[(&str, &str)], expected: &str) {
+        assert_eq!(merge_properties(existing, &overrides(given)), expected);
+    }
+
+    #[test]
+    fn test_parse_properties() {
+        let properties =
+            parse_properties("#Minecraft server properties\nlevel-name=survival\nmotd=A=B\n");
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties["level-name"], "survival");
+        assert_eq!(properties["motd"], "A=B");
+    }
+
+    #[test_case("Hi\nop=me" ; "newline")]
+    #[test_case(" a:b\\c" ; "special characters")]
+    #[test_case("\u{7}bell" ; "control character")]
+    #[test_case("§aGreen" ; "formatting code")]
+    fn test_escape_round_trip(value: &str) {
+        assert_eq!(unescape(&escape(value, false)), value);
+    }
 }
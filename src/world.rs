@@ -0,0 +1,245 @@
+use anyhow::{Context, anyhow, bail};
+use camino::Utf8Path;
+use fs_err::tokio as fs;
+use jiff::Timestamp;
+
+use crate::{
+    jar::JarVersion,
+    nbt::{self, Tag},
+};
+
+/// Location of the world metadata, relative to the world directory.
+pub const LEVEL_PATH: &str = "level.dat";
+
+/// Length of a Minecraft day in ticks.
+const TICKS_PER_DAY: i64 = 24000;
+
+/// The game mode new players start in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GameType {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+    Unknown(i64),
+}
+
+impl From<i64> for GameType {
+    fn from(value: i64) -> Self {
+        match value {
+            0 => GameType::Survival,
+            1 => GameType::Creative,
+            2 => GameType::Adventure,
+            3 => GameType::Spectator,
+            value => GameType::Unknown(value),
+        }
+    }
+}
+
+impl std::fmt::Display for GameType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameType::Survival => f.write_str("survival"),
+            GameType::Creative => f.write_str("creative"),
+            GameType::Adventure => f.write_str("adventure"),
+            GameType::Spectator => f.write_str("spectator"),
+            GameType::Unknown(value) => write!(f, "unknown ({value})"),
+        }
+    }
+}
+
+/// A block position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// World metadata from `level.dat`.
+///
+/// Fields are optional since their presence and location vary between the
+/// versions that wrote the file.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LevelInfo {
+    pub level_name: Option<String>,
+    pub seed: Option<i64>,
+    /// Data version that last wrote the world.
+    pub data_version: Option<i32>,
+    /// Name of the version that last wrote the world, e.g. `1.21.3`.
+    pub version_name: Option<String>,
+    pub game_type: Option<GameType>,
+    pub spawn: Option<Position>,
+    /// Ticks since the world was created, including skipped nights.
+    pub day_time: Option<i64>,
+    pub last_played: Option<Timestamp>,
+}
+
+impl LevelInfo {
+    /// The current day, counting from zero.
+    pub fn day(&self) -> Option<i64> {
+        self.day_time.map(|ticks| ticks.div_euclid(TICKS_PER_DAY))
+    }
+
+    /// Ticks into the current day, where 0 is sunrise and 6000 is noon.
+    pub fn time_of_day(&self) -> Option<i64> {
+        self.day_time.map(|ticks| ticks.rem_euclid(TICKS_PER_DAY))
+    }
+}
+
+/// Read world metadata from `level.dat` in a world directory.
+pub async fn read_level(world: &Utf8Path) -> anyhow::Result<LevelInfo> {
+    let path = world.join(LEVEL_PATH);
+    let data = fs::read(&path).await?;
+    tokio::task::spawn_blocking(move || parse_level(&data))
+        .await?
+        .with_context(|| format!("Unable to read {path}"))
+}
+
+fn parse_level(data: &[u8]) -> anyhow::Result<LevelInfo> {
+    let (_, root) = nbt::from_gzip(data)?;
+    let data = root.get("Data").ok_or(anyhow!("Missing Data tag"))?;
+
+    let int = |name: &str| data.get(name).and_then(Tag::as_i64);
+
+    // Spawn moved from separate coordinates into a compound in 1.21.5.
+    let spawn = match data.get("spawn").and_then(|spawn| spawn.get("pos")) {
+        Some(pos) => match pos.as_int_array() {
+            Some(&[x, y, z]) => Some(Position { x, y, z }),
+            _ => None,
+        },
+        None => match (int("SpawnX"), int("SpawnY"), int("SpawnZ")) {
+            (Some(x), Some(y), Some(z)) => Some(Position {
+                x: x.try_into()?,
+                y: y.try_into()?,
+                z: z.try_into()?,
+            }),
+            _ => None,
+        },
+    };
+
+    // The seed moved into world generation settings in 1.16.
+    let seed = data
+        .get("WorldGenSettings")
+        .and_then(|settings| settings.get("seed"))
+        .and_then(Tag::as_i64)
+        .or_else(|| int("RandomSeed"));
+
+    let last_played = int("LastPlayed")
+        .map(Timestamp::from_millisecond)
+        .transpose()?;
+
+    Ok(LevelInfo {
+        level_name: data.get("LevelName").and_then(Tag::as_str).map(Into::into),
+        seed,
+        data_version: int("DataVersion").map(TryInto::try_into).transpose()?,
+        version_name: data
+            .get("Version")
+            .and_then(|version| version.get("Name"))
+            .and_then(Tag::as_str)
+            .map(Into::into),
+        game_type: int("GameType").map(GameType::from),
+        spawn,
+        day_time: int("DayTime"),
+        last_played,
+    })
+}
+
+/// Refuse to run a server older than the version that last wrote the world.
+///
+/// Older servers can't read newer world data and may corrupt it.
+pub fn check_compatible(level: &LevelInfo, jar: &JarVersion) -> anyhow::Result<()> {
+    let Some(data_version) = level.data_version else {
+        return Ok(());
+    };
+    if i64::from(data_version) > i64::from(jar.world_version) {
+        let written_by = level.version_name.as_deref().unwrap_or("a newer version");
+        bail!(
+            "World was last written by {written_by} (data version {data_version}), which is newer than server {} (data version {})",
+            jar.id,
+            jar.world_version
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+    use crate::{
+        jar::PackVersion,
+        nbt::build::{compound, named, string},
+    };
+
+    fn level_dat() -> Vec<u8> {
+        let version = compound(&[named(8, "Name", &string("1.21.3"))]);
+        let settings = compound(&[named(4, "seed", &42i64.to_be_bytes())]);
+        let data = compound(&[
+            named(8, "LevelName", &string("world")),
+            named(3, "DataVersion", &4082i32.to_be_bytes()),
+            named(10, "Version", &version),
+            named(10, "WorldGenSettings", &settings),
+            named(3, "GameType", &1i32.to_be_bytes()),
+            named(3, "SpawnX", &16i32.to_be_bytes()),
+            named(3, "SpawnY", &64i32.to_be_bytes()),
+            named(3, "SpawnZ", &(-32i32).to_be_bytes()),
+            named(4, "DayTime", &54000i64.to_be_bytes()),
+            named(4, "LastPlayed", &1732838400000i64.to_be_bytes()),
+        ]);
+        let root = named(10, "", &compound(&[named(10, "Data", &data)]));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&root).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn jar(world_version: u32) -> JarVersion {
+        JarVersion {
+            id: "1.21.3".to_string(),
+            name: "1.21.3".to_string(),
+            world_version,
+            protocol_version: 768,
+            pack_version: PackVersion::Single(57),
+            java_version: Some(21),
+            stable: true,
+        }
+    }
+
+    #[test]
+    fn test_parse_level() {
+        let level = parse_level(&level_dat()).unwrap();
+        assert_eq!(level.level_name.as_deref(), Some("world"));
+        assert_eq!(level.seed, Some(42));
+        assert_eq!(level.data_version, Some(4082));
+        assert_eq!(level.version_name.as_deref(), Some("1.21.3"));
+        assert_eq!(level.game_type, Some(GameType::Creative));
+        assert_eq!(
+            level.spawn,
+            Some(Position {
+                x: 16,
+                y: 64,
+                z: -32
+            })
+        );
+        assert_eq!(level.day(), Some(2));
+        assert_eq!(level.time_of_day(), Some(6000));
+        assert_eq!(
+            level.last_played,
+            Some("2024-11-29T00:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_check_compatible() {
+        let level = parse_level(&level_dat()).unwrap();
+        assert!(check_compatible(&level, &jar(4082)).is_ok());
+        assert!(check_compatible(&level, &jar(4189)).is_ok());
+        assert!(check_compatible(&level, &jar(3953)).is_err());
+    }
+}